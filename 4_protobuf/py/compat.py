@@ -0,0 +1,183 @@
+"""Cross-language compatibility checks with the Rust CLI.
+
+Both implementations convert an address book to the same canonical JSON, so a
+book written by one and read by the other can be compared field for field:
+
+    python compat.py dump BOOK_DB            print BOOK_DB as canonical JSON
+    python compat.py write BOOK_JSON OUT_DB  write a canonical JSON book with main.py
+
+Canonical JSON has every field, defaults included. Timestamps are
+{"seconds": ..., "nanos": ...} or null, enums are their names, and a contact
+has a "person" or a "company" key (or neither) for its kind. The Rust side is
+in rust/addressbook_1/src/compat.rs; the golden book both sides are checked
+against is rust/addressbook_1/tests/fixtures/golden.json.
+"""
+
+import json
+import sys
+from typing import Any
+
+import google.protobuf.timestamp_pb2 as timestamppb
+from google.protobuf.internal.enum_type_wrapper import EnumTypeWrapper
+
+import main
+import proto.addressbook_pb2 as pb
+
+
+def enum_to_json(enum: EnumTypeWrapper, value: int) -> Any:
+    # Values this schema does not know stay numbers
+    try:
+        return enum.Name(value)
+    except ValueError:
+        return value
+
+
+def enum_from_json(enum: EnumTypeWrapper, value: Any) -> int:
+    return value if isinstance(value, int) else enum.Value(value)
+
+
+def timestamp_to_json(msg: Any, field: str) -> Any:
+    if not msg.HasField(field):
+        return None
+    ts = getattr(msg, field)
+    return {"seconds": ts.seconds, "nanos": ts.nanos}
+
+
+def timestamp_from_json(value: Any) -> timestamppb.Timestamp | None:
+    if value is None:
+        return None
+    return timestamppb.Timestamp(seconds=value["seconds"], nanos=value["nanos"])
+
+
+def contact_to_json(contact: pb.Contact) -> dict:
+    out: dict[str, Any] = {"last_updated": timestamp_to_json(contact, "last_updated")}
+    kind = contact.WhichOneof("kind")
+    if kind == "person":
+        p = contact.person
+        out["person"] = {
+            "email": p.email,
+            "emails": list(p.emails),
+            "phones": [
+                {"number": pn.number, "type": enum_to_json(pb.Person.PhoneNumber.Type, pn.type)}
+                for pn in p.phones
+            ],
+            "company": p.company,
+        }
+    elif kind == "company":
+        c = contact.company
+        out["company"] = {
+            "emails": [
+                {"email": em.email, "department": enum_to_json(pb.Company.Department, em.department)}
+                for em in c.emails
+            ],
+            "phones": [
+                {"number": pn.number, "department": enum_to_json(pb.Company.Department, pn.department)}
+                for pn in c.phones
+            ],
+        }
+    out["tags"] = list(contact.tags)
+    out["notes"] = contact.notes
+    out["addresses"] = [
+        {
+            "street": a.street,
+            "city": a.city,
+            "region": a.region,
+            "postal_code": a.postal_code,
+            "country": a.country,
+        }
+        for a in contact.addresses
+    ]
+    return out
+
+
+def contact_from_json(value: dict) -> pb.Contact:
+    contact = pb.Contact()
+    ts = timestamp_from_json(value["last_updated"])
+    if ts is not None:
+        contact.last_updated.CopyFrom(ts)
+    if "person" in value:
+        p = value["person"]
+        contact.person.email = p["email"]
+        contact.person.emails.extend(p["emails"])
+        for pn in p["phones"]:
+            contact.person.phones.add(
+                number=pn["number"],
+                type=enum_from_json(pb.Person.PhoneNumber.Type, pn["type"]),
+            )
+        contact.person.company = p["company"]
+    elif "company" in value:
+        c = value["company"]
+        # Sets the oneof even when both lists are empty
+        contact.company.SetInParent()
+        for em in c["emails"]:
+            contact.company.emails.add(
+                email=em["email"],
+                department=enum_from_json(pb.Company.Department, em["department"]),
+            )
+        for pn in c["phones"]:
+            contact.company.phones.add(
+                number=pn["number"],
+                department=enum_from_json(pb.Company.Department, pn["department"]),
+            )
+    contact.tags.extend(value["tags"])
+    contact.notes = value["notes"]
+    for a in value["addresses"]:
+        contact.addresses.add(**a)
+    return contact
+
+
+def book_to_json(book: pb.AddressBook) -> dict:
+    return {
+        "contacts": {name: contact_to_json(book.contacts[name]) for name in sorted(book.contacts)},
+        "changes": [
+            {
+                "id": change.id,
+                "at": timestamp_to_json(change, "at"),
+                "author": change.author,
+                "name": change.name,
+                "before": contact_to_json(change.before) if change.HasField("before") else None,
+                "after": contact_to_json(change.after) if change.HasField("after") else None,
+                "undoes": change.undoes,
+            }
+            for change in book.changes
+        ],
+    }
+
+
+def book_from_json(value: dict) -> pb.AddressBook:
+    book = pb.AddressBook()
+    for name, contact in value["contacts"].items():
+        book.contacts[name].CopyFrom(contact_from_json(contact))
+    for change in value["changes"]:
+        c = book.changes.add(id=change["id"], author=change["author"], name=change["name"], undoes=change["undoes"])
+        at = timestamp_from_json(change["at"])
+        if at is not None:
+            c.at.CopyFrom(at)
+        if change["before"] is not None:
+            c.before.CopyFrom(contact_from_json(change["before"]))
+        if change["after"] is not None:
+            c.after.CopyFrom(contact_from_json(change["after"]))
+    return book
+
+
+def dump(path: str) -> dict:
+    with open(path, 'rb') as f:
+        return book_to_json(main.read_from_db(f))
+
+
+def write(book: dict, path: str):
+    with open(path, 'wb') as f:
+        main.write_to_db(f, book_from_json(book))
+
+
+if __name__ == '__main__':
+    match sys.argv[1:]:
+        case ["dump", path]:
+            json.dump(dump(path), sys.stdout, indent=2, sort_keys=True)
+            print()
+        case ["write", book_path, path]:
+            with open(book_path) as f:
+                write(json.load(f), path)
+        case _other:
+            print(__doc__, file=sys.stderr)
+            sys.exit(2)
@@ -14,20 +14,69 @@ import proto.addressbook_pb2 as pb
 cie_keywords: list[str] = ["cie", "company"]
 per_keywords: list[str] = ["per", "person"]
 DB_FILE_PATH: str = "addressbook.db"
+# Files written by the Rust CLI start with MAGIC and the format version as a
+# little-endian u32. Files without it are raw protobuf (version 0).
+MAGIC: bytes = b"ABDB"
+FORMAT_VERSION: int = 3
 
 
 def read_from_db(db: IO[bytes]) -> pb.AddressBook:
     data = db.read()
+    if data.startswith(MAGIC):
+        version = int.from_bytes(data[4:8], "little")
+        if version > FORMAT_VERSION:
+            raise ValueError(f"unsupported addressbook.db format version {version}")
+        data = data[8:]
+    else:
+        version = 0
     book = pb.AddressBook()
     book.ParseFromString(data)
+    if version < 3:
+        # Version 3 moved Person.email into Person.emails
+        for contact in book.contacts.values():
+            upgrade_contact(contact)
+        for change in book.changes:
+            upgrade_contact(change.before)
+            upgrade_contact(change.after)
     return book
 
 
 def write_to_db(db: IO[bytes], book: pb.AddressBook):
-    data = book.SerializeToString()
+    data = MAGIC + FORMAT_VERSION.to_bytes(4, "little") + book.SerializeToString()
+    # Replace the book read earlier rather than appending to it
+    db.seek(0)
+    db.truncate()
     db.write(data)
 
 
+def upgrade_contact(contact: pb.Contact):
+    """Move a person's legacy single email to the front of its emails."""
+    if contact.WhichOneof("kind") == "person" and contact.person.email:
+        contact.person.emails.insert(0, contact.person.email)
+        contact.person.ClearField("email")
+
+
+def copy_contact(book: pb.AddressBook, name: str) -> pb.Contact | None:
+    """A copy of the contact `name`, None if there is none."""
+    if name not in book.contacts:
+        return None
+    contact = pb.Contact()
+    contact.CopyFrom(book.contacts[name])
+    return contact
+
+
+def record_change(book: pb.AddressBook, name: str, before: pb.Contact | None, after: pb.Contact):
+    """Append the change of contact `name` from `before` to `after` to the log, as the Rust CLI does."""
+    change = book.changes.add()
+    change.id = book.changes[-2].id + 1 if len(book.changes) > 1 else 1
+    change.at.GetCurrentTime()
+    change.author = os.environ.get("USER") or os.environ.get("USERNAME") or "unknown"
+    change.name = name
+    if before is not None:
+        change.before.CopyFrom(before)
+    change.after.CopyFrom(after)
+
+
 def str_to_phone_type(s: str) -> pb.Person.PhoneNumber.Type:
     match s:
         case "home":
@@ -57,20 +106,24 @@ def redact_private_info(msg: Message):
 
             if opts.debug_redact and field.type == descriptorpb.FieldDescriptorProto.TYPE_STRING:
                 old = getattr(msg, field.name)
-                setattr(msg, field.name, '*' * len(old))
+                if field.label == descriptorpb.FieldDescriptorProto.LABEL_REPEATED:
+                    old[:] = ['*' * len(s) for s in old]
+                else:
+                    setattr(msg, field.name, '*' * len(old))
 
 
 def add_person(db: IO[bytes], name: str, email: str, phone: str, phone_type: str):
     """Add a person to the database."""
     book = read_from_db(db)
+    before = copy_contact(book, name)
 
     if name in book.contacts:
         person = book.contacts[name].person
     else:
         person = pb.Person()
 
-    if email is not None:
-        person.email = email
+    if email is not None and email not in person.emails:
+        person.emails.append(email)
 
     if phone is not None:
         nb = pb.Person.PhoneNumber()
@@ -78,11 +131,15 @@ def add_person(db: IO[bytes], name: str, email: str, phone: str, phone_type: str
         nb.type = str_to_phone_type(phone_type)
         person.phones.append(nb)
 
+    # Keep the tags, notes and addresses the Rust CLI may have set
     contact = pb.Contact()
+    if name in book.contacts:
+        contact.CopyFrom(book.contacts[name])
     update_ts = timestamppb.Timestamp()
     update_ts.GetCurrentTime()
     contact.last_updated.CopyFrom(update_ts)
     contact.person.CopyFrom(person)
+    record_change(book, name, before, contact)
     book.contacts[name].CopyFrom(contact)
     write_to_db(db, book)
 
@@ -90,6 +147,7 @@ def add_person(db: IO[bytes], name: str, email: str, phone: str, phone_type: str
 def add_company(db: IO[bytes], name: str, email: str, email_dep: str, phone: str, phone_dep: str):
     """Add a company to the database."""
     book = read_from_db(db)
+    before = copy_contact(book, name)
 
     if book.contacts is None:
         book.contacts = {}
@@ -111,11 +169,15 @@ def add_company(db: IO[bytes], name: str, email: str, email_dep: str, phone: str
         nb.department = str_to_department(phone_dep)
         company.phones.append(nb)
 
+    # Keep the tags, notes and addresses the Rust CLI may have set
     contact = pb.Contact()
+    if name in book.contacts:
+        contact.CopyFrom(book.contacts[name])
     update_ts = timestamppb.Timestamp()
     update_ts.GetCurrentTime()
     contact.last_updated.CopyFrom(update_ts)
     contact.company.CopyFrom(company)
+    record_change(book, name, before, contact)
     book.contacts[name].CopyFrom(contact)
     write_to_db(db, book)
 
@@ -127,6 +189,9 @@ def list_contacts(db: IO[bytes], redact: bool):
         contact = book.contacts[name]
 
         if redact:
+            redact_private_info(contact)
+            for address in contact.addresses:
+                redact_private_info(address)
             if contact.WhichOneof("kind") == "person":
                 redact_private_info(contact.person)
                 for phone in contact.person.phones:
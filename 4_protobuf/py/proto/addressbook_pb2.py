@@ -25,7 +25,7 @@ _sym_db = _symbol_database.Default()
 from google.protobuf import timestamp_pb2 as google_dot_protobuf_dot_timestamp__pb2
 
 
-DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(b'\n\x11\x61\x64\x64ressbook.proto\x1a\x1fgoogle/protobuf/timestamp.proto\"\xdb\x01\n\x06Person\x12\x12\n\x05\x65mail\x18\x01 \x01(\tB\x03\x80\x01\x01\x12#\n\x06phones\x18\x02 \x03(\x0b\x32\x13.Person.PhoneNumber\x1a\x97\x01\n\x0bPhoneNumber\x12\x13\n\x06number\x18\x01 \x01(\tB\x03\x80\x01\x01\x12&\n\x04type\x18\x02 \x01(\x0e\x32\x18.Person.PhoneNumber.Type\"K\n\x04Type\x12\x14\n\x10TYPE_UNSPECIFIED\x10\x00\x12\x0f\n\x0bTYPE_MOBILE\x10\x01\x12\r\n\tTYPE_HOME\x10\x02\x12\r\n\tTYPE_WORK\x10\x03\"\xce\x02\n\x07\x43ompany\x12%\n\x06\x65mails\x18\x01 \x03(\x0b\x32\x15.Company.EmailAddress\x12$\n\x06phones\x18\x02 \x03(\x0b\x32\x14.Company.PhoneNumber\x1aK\n\x0c\x45mailAddress\x12\x12\n\x05\x65mail\x18\x01 \x01(\tB\x03\x80\x01\x01\x12\'\n\ndepartment\x18\x02 \x01(\x0e\x32\x13.Company.Department\x1aK\n\x0bPhoneNumber\x12\x13\n\x06number\x18\x01 \x01(\tB\x03\x80\x01\x01\x12\'\n\ndepartment\x18\x02 \x01(\x0e\x32\x13.Company.Department\"\\\n\nDepartment\x12\x1a\n\x16\x44\x45PARTMENT_UNSPECIFIED\x10\x00\x12\x11\n\rDEPARTMENT_HR\x10\x01\x12\x1f\n\x1b\x44\x45PARTMENT_CUSTOMER_SERVICE\x10\x02\"{\n\x07\x43ontact\x12\x30\n\x0clast_updated\x18\x01 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\x19\n\x06person\x18\x02 \x01(\x0b\x32\x07.PersonH\x00\x12\x1b\n\x07\x63ompany\x18\x03 \x01(\x0b\x32\x08.CompanyH\x00\x42\x06\n\x04kind\"v\n\x0b\x41\x64\x64ressBook\x12,\n\x08\x63ontacts\x18\x01 \x03(\x0b\x32\x1a.AddressBook.ContactsEntry\x1a\x39\n\rContactsEntry\x12\x0b\n\x03key\x18\x01 \x01(\t\x12\x17\n\x05value\x18\x02 \x01(\x0b\x32\x08.Contact:\x02\x38\x01\x62\x06proto3')
+DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(b'\n\x11\x61\x64\x64ressbook.proto\x1a\x1fgoogle/protobuf/timestamp.proto\"\x81\x02\n\x06Person\x12\x12\n\x05\x65mail\x18\x01 \x01(\tB\x03\x80\x01\x01\x12#\n\x06phones\x18\x02 \x03(\x0b\x32\x13.Person.PhoneNumber\x12\x13\n\x06\x65mails\x18\x03 \x03(\tB\x03\x80\x01\x01\x12\x0f\n\x07\x63ompany\x18\x04 \x01(\t\x1a\x97\x01\n\x0bPhoneNumber\x12\x13\n\x06number\x18\x01 \x01(\tB\x03\x80\x01\x01\x12&\n\x04type\x18\x02 \x01(\x0e\x32\x18.Person.PhoneNumber.Type\"K\n\x04Type\x12\x14\n\x10TYPE_UNSPECIFIED\x10\x00\x12\x0f\n\x0bTYPE_MOBILE\x10\x01\x12\r\n\tTYPE_HOME\x10\x02\x12\r\n\tTYPE_WORK\x10\x03\"\xce\x02\n\x07\x43ompany\x12%\n\x06\x65mails\x18\x01 \x03(\x0b\x32\x15.Company.EmailAddress\x12$\n\x06phones\x18\x02 \x03(\x0b\x32\x14.Company.PhoneNumber\x1aK\n\x0c\x45mailAddress\x12\x12\n\x05\x65mail\x18\x01 \x01(\tB\x03\x80\x01\x01\x12\'\n\ndepartment\x18\x02 \x01(\x0e\x32\x13.Company.Department\x1aK\n\x0bPhoneNumber\x12\x13\n\x06number\x18\x01 \x01(\tB\x03\x80\x01\x01\x12\'\n\ndepartment\x18\x02 \x01(\x0e\x32\x13.Company.Department\"\\\n\nDepartment\x12\x1a\n\x16\x44\x45PARTMENT_UNSPECIFIED\x10\x00\x12\x11\n\rDEPARTMENT_HR\x10\x01\x12\x1f\n\x1b\x44\x45PARTMENT_CUSTOMER_SERVICE\x10\x02\"m\n\rPostalAddress\x12\x13\n\x06street\x18\x01 \x01(\tB\x03\x80\x01\x01\x12\x0c\n\x04\x63ity\x18\x02 \x01(\t\x12\x0e\n\x06region\x18\x03 \x01(\t\x12\x18\n\x0bpostal_code\x18\x04 \x01(\tB\x03\x80\x01\x01\x12\x0f\n\x07\x63ountry\x18\x05 \x01(\t\"\xc0\x01\n\x07\x43ontact\x12\x30\n\x0clast_updated\x18\x01 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\x19\n\x06person\x18\x02 \x01(\x0b\x32\x07.PersonH\x00\x12\x1b\n\x07\x63ompany\x18\x03 \x01(\x0b\x32\x08.CompanyH\x00\x12\x0c\n\x04tags\x18\x04 \x03(\t\x12\x12\n\x05notes\x18\x05 \x01(\tB\x03\x80\x01\x01\x12!\n\taddresses\x18\x06 \x03(\x0b\x32\x0e.PostalAddressB\x06\n\x04kind\"\x9d\x01\n\x06\x43hange\x12\n\n\x02id\x18\x01 \x01(\x04\x12&\n\x02\x61t\x18\x02 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\x0e\n\x06\x61uthor\x18\x03 \x01(\t\x12\x0c\n\x04name\x18\x04 \x01(\t\x12\x18\n\x06\x62\x65\x66ore\x18\x05 \x01(\x0b\x32\x08.Contact\x12\x17\n\x05\x61\x66ter\x18\x06 \x01(\x0b\x32\x08.Contact\x12\x0e\n\x06undoes\x18\x07 \x01(\x04\"\x90\x01\n\x0b\x41\x64\x64ressBook\x12,\n\x08\x63ontacts\x18\x01 \x03(\x0b\x32\x1a.AddressBook.ContactsEntry\x12\x18\n\x07\x63hanges\x18\x02 \x03(\x0b\x32\x07.Change\x1a\x39\n\rContactsEntry\x12\x0b\n\x03key\x18\x01 \x01(\t\x12\x17\n\x05value\x18\x02 \x01(\x0b\x32\x08.Contact:\x02\x38\x01\x62\x06proto3')
 
 _globals = globals()
 _builder.BuildMessageAndEnumDescriptors(DESCRIPTOR, _globals)
@@ -36,30 +36,42 @@ if not _descriptor._USE_C_DESCRIPTORS:
   _globals['_PERSON_PHONENUMBER'].fields_by_name['number']._serialized_options = b'\200\001\001'
   _globals['_PERSON'].fields_by_name['email']._loaded_options = None
   _globals['_PERSON'].fields_by_name['email']._serialized_options = b'\200\001\001'
+  _globals['_PERSON'].fields_by_name['emails']._loaded_options = None
+  _globals['_PERSON'].fields_by_name['emails']._serialized_options = b'\200\001\001'
   _globals['_COMPANY_EMAILADDRESS'].fields_by_name['email']._loaded_options = None
   _globals['_COMPANY_EMAILADDRESS'].fields_by_name['email']._serialized_options = b'\200\001\001'
   _globals['_COMPANY_PHONENUMBER'].fields_by_name['number']._loaded_options = None
   _globals['_COMPANY_PHONENUMBER'].fields_by_name['number']._serialized_options = b'\200\001\001'
+  _globals['_POSTALADDRESS'].fields_by_name['street']._loaded_options = None
+  _globals['_POSTALADDRESS'].fields_by_name['street']._serialized_options = b'\200\001\001'
+  _globals['_POSTALADDRESS'].fields_by_name['postal_code']._loaded_options = None
+  _globals['_POSTALADDRESS'].fields_by_name['postal_code']._serialized_options = b'\200\001\001'
+  _globals['_CONTACT'].fields_by_name['notes']._loaded_options = None
+  _globals['_CONTACT'].fields_by_name['notes']._serialized_options = b'\200\001\001'
   _globals['_ADDRESSBOOK_CONTACTSENTRY']._loaded_options = None
   _globals['_ADDRESSBOOK_CONTACTSENTRY']._serialized_options = b'8\001'
   _globals['_PERSON']._serialized_start=55
-  _globals['_PERSON']._serialized_end=274
-  _globals['_PERSON_PHONENUMBER']._serialized_start=123
-  _globals['_PERSON_PHONENUMBER']._serialized_end=274
-  _globals['_PERSON_PHONENUMBER_TYPE']._serialized_start=199
-  _globals['_PERSON_PHONENUMBER_TYPE']._serialized_end=274
-  _globals['_COMPANY']._serialized_start=277
-  _globals['_COMPANY']._serialized_end=611
-  _globals['_COMPANY_EMAILADDRESS']._serialized_start=365
-  _globals['_COMPANY_EMAILADDRESS']._serialized_end=440
-  _globals['_COMPANY_PHONENUMBER']._serialized_start=442
-  _globals['_COMPANY_PHONENUMBER']._serialized_end=517
-  _globals['_COMPANY_DEPARTMENT']._serialized_start=519
-  _globals['_COMPANY_DEPARTMENT']._serialized_end=611
-  _globals['_CONTACT']._serialized_start=613
-  _globals['_CONTACT']._serialized_end=736
-  _globals['_ADDRESSBOOK']._serialized_start=738
-  _globals['_ADDRESSBOOK']._serialized_end=856
-  _globals['_ADDRESSBOOK_CONTACTSENTRY']._serialized_start=799
-  _globals['_ADDRESSBOOK_CONTACTSENTRY']._serialized_end=856
+  _globals['_PERSON']._serialized_end=312
+  _globals['_PERSON_PHONENUMBER']._serialized_start=161
+  _globals['_PERSON_PHONENUMBER']._serialized_end=312
+  _globals['_PERSON_PHONENUMBER_TYPE']._serialized_start=237
+  _globals['_PERSON_PHONENUMBER_TYPE']._serialized_end=312
+  _globals['_COMPANY']._serialized_start=315
+  _globals['_COMPANY']._serialized_end=649
+  _globals['_COMPANY_EMAILADDRESS']._serialized_start=403
+  _globals['_COMPANY_EMAILADDRESS']._serialized_end=478
+  _globals['_COMPANY_PHONENUMBER']._serialized_start=480
+  _globals['_COMPANY_PHONENUMBER']._serialized_end=555
+  _globals['_COMPANY_DEPARTMENT']._serialized_start=557
+  _globals['_COMPANY_DEPARTMENT']._serialized_end=649
+  _globals['_POSTALADDRESS']._serialized_start=651
+  _globals['_POSTALADDRESS']._serialized_end=760
+  _globals['_CONTACT']._serialized_start=763
+  _globals['_CONTACT']._serialized_end=955
+  _globals['_CHANGE']._serialized_start=958
+  _globals['_CHANGE']._serialized_end=1115
+  _globals['_ADDRESSBOOK']._serialized_start=1118
+  _globals['_ADDRESSBOOK']._serialized_end=1262
+  _globals['_ADDRESSBOOK_CONTACTSENTRY']._serialized_start=1205
+  _globals['_ADDRESSBOOK_CONTACTSENTRY']._serialized_end=1262
 # @@protoc_insertion_point(module_scope)
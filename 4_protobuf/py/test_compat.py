@@ -0,0 +1,93 @@
+"""Checks this CLI against the golden files written by the Rust CLI.
+
+Run from this directory with `python -m unittest test_compat`.
+"""
+
+import json
+import os
+import tempfile
+import unittest
+
+import compat
+import main
+import proto.addressbook_pb2 as pb
+
+FIXTURES: str = os.path.join(os.path.dirname(__file__), "..", "rust", "addressbook_1", "tests", "fixtures")
+
+
+def load_golden() -> dict:
+    with open(os.path.join(FIXTURES, "golden.json")) as f:
+        return json.load(f)
+
+
+class CompatTest(unittest.TestCase):
+    def test_reads_rust_book(self):
+        self.assertEqual(load_golden(), compat.dump(os.path.join(FIXTURES, "golden_rs.db")))
+
+    def test_round_trip(self):
+        with tempfile.TemporaryDirectory() as d:
+            path = os.path.join(d, "addressbook.db")
+            compat.write(load_golden(), path)
+
+            self.assertEqual(load_golden(), compat.dump(path))
+
+    def test_add_keeps_rust_fields(self):
+        with tempfile.TemporaryDirectory() as d:
+            path = os.path.join(d, "addressbook.db")
+            with open(os.path.join(FIXTURES, "golden_rs.db"), 'rb') as f:
+                book = main.read_from_db(f)
+            with open(path, 'wb') as f:
+                main.write_to_db(f, book)
+
+            with open(path, 'rb+') as f:
+                main.add_person(f, "Alice Smith", "alice@example.com", "+15550104", "home")
+
+            alice = compat.dump(path)["contacts"]["Alice Smith"]
+            self.assertEqual(["alice@example.com", "a.smith@work.example"], alice["person"]["emails"])
+            self.assertEqual(5, len(alice["person"]["phones"]))
+            self.assertEqual("TYPE_HOME", alice["person"]["phones"][4]["type"])
+            self.assertEqual(["friends", "work"], alice["tags"])
+            self.assertEqual("Prefers email", alice["notes"])
+            changes = compat.dump(path)["changes"]
+            self.assertEqual(4, len(changes))
+            self.assertEqual(4, changes[3]["id"])
+            self.assertEqual("Alice Smith", changes[3]["name"])
+            self.assertEqual(4, len(changes[3]["before"]["person"]["phones"]))
+            self.assertEqual(alice, changes[3]["after"])
+
+    def test_writes_header(self):
+        with tempfile.TemporaryDirectory() as d:
+            path = os.path.join(d, "addressbook.db")
+            compat.write(load_golden(), path)
+
+            with open(path, 'rb') as f:
+                data = f.read()
+            self.assertEqual(main.MAGIC + main.FORMAT_VERSION.to_bytes(4, "little"), data[:8])
+
+    def test_add_moves_legacy_email(self):
+        with tempfile.TemporaryDirectory() as d:
+            path = os.path.join(d, "addressbook.db")
+            book = pb.AddressBook()
+            book.contacts["Bob"].person.email = "bob@example.com"
+            with open(path, 'wb') as f:
+                f.write(book.SerializeToString())
+
+            with open(path, 'rb+') as f:
+                main.add_person(f, "Bob", "bob@work.example", None, None)
+
+            dumped = compat.dump(path)
+            self.assertEqual(["bob@example.com", "bob@work.example"], dumped["contacts"]["Bob"]["person"]["emails"])
+            self.assertIsNone(dumped["changes"][0]["before"]["last_updated"])
+            self.assertEqual(1, dumped["changes"][0]["id"])
+
+    def test_rejects_newer_format(self):
+        with tempfile.TemporaryFile() as f:
+            f.write(main.MAGIC + (main.FORMAT_VERSION + 1).to_bytes(4, "little"))
+            f.seek(0)
+
+            with self.assertRaises(ValueError):
+                main.read_from_db(f)
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -1,5 +0,0 @@
-use std::io::Result;
-fn main() -> Result<()> {
-    prost_build::compile_protos(&["src/addressbook.proto"], &["src/"])?;
-    Ok(())
-}
\ No newline at end of file
@@ -1,14 +1,9 @@
-mod pb {
-    include!(concat!(env!("OUT_DIR"), "/addressbook.ab.rs"));
-}
-
-use std::fs;
 use std::collections::HashMap;
-use std::error::Error;
-use std::io::{BufReader, Read, Write, BufWriter};
-use prost::Message;
-use prost_types::Timestamp;
-use std::time;
+
+use addressbook_core::{pb, validate, AddressBook, Details};
+use addressbook_core::{str_to_department, str_to_phone_type};
+
+pub use addressbook_core::{error, AddressBookError};
 
 const DB_FILE_PATH: &str = "addressbook.db";
 
@@ -41,155 +36,47 @@ impl Config {
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let mut f = open_db_file(DB_FILE_PATH);
+pub fn run(config: Config) -> Result<(), AddressBookError> {
+    let mut book = AddressBook::open(DB_FILE_PATH)?;
     match config.command.as_ref() {
         "add" => {
-            if config.params["--kind"] == "per" || config.params["--kind"] == "person"{
-                add_person(&mut f, &config.params["--name"], 
-                &config.params.get("--email").unwrap_or(&"None".to_string()),  
-                &config.params.get("--phone").unwrap_or(&"None".to_string()), 
-                &config.params.get("--type").unwrap_or(&"None".to_string()));
+            let param = |arg| config.params.get(arg).map(String::as_str);
+            let required = |arg| param(arg).ok_or(AddressBookError::MissingArgument(arg));
+            let kind = required("--kind")?;
+            let name = required("--name")?;
+            book.set_region(param("--region").unwrap_or(validate::DEFAULT_REGION));
+            let email = param("--email");
+            let phone = param("--phone");
+            let phone_type = param("--type").unwrap_or("");
+            if kind == "per" || kind == "person"{
+                book.upsert_person(name, email, phone.map(|p| (p, str_to_phone_type(phone_type))), &Details::default())?;
+            }
+            else if kind == "cie" || kind == "company"{
+                let email_dep = str_to_department(param("--dep").unwrap_or(""));
+                // A company phone takes its department from --type
+                let phone_dep = str_to_department(phone_type);
+                book.upsert_company(name, email.map(|e| (e, email_dep)), phone.map(|p| (p, phone_dep)), &Details::default())?;
             }
-            else if config.params["--kind"] == "cie" || config.params["--kind"] == "company"{
-                add_company(&mut f, &config.params["--name"], 
-                &config.params.get("--email").unwrap_or(&"None".to_string()), 
-                &config.params.get("--dep").unwrap_or(&"None".to_string()), 
-                &config.params.get("--phone").unwrap_or(&"None".to_string()), 
-                &config.params.get("--type").unwrap_or(&"None".to_string())
-            );
+            else {
+                return Err(AddressBookError::InvalidArgument { arg: "--kind", value: kind.to_string() });
             }
-            Ok(())
+            book.save()
         },
         "list" => {
-            list_contacts(&mut f);
-            Ok(())
+            list_contacts(&book)
         },
-        _ => Err("Command not found")?,
+        command => Err(AddressBookError::InvalidArgument { arg: "command", value: command.to_string() }),
     }
 }
 
-fn open_db_file(file_path: &str) -> fs::File {
-    // File::open only for reading
-    fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true) // Creates if file does not exists
-        .open(file_path)
-        .unwrap()
-}
-
-fn read_from_db(f: &mut fs::File) -> pb::AddressBook {
-    let mut buf_reader = BufReader::new(f);
-    let mut contents = Vec::new();
-    buf_reader.read_to_end(&mut contents).unwrap();
-    pb::AddressBook::decode(contents.as_slice()).unwrap()
-}
-
-fn write_to_db(f: &mut fs::File, book: pb::AddressBook) {
-    let mut buf_writer = BufWriter::new(f);
-    let contents = book.encode_to_vec();
-    buf_writer.write_all(&contents).unwrap();
-    buf_writer.flush().unwrap();
-}
-fn str_to_phone_type(s: &str) ->i32 {
-    match s {
-        "home" => 2,
-        "mobile" => 1,
-        "work" => 3,
-        _ => 0,
-    }
-}
-fn str_to_department(s: &str) -> i32 {
-    match s {
-        "hr" => 1,
-        "cs" => 2,
-        _ => 0
-    }
-}
-
-fn add_person(f: &mut fs::File, name: &str, email: &str, phone: &str, phone_type: &str) {
-    let mut book = read_from_db(f);
-    let mut person: pb::Person;
-    if book.contacts.contains_key(name) {
-        // If Kind exists
-        let kind = book.contacts.get(name).unwrap().kind.clone().unwrap();
-        match kind {
-            pb::contact::Kind::Person(p) => { person = p },
-            pb::contact::Kind::Company(_) => {panic!("Company")},
-        }
-    }
-    else {
-        person = pb::Person::default();
-    }
-    person.email = email.to_string();
-    let mut nb = pb::person::PhoneNumber::default();
-    nb.number = phone.to_string();
-    nb.r#type = str_to_phone_type(phone_type);
-    person.phones.push(nb);
-
-    let mut contact = pb::Contact::default();
-    let mut update_ts = Timestamp::default();
-    let duration = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap();
-    update_ts.seconds = duration.as_secs() as i64;
-    update_ts.nanos = duration.subsec_nanos() as i32;
-
-    contact.last_updated = Some(update_ts);
-    contact.kind = Some(pb::contact::Kind::Person(person));
-    book.contacts.insert(String::from(name), contact);
-
-    write_to_db(f, book);
-}
-
-fn add_company(f: &mut fs::File, name: &str, email: &str, email_dep: &str, phone: &str, phone_dep: &str) {
-    let mut book = read_from_db(f);
-
-    let mut company: pb::Company;
-    if book.contacts.contains_key(name) {
-        // If Kind exists
-        let kind = book.contacts.get(name).unwrap().kind.clone().unwrap(); // .clone
-        match kind {
-            pb::contact::Kind::Company(comp) => {company = comp },
-            pb::contact::Kind::Person(_) => { panic!("Person") },
-        }
-    }
-    else {
-        company = pb::Company::default();
-    }
-
-    let mut addr = pb::company::EmailAddress::default();
-    addr.email = email.to_string();
-    addr.department = str_to_department(email_dep);
-    company.emails.push(addr);
-
-    let mut nb = pb::company::PhoneNumber::default();
-    nb.number = phone.to_string();
-    nb.department = str_to_department(phone_dep);
-    company.phones.push(nb);
-
-    let mut contact = pb::Contact::default();
-    let mut update_ts = Timestamp::default();
-    let duration = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap();
-    update_ts.seconds = duration.as_secs() as i64;
-    update_ts.nanos = duration.subsec_nanos() as i32;
-
-    contact.last_updated = Some(update_ts);
-    contact.kind = Some(pb::contact::Kind::Company(company));
-    book.contacts.insert(String::from(name), contact);
-
-    write_to_db(f, book);
-}
-
-fn list_contacts(f: &mut fs::File) {
-    let book = read_from_db(f);
-    let mut keys: Vec<&String>  = book.contacts.keys().collect();
-    keys.sort();
-    for name in keys {
-        let contact = book.contacts.get(name).unwrap();
+fn list_contacts(book: &AddressBook) -> Result<(), AddressBookError> {
+    let mut contacts: Vec<(String, pb::Contact)> = book.iter()?.collect();
+    contacts.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (name, contact) in contacts {
         println!("name: {}", name);
-        println!("last_updated: {:?}", contact.last_updated.unwrap());
+        println!("last_updated: {:?}", contact.last_updated.unwrap_or_default());
         println!("{:#?}", contact);
         println!("-----------------------");
     }
+    Ok(())
 }
-
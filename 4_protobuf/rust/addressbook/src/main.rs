@@ -11,7 +11,7 @@ fn main() {
     });
 
     if let Err(e) = addressbook::run(config) {
-        eprintln!("Application error: {e}");
+        eprintln!("Error: {e}");
         process::exit(1);
     }
 }
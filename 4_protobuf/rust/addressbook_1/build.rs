@@ -1,5 +1,19 @@
 use std::io::Result;
+
+// The messages of addressbook.proto, which addressbook_core generates.
+const CORE_MESSAGES: &[&str] = &["Person", "Company", "PostalAddress", "Contact", "Change", "AddressBook"];
+
 fn main() -> Result<()> {
-    prost_build::compile_protos(&["src/addressbook.proto"], &["src/"])?;
+    let mut config = prost_build::Config::new();
+    config.enable_type_names();
+    for message in CORE_MESSAGES {
+        config.extern_path(format!(".addressbook.ab.{message}"), format!("::addressbook_core::pb::{message}"));
+    }
+    println!("cargo:rerun-if-changed=../addressbook_core/src/addressbook.proto");
+    tonic_build::configure().compile_protos_with_config(
+        config,
+        &["src/addressbook_service.proto", "src/sync.proto"],
+        &["src/", "../addressbook_core/src/"],
+    )?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,7 +1,29 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset};
 use clap::{Args, Parser, Subcommand};
+use clap_complete::ArgValueCompleter;
+
+use crate::{complete, validate};
 
 #[derive(Parser)]
+#[command(after_help = "Encrypted address books take their passphrase from ADDRESSBOOK_PASSPHRASE, or ask for it.")]
 pub struct Cli {
+    /// Address book file, protobuf or SQLite
+    #[arg(long, global = true, default_value = "addressbook.db")]
+    pub db: PathBuf,
+
+    /// Use an addressbook_server at this URL (e.g. http://127.0.0.1:50051) instead of --db
+    #[arg(long, global = true)]
+    pub server: Option<String>,
+
+    /// Name recorded in the change log, $USER by default
+    #[arg(long, global = true)]
+    pub author: Option<String>,
+
+    #[command(flatten)]
+    pub time: TimeArgs,
+
     #[command(subcommand)]
     pub command: Option<Commands>
 }
@@ -10,6 +32,31 @@ pub struct Cli {
 pub enum Commands {
     Add(AddArgs),
     List(ListArgs),
+    /// Copy all contacts into another file, possibly with another backend
+    MigrateStorage(MigrateStorageArgs),
+    /// Show the recorded changes of a contact
+    History(HistoryArgs),
+    /// Revert the latest change that was not undone yet
+    Undo,
+    /// Encrypt the address book with a new passphrase, asked for or taken from ADDRESSBOOK_NEW_PASSPHRASE (protobuf books only: SQLite books stay plain)
+    Rekey(RekeyArgs),
+    /// Find contacts that look like duplicates and merge them, asking which name to keep
+    Dedupe(DedupeArgs),
+    /// Merge changes with another address book, keeping the newest version of each contact
+    Sync(SyncArgs),
+    /// Browse, search and edit contacts in a terminal UI
+    Tui(TuiArgs),
+    /// Serve the contacts as a JSON REST API over HTTP
+    Serve(ServeArgs),
+    /// Run many commands, one per line, and save them all at once
+    Batch(BatchArgs),
+    /// Print the shell script that completes commands, flags and contact names
+    #[command(after_help = "e.g. `source <(addressbook_1 completions bash)` in ~/.bashrc, \
+`source <(addressbook_1 completions zsh)` in ~/.zshrc, or \
+`addressbook_1 completions fish > ~/.config/fish/completions/addressbook_1.fish`")]
+    Completions(CompletionsArgs),
+    /// Print the man page in roff
+    Man(ManArgs),
 }
 
 #[derive(
@@ -44,9 +91,44 @@ pub enum KindType {
     Person,
 }
 
+#[derive(
+    clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq,
+)]
+pub enum FormatType {
+    /// One "field: value" line per field
+    #[default]
+    Plain,
+    /// One row per contact, with the same columns in the same order every time
+    Table,
+    Yaml,
+    Json,
+}
+
+#[derive(
+    clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq,
+)]
+pub enum TimeType {
+    /// e.g. 2024-05-01T14:00:00+02:00
+    #[default]
+    Rfc3339,
+    /// e.g. "3 days ago"; JSON and YAML output keep RFC 3339
+    Relative,
+}
+
+#[derive(Args)]
+pub struct TimeArgs {
+    /// How to show timestamps
+    #[arg(long, global = true, default_value_t, value_enum)]
+    pub time: TimeType,
+
+    /// Time zone of the timestamps shown: an IANA name such as Europe/Paris, UTC, or local
+    #[arg(long, global = true, default_value = "local")]
+    pub tz: String,
+}
+
 #[derive(Args)]
 pub struct AddArgs {
-    #[arg(short, long)]
+    #[arg(short, long, add = ArgValueCompleter::new(complete::names))]
     pub name: String,
 
     #[arg(short, long)]
@@ -63,11 +145,204 @@ pub struct AddArgs {
 
     #[arg(short, long, default_value_t, value_enum)]
     pub r#type: PhoneType,
-    
+
+    /// Region for phone numbers given without a country code
+    #[arg(long, default_value = validate::DEFAULT_REGION)]
+    pub region: String,
+
+    /// Tag to add to the contact, can be repeated
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+
+    /// Replace the contact's notes
+    #[arg(long)]
+    pub notes: Option<String>,
+
+    /// Postal address to add, as "street; city; region; postal code; country"
+    #[arg(long)]
+    pub address: Option<String>,
+
+    /// Company contact the person belongs to
+    #[arg(long)]
+    pub company: Option<String>,
 }
 
 #[derive(Args)]
 pub struct ListArgs {
     #[arg(short, long)]
     pub redact: bool,
-}
\ No newline at end of file
+
+    /// Only list contacts with this tag
+    #[arg(long)]
+    pub tag: Option<String>,
+
+    #[arg(short, long, default_value_t, value_enum)]
+    pub format: FormatType,
+
+    /// Show the book as it was at this RFC 3339 time (e.g. 2024-05-01T12:00:00Z)
+    #[arg(long, value_parser = DateTime::parse_from_rfc3339)]
+    pub as_of: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    #[arg(short, long, add = ArgValueCompleter::new(complete::names))]
+    pub name: String,
+
+    #[arg(short, long)]
+    pub redact: bool,
+}
+
+#[derive(
+    clap::ValueEnum, Clone, Debug,
+)]
+pub enum BackendType {
+    Proto,
+    Sqlite,
+}
+
+#[derive(Args)]
+pub struct MigrateStorageArgs {
+    /// Destination file
+    #[arg(long)]
+    pub to: PathBuf,
+
+    /// Destination backend, guessed from the file when omitted
+    #[arg(short, long, value_enum)]
+    pub backend: Option<BackendType>,
+
+    /// Write an encrypted address book in plain, as a SQLite one must be
+    #[arg(long)]
+    pub decrypt: bool,
+}
+
+#[derive(Args)]
+pub struct RekeyArgs {
+    /// Store the address book in plain instead
+    #[arg(long)]
+    pub decrypt: bool,
+}
+
+#[derive(Args)]
+pub struct DedupeArgs {
+    /// Merge every group into its most recently updated contact without asking
+    #[arg(short, long, conflicts_with = "dry_run")]
+    pub yes: bool,
+
+    /// Only list the groups
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args)]
+pub struct SyncArgs {
+    #[command(flatten)]
+    pub peer: SyncPeer,
+
+    /// Stop after the first sync instead of waiting for more
+    #[arg(long, requires = "listen")]
+    pub once: bool,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct SyncPeer {
+    /// Another address book file
+    #[arg(long)]
+    pub with: Option<PathBuf>,
+
+    /// An instance running `sync --listen` at this address (e.g. 192.168.1.2:7878)
+    #[arg(long)]
+    pub connect: Option<String>,
+
+    /// Wait for instances running `sync --connect` on this address
+    #[arg(long)]
+    pub listen: Option<String>,
+}
+
+#[derive(Args)]
+pub struct TuiArgs {
+    /// Start with emails and phones hidden
+    #[arg(short, long)]
+    pub redact: bool,
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: String,
+
+    /// Token allowed to read and change contacts, can be repeated; without any token the API is open to all
+    #[arg(long = "token")]
+    pub tokens: Vec<String>,
+
+    /// Token only allowed to read contacts, can be repeated
+    #[arg(long = "read-token")]
+    pub read_tokens: Vec<String>,
+
+    /// Hide emails, phones and other private fields from read-only tokens
+    #[arg(short, long)]
+    pub redact: bool,
+
+    /// Region for phone numbers given without a country code
+    #[arg(long, default_value = validate::DEFAULT_REGION)]
+    pub region: String,
+}
+
+#[derive(Args)]
+#[command(after_help = "Each line is a command such as `add --name \"Bob Smith\" --kind per --email bob@example.com` \
+or `delete --name Bob`, or the same as JSON: {\"op\": \"add\", \"name\": \"Bob\", \"kind\": \"per\", \"tag\": [\"work\"]}. \
+JSON can also replace a whole contact: {\"op\": \"put\", \"name\": \"Bob\", \"contact\": {...}}. \
+Empty lines and lines starting with # are skipped.")]
+pub struct BatchArgs {
+    /// File to read the commands from, stdin when omitted or -
+    pub file: Option<PathBuf>,
+
+    /// Skip the lines that fail and save the others, instead of saving nothing
+    #[arg(long)]
+    pub keep_going: bool,
+}
+
+/// One line of a `batch` script.
+#[derive(Parser)]
+#[command(name = "batch", no_binary_name = true)]
+pub struct BatchLine {
+    #[command(subcommand)]
+    pub command: BatchCommand,
+}
+
+#[derive(Subcommand)]
+pub enum BatchCommand {
+    Add(AddArgs),
+    /// Delete a contact
+    Delete(DeleteArgs),
+}
+
+#[derive(Args)]
+pub struct DeleteArgs {
+    #[arg(short, long)]
+    pub name: String,
+}
+
+#[derive(
+    clap::ValueEnum, Clone, Copy, Debug,
+)]
+pub enum ShellType {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    #[arg(value_enum)]
+    pub shell: ShellType,
+}
+
+#[derive(Args)]
+pub struct ManArgs {
+    /// Write one page per command into this directory instead
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
@@ -0,0 +1,258 @@
+// The `batch` subcommand: many changes read from a script and saved at once.
+//
+// A line is either a command with the flags of the CLI, split like a shell
+// would (quotes and backslashes), or a JSON object whose "op" is the command
+// and whose other keys are its long flags: {"op": "add", "name": "Bob",
+// "kind": "per", "tag": ["a", "b"]}. JSON can also replace a whole contact
+// with {"op": "put", "name": ..., "contact": ...}, the contact as the HTTP
+// API takes it. Nothing is durable until every line ran, because the book
+// is only saved at the end; so a failing line rolls the whole batch back.
+
+use std::io::BufRead;
+
+use clap::Parser;
+use serde_json::Value;
+
+use crate::arguments::{BatchCommand, BatchLine};
+use crate::error::{AddressBookError, BookError};
+use crate::pb;
+use crate::{add, http, AddressBook};
+
+/// What happened to each line of a batch.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Line number (from 1) and what the line did, or why it failed.
+    /// Skipped lines are left out; so are the lines after a failure
+    /// without `keep_going`, which are not run.
+    pub lines: Vec<(usize, Result<String, String>)>,
+    /// Whether the changes were saved.
+    pub saved: bool,
+}
+
+impl Report {
+    pub fn failed(&self) -> usize {
+        self.lines.iter().filter(|(_, result)| result.is_err()).count()
+    }
+}
+
+enum Op {
+    Command(BatchCommand),
+    Put { name: String, contact: pb::Contact },
+}
+
+/// Runs every line of `input` on `book`, then saves the book unless a line
+/// failed and `keep_going` is false. A line that fails changes nothing.
+pub fn run(book: &mut AddressBook, input: impl BufRead, keep_going: bool) -> Result<Report, AddressBookError> {
+    let mut report = Report::default();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = parse(line).and_then(|op| apply(book, op).map_err(|e| e.to_string()));
+        let failed = result.is_err();
+        report.lines.push((i + 1, result));
+        if failed && !keep_going {
+            return Ok(report);
+        }
+    }
+    book.save()?;
+    report.saved = true;
+    Ok(report)
+}
+
+fn parse(line: &str) -> Result<Op, String> {
+    if line.starts_with('{') {
+        return parse_json(line);
+    }
+    parse_command(split_words(line)?)
+}
+
+fn parse_command(words: Vec<String>) -> Result<Op, String> {
+    match BatchLine::try_parse_from(words) {
+        Ok(parsed) => Ok(Op::Command(parsed.command)),
+        // The first line of clap's message, without the usage that follows
+        Err(e) => Err(e.to_string().lines().next().unwrap_or_default().trim_start_matches("error: ").to_string()),
+    }
+}
+
+fn parse_json(line: &str) -> Result<Op, String> {
+    let value: Value = serde_json::from_str(line).map_err(|e| format!("invalid JSON: {e}"))?;
+    let Some(Value::String(op)) = value.get("op") else {
+        return Err("missing \"op\"".to_string());
+    };
+    if op == "put" {
+        let Some(Value::String(name)) = value.get("name") else {
+            return Err("missing \"name\"".to_string());
+        };
+        let contact = http::contact_from_json(value.get("contact").unwrap_or(&Value::Null))?;
+        return Ok(Op::Put { name: name.clone(), contact });
+    }
+
+    let mut words = vec![op.clone()];
+    for (key, value) in value.as_object().into_iter().flatten().filter(|(key, _)| *key != "op") {
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value {
+            Value::Null | Value::Bool(false) => {}
+            Value::Bool(true) => words.push(flag),
+            Value::String(s) => words.extend([flag, s.clone()]),
+            Value::Number(n) => words.extend([flag, n.to_string()]),
+            Value::Array(values) => {
+                for value in values {
+                    let Value::String(s) = value else {
+                        return Err(format!("{key:?} must hold strings"));
+                    };
+                    words.extend([flag.clone(), s.clone()]);
+                }
+            }
+            Value::Object(_) => return Err(format!("{key:?} cannot be an object")),
+        }
+    }
+    parse_command(words)
+}
+
+fn apply(book: &mut AddressBook, op: Op) -> Result<String, AddressBookError> {
+    match op {
+        Op::Command(BatchCommand::Add(x)) => {
+            let existed = book.get(&x.name)?.is_some();
+            add(book, &x)?;
+            Ok(format!("{} {}", if existed { "updated" } else { "created" }, x.name))
+        }
+        Op::Command(BatchCommand::Delete(x)) => match book.remove(&x.name)? {
+            Some(_) => Ok(format!("deleted {}", x.name)),
+            None => Err(BookError::NotFound(x.name).into()),
+        },
+        Op::Put { name, contact } => {
+            let existed = book.get(&name)?.is_some();
+            book.replace(&name, contact)?;
+            Ok(format!("{} {name}", if existed { "replaced" } else { "created" }))
+        }
+    }
+}
+
+// Splits a line into words like a POSIX shell: whitespace separates words,
+// quotes group them, and a backslash escapes the next character outside
+// single quotes.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated ' quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated \" quote".to_string()),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::contact::Kind;
+
+    fn open(dir: &tempfile::TempDir) -> AddressBook {
+        AddressBook::open(dir.path().join("addressbook.db")).unwrap()
+    }
+
+    const SCRIPT: &str = r#"
+# people
+add --name "Bob Smith" --kind per --email bob@example.com --tag friends
+{"op": "add", "name": "Acme", "kind": "cie", "phone": "201-555-0100", "dep": "hr", "tag": ["client", "big"]}
+add --name 'Bob Smith' --kind per --phone 201-555-0123 --type mobile
+{"op": "put", "name": "Al", "contact": {"kind": "person", "emails": ["al@example.com"]}}
+delete --name Al
+"#;
+
+    #[test]
+    fn applies_every_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut book = open(&dir);
+
+        let report = run(&mut book, SCRIPT.as_bytes(), false).unwrap();
+
+        assert!(report.saved);
+        let lines: Vec<(usize, String)> = report.lines.into_iter().map(|(n, r)| (n, r.unwrap())).collect();
+        assert_eq!(
+            vec![
+                (3, "created Bob Smith".to_string()),
+                (4, "created Acme".to_string()),
+                (5, "updated Bob Smith".to_string()),
+                (6, "created Al".to_string()),
+                (7, "deleted Al".to_string()),
+            ],
+            lines
+        );
+        let book = open(&dir);
+        let names: Vec<String> = book.iter().unwrap().map(|(name, _)| name).collect();
+        assert_eq!(vec!["Acme", "Bob Smith"], names);
+        let acme = book.get("Acme").unwrap().unwrap();
+        assert_eq!(vec!["client", "big"], acme.tags);
+        match book.get("Bob Smith").unwrap().unwrap().kind {
+            Some(Kind::Person(p)) => assert_eq!("+12015550123", p.phones[0].number),
+            _ => panic!("expected a person"),
+        }
+    }
+
+    #[test]
+    fn first_error_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut book = open(&dir);
+        let script = "add --name Bob --kind per --email bob@example.com\nadd --name Al --kind per --email nope\nadd --name Cy --kind per --email cy@example.com\n";
+
+        let report = run(&mut book, script.as_bytes(), false).unwrap();
+
+        assert!(!report.saved);
+        assert_eq!(2, report.lines.len());
+        assert_eq!(1, report.failed());
+        assert_eq!(2, report.lines[1].0);
+        assert!(report.lines[1].1.as_ref().unwrap_err().contains("invalid email"));
+        assert_eq!(0, open(&dir).iter().unwrap().count());
+    }
+
+    #[test]
+    fn keep_going_saves_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut book = open(&dir);
+        let script = "add --name Bob --kind per --email bob@example.com\nfrobnicate --name Al\n{\"op\": \"add\"\ndelete --name Nobody\nadd --name Cy --kind per --email cy@example.com\n";
+
+        let report = run(&mut book, script.as_bytes(), true).unwrap();
+
+        assert!(report.saved);
+        assert_eq!(5, report.lines.len());
+        assert_eq!(3, report.failed());
+        let names: Vec<String> = open(&dir).iter().unwrap().map(|(name, _)| name).collect();
+        assert_eq!(vec!["Bob", "Cy"], names);
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(vec!["add", "--name", "Bob Smith", "it's"], split_words(r#"add  --name "Bob Smith" it\'s"#).unwrap());
+        assert_eq!(vec!["a\"b", "", "c d"], split_words(r#"'a"b' "" c\ d"#).unwrap());
+        assert!(split_words("add --name \"Bob").is_err());
+    }
+}
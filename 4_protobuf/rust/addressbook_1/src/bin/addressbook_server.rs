@@ -0,0 +1,48 @@
+// Run examples:
+// cargo run --bin addressbook_server -- --db addressbook.db --addr 127.0.0.1:50051
+// cargo run -- --server http://127.0.0.1:50051 list
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process;
+
+use addressbook_1::{grpc, read_passphrase, validate, AddressBook, AddressBookError};
+use clap::Parser;
+
+/// Serves an address book over gRPC
+#[derive(Parser)]
+#[command(after_help = "An encrypted address book takes its passphrase from ADDRESSBOOK_PASSPHRASE, or asks for it.")]
+struct ServerCli {
+    /// Address book file, protobuf or SQLite
+    #[arg(long, default_value = "addressbook.db")]
+    db: PathBuf,
+
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    addr: SocketAddr,
+
+    /// Region for phone numbers given without a country code
+    #[arg(long, default_value = validate::DEFAULT_REGION)]
+    region: String,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = ServerCli::parse();
+
+    let result = match open(&cli) {
+        Ok(book) => grpc::serve(book, cli.addr, &cli.region).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+}
+
+fn open(cli: &ServerCli) -> Result<AddressBook, AddressBookError> {
+    let book = match read_passphrase(&cli.db)? {
+        Some(passphrase) => AddressBook::open_encrypted(&cli.db, &passphrase)?,
+        None => AddressBook::open(&cli.db)?,
+    };
+    Ok(book)
+}
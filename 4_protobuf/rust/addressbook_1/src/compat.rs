@@ -0,0 +1,322 @@
+// Golden-file compatibility tests with the Python CLI in 4_protobuf/py.
+//
+// Both implementations turn a book into the same canonical JSON (described in
+// py/compat.py), which makes "written by one, read by the other" checkable
+// field for field. `golden_book` is the reference book and
+// tests/fixtures/golden.json its canonical JSON; tests/fixtures/golden_rs.db
+// is the book as this crate writes it, which py/test_compat.py reads.
+// Regenerate both after a format change with `UPDATE_GOLDEN=1 cargo test compat`.
+//
+// The tests that run Python need `$PYTHON` (python3 by default) with the
+// protobuf package, so they are ignored by default: run them with
+// `cargo test compat -- --ignored`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use prost_types::Timestamp;
+use serde_json::{json, Value};
+
+use addressbook_core::db;
+use crate::pb;
+use crate::pb::company::Department;
+use crate::pb::contact::Kind;
+use crate::pb::person::phone_number::Type;
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+fn golden_json() -> Value {
+    serde_json::from_slice(&fs::read(fixture("golden.json")).unwrap()).unwrap()
+}
+
+fn ts(seconds: i64, nanos: i32) -> Option<Timestamp> {
+    Some(Timestamp { seconds, nanos })
+}
+
+// Every field set at least once, every enum value used, and the edge cases:
+// a contact without a kind, a company without entries, a pre-1970 timestamp
+// and a non-ASCII name.
+fn golden_book() -> pb::AddressBook {
+    let phone = |number: &str, phone_type: Type| pb::person::PhoneNumber {
+        number: number.to_string(),
+        r#type: phone_type.into(),
+    };
+    let alice = pb::Contact {
+        last_updated: ts(1_700_000_000, 123_456_789),
+        kind: Some(Kind::Person(pb::Person {
+            emails: vec!["alice@example.com".to_string(), "a.smith@work.example".to_string()],
+            phones: vec![
+                phone("+15550100", Type::Unspecified),
+                phone("+15550101", Type::Mobile),
+                phone("+15550102", Type::Home),
+                phone("+15550103", Type::Work),
+            ],
+            company: "Acme Corp".to_string(),
+            ..Default::default()
+        })),
+        tags: vec!["friends".to_string(), "work".to_string()],
+        notes: "Prefers email".to_string(),
+        addresses: vec![pb::PostalAddress {
+            street: "1 Main St".to_string(),
+            city: "Springfield".to_string(),
+            region: "IL".to_string(),
+            postal_code: "62701".to_string(),
+            country: "US".to_string(),
+        }],
+    };
+    let acme = pb::Contact {
+        last_updated: ts(1_600_000_000, 0),
+        kind: Some(Kind::Company(pb::Company {
+            emails: vec![
+                pb::company::EmailAddress {
+                    email: "hr@acme.example".to_string(),
+                    department: Department::Hr.into(),
+                },
+                pb::company::EmailAddress {
+                    email: "help@acme.example".to_string(),
+                    department: Department::CustomerService.into(),
+                },
+            ],
+            phones: vec![pb::company::PhoneNumber {
+                number: "+15550200".to_string(),
+                department: Department::Unspecified.into(),
+            }],
+        })),
+        ..Default::default()
+    };
+    let empty = pb::Contact {
+        last_updated: ts(-1, 999_999_999),
+        kind: Some(Kind::Company(pb::Company::default())),
+        ..Default::default()
+    };
+    let zoe = pb::Contact {
+        kind: Some(Kind::Person(pb::Person {
+            emails: vec!["zoë@example.com".to_string()],
+            ..Default::default()
+        })),
+        notes: "Ünïcödé ✓".to_string(),
+        ..Default::default()
+    };
+
+    let mut book = pb::AddressBook::default();
+    book.contacts.insert("Alice Smith".to_string(), alice.clone());
+    book.contacts.insert("Acme Corp".to_string(), acme);
+    book.contacts.insert("Empty Inc".to_string(), empty);
+    book.contacts.insert("Zoë".to_string(), zoe);
+    book.contacts.insert("No Kind".to_string(), pb::Contact::default());
+    book.changes = vec![
+        pb::Change {
+            id: 1,
+            at: ts(1_700_000_000, 123_456_789),
+            author: "alice".to_string(),
+            name: "Alice Smith".to_string(),
+            before: None,
+            after: Some(alice.clone()),
+            undoes: 0,
+        },
+        pb::Change {
+            id: 2,
+            at: ts(1_700_000_100, 0),
+            author: "bob".to_string(),
+            name: "Alice Smith".to_string(),
+            before: Some(alice.clone()),
+            after: None,
+            undoes: 0,
+        },
+        pb::Change {
+            id: 3,
+            at: ts(1_700_000_200, 1),
+            author: "alice".to_string(),
+            name: "Alice Smith".to_string(),
+            before: None,
+            after: Some(alice),
+            undoes: 2,
+        },
+    ];
+    book
+}
+
+fn timestamp_to_json(ts: Option<Timestamp>) -> Value {
+    match ts {
+        Some(ts) => json!({ "seconds": ts.seconds, "nanos": ts.nanos }),
+        None => Value::Null,
+    }
+}
+
+// Values this schema does not know stay numbers, as in Python.
+fn enum_to_json<E: TryFrom<i32>>(value: i32, name: fn(&E) -> &'static str) -> Value {
+    match E::try_from(value) {
+        Ok(e) => json!(name(&e)),
+        Err(_) => json!(value),
+    }
+}
+
+fn contact_to_json(contact: &pb::Contact) -> Value {
+    let mut out = json!({
+        "last_updated": timestamp_to_json(contact.last_updated),
+        "tags": contact.tags,
+        "notes": contact.notes,
+        "addresses": contact.addresses.iter().map(|a| json!({
+            "street": a.street,
+            "city": a.city,
+            "region": a.region,
+            "postal_code": a.postal_code,
+            "country": a.country,
+        })).collect::<Vec<_>>(),
+    });
+    match &contact.kind {
+        Some(Kind::Person(p)) => {
+            out["person"] = json!({
+                "email": p.email,
+                "emails": p.emails,
+                "phones": p.phones.iter().map(|pn| json!({
+                    "number": pn.number,
+                    "type": enum_to_json(pn.r#type, Type::as_str_name),
+                })).collect::<Vec<_>>(),
+                "company": p.company,
+            })
+        }
+        Some(Kind::Company(c)) => {
+            out["company"] = json!({
+                "emails": c.emails.iter().map(|em| json!({
+                    "email": em.email,
+                    "department": enum_to_json(em.department, Department::as_str_name),
+                })).collect::<Vec<_>>(),
+                "phones": c.phones.iter().map(|pn| json!({
+                    "number": pn.number,
+                    "department": enum_to_json(pn.department, Department::as_str_name),
+                })).collect::<Vec<_>>(),
+            })
+        }
+        None => {}
+    }
+    out
+}
+
+/// The canonical JSON of `book`, as `py/compat.py dump` prints it.
+fn book_to_json(book: &pb::AddressBook) -> Value {
+    let mut contacts: Vec<_> = book.contacts.iter().collect();
+    contacts.sort_by_key(|(name, _)| *name);
+    let contacts: serde_json::Map<String, Value> =
+        contacts.into_iter().map(|(name, contact)| (name.clone(), contact_to_json(contact))).collect();
+    let changes: Vec<Value> = book
+        .changes
+        .iter()
+        .map(|change| {
+            json!({
+                "id": change.id,
+                "at": timestamp_to_json(change.at),
+                "author": change.author,
+                "name": change.name,
+                "before": change.before.as_ref().map_or(Value::Null, contact_to_json),
+                "after": change.after.as_ref().map_or(Value::Null, contact_to_json),
+                "undoes": change.undoes,
+            })
+        })
+        .collect();
+    json!({ "contacts": contacts, "changes": changes })
+}
+
+// The Python interpreter, which must be able to run py/compat.py.
+fn python() -> String {
+    let python = env::var("PYTHON").unwrap_or_else(|_| "python3".to_string());
+    let usable = Command::new(&python)
+        .args(["-c", "import google.protobuf"])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    assert!(usable, "{python} cannot import google.protobuf: install it, or set $PYTHON to a Python that has it");
+    python
+}
+
+fn py_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../py")
+}
+
+fn run_compat(python: &str, args: &[&Path]) -> Vec<u8> {
+    let output = Command::new(python).arg(py_dir().join("compat.py")).args(args).output().unwrap();
+    assert!(output.status.success(), "compat.py failed: {}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+#[test]
+fn golden_json_is_current() {
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        let json = serde_json::to_string_pretty(&book_to_json(&golden_book())).unwrap();
+        fs::write(fixture("golden.json"), json + "\n").unwrap();
+    }
+
+    assert_eq!(golden_json(), book_to_json(&golden_book()));
+}
+
+#[test]
+fn golden_file_is_current() {
+    let path = fixture("golden_rs.db");
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, db::encode(&golden_book())).unwrap();
+    }
+
+    let (version, book) = db::decode(&fs::read(&path).unwrap()).unwrap();
+
+    assert_eq!(db::CURRENT_VERSION, version, "regenerate golden_rs.db with UPDATE_GOLDEN=1");
+    assert_eq!(golden_book(), book);
+}
+
+#[test]
+#[ignore = "needs python3 with the protobuf package"]
+fn python_reads_rust_book() {
+    let python = python();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("addressbook.db");
+    fs::write(&path, db::encode(&golden_book())).unwrap();
+
+    let dumped: Value = serde_json::from_slice(&run_compat(&python, &[Path::new("dump"), &path])).unwrap();
+
+    assert_eq!(golden_json(), dumped);
+}
+
+#[test]
+#[ignore = "needs python3 with the protobuf package"]
+fn rust_reads_python_book() {
+    let python = python();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("addressbook.db");
+
+    run_compat(&python, &[Path::new("write"), &fixture("golden.json"), &path]);
+    let (version, book) = db::decode(&fs::read(&path).unwrap()).unwrap();
+
+    assert_eq!(db::CURRENT_VERSION, version);
+    assert_eq!(golden_json(), book_to_json(&book));
+}
+
+#[test]
+#[ignore = "needs python3 with the protobuf package"]
+fn python_add_is_logged() {
+    let python = python();
+    let dir = tempfile::tempdir().unwrap();
+    fs::copy(fixture("golden_rs.db"), dir.path().join("addressbook.db")).unwrap();
+
+    // main.py works on addressbook.db in its current directory
+    let output = Command::new(&python)
+        .arg(py_dir().join("main.py"))
+        .args(["add", "--name", "Alice Smith", "--kind", "per", "--phone", "+15550104", "--type", "home"])
+        .current_dir(dir.path())
+        .env("USER", "carol")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "main.py failed: {}", String::from_utf8_lossy(&output.stderr));
+    let (version, book) = db::decode(&fs::read(dir.path().join("addressbook.db")).unwrap()).unwrap();
+
+    assert_eq!(db::CURRENT_VERSION, version);
+    let golden = golden_book();
+    assert_eq!(golden.changes, book.changes[..3]);
+    let change = &book.changes[3];
+    assert_eq!((4, "carol", "Alice Smith", 0), (change.id, change.author.as_str(), change.name.as_str(), change.undoes));
+    assert_eq!(golden.contacts.get("Alice Smith"), change.before.as_ref());
+    assert_eq!(book.contacts.get("Alice Smith"), change.after.as_ref());
+    let Some(Kind::Person(alice)) = &book.contacts["Alice Smith"].kind else { panic!("Alice is a person") };
+    assert_eq!(5, alice.phones.len());
+}
@@ -0,0 +1,162 @@
+// Shell completion and the man page.
+//
+// Completion is dynamic: the script `completions` prints calls back into the
+// program (with $COMPLETE set, see `clap_complete::CompleteEnv` in main.rs)
+// on every Tab, so `--name` can offer the contacts of the book. The book is
+// the one `--db` names on the command line being completed, or addressbook.db.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::CommandFactory;
+use clap_complete::env::{Bash, EnvCompleter, Fish, Zsh};
+use clap_complete::CompletionCandidate;
+
+use crate::arguments::{Cli, ShellType};
+use crate::storage::Backend;
+use crate::{crypt, AddressBook};
+
+/// Environment variable that switches the program into completing.
+pub const COMPLETE_VAR: &str = "COMPLETE";
+const BIN: &str = "addressbook_1";
+
+/// Writes the script that registers the completions of `shell`.
+pub fn write_registration(shell: ShellType, out: &mut dyn Write) -> io::Result<()> {
+    let shell: &dyn EnvCompleter = match shell {
+        ShellType::Bash => &Bash,
+        ShellType::Zsh => &Zsh,
+        ShellType::Fish => &Fish,
+    };
+    shell.write_registration(COMPLETE_VAR, BIN, BIN, BIN, out)
+}
+
+/// Writes the man page of the program, or with `dir` one page per
+/// subcommand into `dir`.
+pub fn write_man(dir: Option<&Path>, out: &mut dyn Write) -> io::Result<()> {
+    let cmd = Cli::command().name(BIN);
+    match dir {
+        Some(dir) => clap_mangen::generate_to(cmd, dir),
+        None => clap_mangen::Man::new(cmd).render(out),
+    }
+}
+
+/// Completes `--name` with the contacts whose name starts with `current`.
+pub fn names(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(prefix) = current.to_str() else {
+        return Vec::new();
+    };
+    contact_names(&db_path(env::args_os()), prefix)
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+// The names in the book at `path` starting with `prefix`. The book is only
+// read: an older one is not upgraded, and nothing is ever written. Any
+// problem gives no names, as a completion cannot report errors or ask for
+// anything. So does an encrypted book, whose key would take an Argon2
+// derivation on every Tab.
+fn contact_names(path: &Path, prefix: &str) -> Vec<String> {
+    // Opening a missing SQLite book would create it
+    if !path.exists() || !matches!(crypt::is_encrypted_file(path), Ok(false)) {
+        return Vec::new();
+    }
+    let book = Backend::detect(path).and_then(|backend| AddressBook::open_read_only(path, backend, None));
+    match book.and_then(|book| book.iter().map(|contacts| contacts.collect::<Vec<_>>())) {
+        Ok(contacts) => contacts.into_iter().map(|(name, _)| name).filter(|name| name.starts_with(prefix)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// The value of the last `--db` in `args`, or the default book.
+fn db_path(args: impl IntoIterator<Item = OsString>) -> PathBuf {
+    let mut path = PathBuf::from("addressbook.db");
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--db" {
+            path = args.next().map(PathBuf::from).unwrap_or(path);
+        } else if let Some(value) = arg.to_str().and_then(|arg| arg.strip_prefix("--db=")) {
+            path = PathBuf::from(value);
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Details;
+
+    #[test]
+    fn names_from_the_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.db");
+        let mut book = AddressBook::open(&path).unwrap();
+        for name in ["Bob Smith", "Bobby", "Alice"] {
+            book.upsert_person(name, Some("x@example.com"), None, &Details::default()).unwrap();
+        }
+        book.save().unwrap();
+
+        assert_eq!(vec!["Bob Smith", "Bobby"], contact_names(&path, "Bob"));
+        assert_eq!(3, contact_names(&path, "").len());
+        assert!(contact_names(&dir.path().join("missing.db"), "").is_empty());
+        assert!(!dir.path().join("missing.db").exists());
+    }
+
+    #[test]
+    fn names_leave_the_book_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.db");
+        let legacy = include_bytes!("../../addressbook_core/tests/fixtures/v0.db");
+        std::fs::write(&path, legacy).unwrap();
+
+        assert!(!contact_names(&path, "").is_empty());
+        assert_eq!(legacy.as_slice(), std::fs::read(&path).unwrap());
+
+        let mut book = AddressBook::open(&path).unwrap();
+        book.rekey(Some("correct horse")).unwrap();
+        book.save().unwrap();
+        assert!(contact_names(&path, "").is_empty());
+    }
+
+    #[test]
+    fn db_from_the_command_line() {
+        let args = |line: &str| line.split(' ').map(OsString::from).collect::<Vec<_>>();
+
+        assert_eq!(PathBuf::from("addressbook.db"), db_path(args("addressbook_1 -- addressbook_1 add --name B")));
+        assert_eq!(PathBuf::from("a.db"), db_path(args("addressbook_1 -- addressbook_1 --db a.db add --name B")));
+        assert_eq!(PathBuf::from("b.db"), db_path(args("addressbook_1 -- addressbook_1 history --db=b.db --name")));
+    }
+
+    #[test]
+    fn name_arguments_complete_names() {
+        let mut cmd = Cli::command();
+        cmd.build();
+        for path in [["add", "name"], ["history", "name"]] {
+            let sub = cmd.find_subcommand(path[0]).unwrap();
+            let arg = sub.get_arguments().find(|arg| arg.get_id() == path[1]).unwrap();
+            assert!(arg.get::<clap_complete::ArgValueCompleter>().is_some(), "{path:?}");
+        }
+    }
+
+    #[test]
+    fn scripts_and_man_page() {
+        for shell in [ShellType::Bash, ShellType::Zsh, ShellType::Fish] {
+            let mut script = Vec::new();
+            write_registration(shell, &mut script).unwrap();
+            let script = String::from_utf8(script).unwrap();
+            assert!(script.contains(COMPLETE_VAR) && script.contains(BIN), "{script}");
+        }
+
+        let mut page = Vec::new();
+        write_man(None, &mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.contains(".TH addressbook_1 1"), "{page}");
+
+        let dir = tempfile::tempdir().unwrap();
+        write_man(Some(dir.path()), &mut io::sink()).unwrap();
+        assert!(dir.path().join("addressbook_1-add.1").exists());
+    }
+}
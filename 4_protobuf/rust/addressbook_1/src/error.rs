@@ -0,0 +1,96 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use addressbook_core::validate::ValidationError;
+use addressbook_core::CryptError;
+
+/// Errors of the shared address book, see `addressbook_core::AddressBookError`.
+pub use addressbook_core::AddressBookError as BookError;
+
+#[derive(Debug)]
+pub enum AddressBookError {
+    /// The address book itself failed: storage, validation, a missing contact…
+    Book(BookError),
+    /// The command cannot be used with `--server`.
+    Unsupported(&'static str),
+    /// Lines of a `batch` failed; without `--keep-going` nothing was saved.
+    BatchFailed { failed: usize, saved: bool },
+    /// The other side of a sync sent something unexpected.
+    SyncProtocol(String),
+    /// The gRPC connection to the server failed.
+    Transport(tonic::transport::Error),
+    /// The server rejected the request.
+    Rpc(Box<tonic::Status>),
+}
+
+impl fmt::Display for AddressBookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressBookError::Book(e) => e.fmt(f),
+            AddressBookError::Unsupported(command) => write!(f, "{command} is not supported with --server"),
+            AddressBookError::BatchFailed { failed, saved } => {
+                let lines = if *failed == 1 { "line" } else { "lines" };
+                let outcome = if *saved { "the other changes were saved" } else { "nothing was saved" };
+                write!(f, "{failed} {lines} failed, {outcome}")
+            }
+            AddressBookError::SyncProtocol(msg) => write!(f, "sync failed: {msg}"),
+            AddressBookError::Transport(e) => write!(f, "cannot reach the server: {e}"),
+            AddressBookError::Rpc(status) => write!(f, "server error: {}", status.message()),
+        }
+    }
+}
+
+impl Error for AddressBookError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            // The wrapper adds nothing to the message, so skip it
+            AddressBookError::Book(e) => e.source(),
+            AddressBookError::Transport(e) => Some(e),
+            AddressBookError::Rpc(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<BookError> for AddressBookError {
+    fn from(e: BookError) -> Self {
+        AddressBookError::Book(e)
+    }
+}
+
+impl From<io::Error> for AddressBookError {
+    fn from(e: io::Error) -> Self {
+        AddressBookError::Book(e.into())
+    }
+}
+
+impl From<prost::DecodeError> for AddressBookError {
+    fn from(e: prost::DecodeError) -> Self {
+        AddressBookError::Book(e.into())
+    }
+}
+
+impl From<ValidationError> for AddressBookError {
+    fn from(e: ValidationError) -> Self {
+        AddressBookError::Book(e.into())
+    }
+}
+
+impl From<CryptError> for AddressBookError {
+    fn from(e: CryptError) -> Self {
+        AddressBookError::Book(e.into())
+    }
+}
+
+impl From<tonic::transport::Error> for AddressBookError {
+    fn from(e: tonic::transport::Error) -> Self {
+        AddressBookError::Transport(e)
+    }
+}
+
+impl From<tonic::Status> for AddressBookError {
+    fn from(e: tonic::Status) -> Self {
+        AddressBookError::Rpc(Box::new(e))
+    }
+}
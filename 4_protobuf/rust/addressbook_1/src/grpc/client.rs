@@ -0,0 +1,89 @@
+use tokio::runtime::Runtime;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Channel;
+use tonic::Request;
+
+use crate::arguments::{Commands, KindType};
+use crate::error::AddressBookError;
+use crate::grpc::AUTHOR_KEY;
+use crate::pb;
+use crate::pb::contact_service_client::ContactServiceClient;
+use crate::pb::upsert_request::Kind as UpsertKind;
+use crate::output::Output;
+use crate::{has_tag, print_contacts, str_to_department, str_to_phone_type, validate};
+
+/// Runs a CLI command against the server at `url` instead of a local file.
+/// `author` is sent along with changes for the server's change log.
+pub fn run(url: &str, author: &str, command: &Commands, output: Output) -> Result<(), AddressBookError> {
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let mut client = ContactServiceClient::connect(url.to_string()).await?;
+        run_command(&mut client, author, command, output).await
+    })
+}
+
+fn with_author<T>(message: T, author: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    // Names that are not valid header values are left to the server's default
+    if let Ok(author) = MetadataValue::try_from(author) {
+        request.metadata_mut().insert(AUTHOR_KEY, author);
+    }
+    request
+}
+
+async fn run_command(
+    client: &mut ContactServiceClient<Channel>,
+    author: &str,
+    command: &Commands,
+    output: Output,
+) -> Result<(), AddressBookError> {
+    match command {
+        Commands::Add(x) => {
+            let kind = match x.kind {
+                KindType::Cie | KindType::Company => UpsertKind::Company(pb::UpsertCompany {
+                    email: x.email.clone(),
+                    phone: x.phone.clone(),
+                    department: str_to_department(x.dep.clone()).into(),
+                }),
+                KindType::Per | KindType::Person => UpsertKind::Person(pb::UpsertPerson {
+                    email: x.email.clone(),
+                    phone: x.phone.clone(),
+                    r#type: str_to_phone_type(x.r#type.clone()).into(),
+                    company: x.company.clone(),
+                }),
+            };
+            client
+                .upsert(with_author(
+                    pb::UpsertRequest {
+                        name: x.name.clone(),
+                        region: x.region.clone(),
+                        kind: Some(kind),
+                        tags: x.tags.clone(),
+                        notes: x.notes.clone(),
+                        address: x.address.as_deref().map(validate::address).transpose()?,
+                    },
+                    author,
+                ))
+                .await?;
+        }
+        Commands::List(x) if x.as_of.is_some() => return Err(AddressBookError::Unsupported("list --as-of")),
+        Commands::List(x) => {
+            let book = client.list(pb::ListRequest { redact: x.redact }).await?.into_inner();
+            let mut contacts: Vec<(String, pb::Contact)> =
+                book.contacts.into_iter().filter(|(_, c)| has_tag(c, x.tag.as_deref())).collect();
+            contacts.sort_by(|a, b| a.0.cmp(&b.0));
+            print_contacts(contacts, false, &output.with_format(x.format));
+        }
+        Commands::MigrateStorage(_) => return Err(AddressBookError::Unsupported("migrate-storage")),
+        Commands::History(_) => return Err(AddressBookError::Unsupported("history")),
+        Commands::Undo => return Err(AddressBookError::Unsupported("undo")),
+        Commands::Rekey(_) => return Err(AddressBookError::Unsupported("rekey")),
+        Commands::Dedupe(_) => return Err(AddressBookError::Unsupported("dedupe")),
+        Commands::Sync(_) => return Err(AddressBookError::Unsupported("sync")),
+        Commands::Tui(_) => return Err(AddressBookError::Unsupported("tui")),
+        Commands::Serve(_) => return Err(AddressBookError::Unsupported("serve")),
+        Commands::Batch(_) => return Err(AddressBookError::Unsupported("batch")),
+        Commands::Completions(_) | Commands::Man(_) => unreachable!("handled before connecting"),
+    }
+    Ok(())
+}
@@ -0,0 +1,135 @@
+// gRPC access to an address book, see addressbook_service.proto.
+
+pub mod client;
+mod server;
+
+pub use server::{serve, ContactServer};
+
+/// Request metadata naming who made a change, for the server's change log.
+pub const AUTHOR_KEY: &str = "x-author";
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tokio_stream::StreamExt;
+    use tonic::transport::Channel;
+    use tonic::Code;
+
+    use super::*;
+    use crate::pb;
+    use crate::pb::contact::Kind;
+    use crate::pb::contact_service_client::ContactServiceClient;
+    use crate::pb::upsert_request::Kind as UpsertKind;
+    use crate::AddressBook;
+
+    // Starts a server on a free local port and connects a client to it.
+    async fn start(dir: &tempfile::TempDir) -> ContactServiceClient<Channel> {
+        let book = AddressBook::open(dir.path().join("addressbook.db")).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(ContactServer::new(book).into_service())
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        ContactServiceClient::connect(format!("http://{addr}")).await.unwrap()
+    }
+
+    fn upsert_person(name: &str, email: &str, phone: &str) -> pb::UpsertRequest {
+        pb::UpsertRequest {
+            name: name.to_string(),
+            region: String::new(),
+            kind: Some(UpsertKind::Person(pb::UpsertPerson {
+                email: Some(email.to_string()),
+                phone: Some(phone.to_string()),
+                r#type: pb::person::phone_number::Type::Home.into(),
+                company: None,
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn upsert_get_list_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut client = start(&dir).await;
+
+        client.upsert(upsert_person("Bob", "bob@example.com", "201-555-0123")).await.unwrap();
+
+        let contact = client.get(pb::GetRequest { name: "Bob".to_string() }).await.unwrap().into_inner();
+        match contact.kind {
+            Some(Kind::Person(p)) => assert_eq!("+12015550123", p.phones[0].number),
+            _ => panic!("expected a person"),
+        }
+
+        let book = client.list(pb::ListRequest { redact: true }).await.unwrap().into_inner();
+        match &book.contacts["Bob"].kind {
+            Some(Kind::Person(p)) => assert_eq!("***************", p.emails[0]),
+            _ => panic!("expected a person"),
+        }
+
+        let response = client.delete(pb::DeleteRequest { name: "Bob".to_string() }).await.unwrap();
+        assert!(response.into_inner().deleted);
+        let status = client.get(pb::GetRequest { name: "Bob".to_string() }).await.unwrap_err();
+        assert_eq!(Code::NotFound, status.code());
+    }
+
+    #[tokio::test]
+    async fn invalid_upsert() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut client = start(&dir).await;
+
+        let status = client.upsert(upsert_person("Bob", "not an email", "201-555-0123")).await.unwrap_err();
+
+        assert_eq!(Code::InvalidArgument, status.code());
+    }
+
+    #[tokio::test]
+    async fn changes_are_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut client = start(&dir).await;
+
+        let mut request = tonic::Request::new(upsert_person("Bob", "bob@example.com", "201-555-0123"));
+        request.metadata_mut().insert(AUTHOR_KEY, "alice".parse().unwrap());
+        client.upsert(request).await.unwrap();
+
+        let book = AddressBook::open(dir.path().join("addressbook.db")).unwrap();
+        assert!(book.get("Bob").unwrap().is_some());
+        assert_eq!("alice", book.history("Bob").unwrap()[0].author);
+    }
+
+    #[tokio::test]
+    async fn failed_save_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut client = start(&dir).await;
+        client.upsert(upsert_person("Bob", "bob@example.com", "201-555-0123")).await.unwrap();
+        // Nothing can be saved from now on
+        std::fs::remove_dir_all(dir.path()).unwrap();
+
+        let status = client.upsert(upsert_person("Carol", "carol@example.com", "201-555-0124")).await.unwrap_err();
+        assert_eq!(Code::Internal, status.code());
+        let status = client.delete(pb::DeleteRequest { name: "Bob".to_string() }).await.unwrap_err();
+        assert_eq!(Code::Internal, status.code());
+
+        let status = client.get(pb::GetRequest { name: "Carol".to_string() }).await.unwrap_err();
+        assert_eq!(Code::NotFound, status.code());
+        client.get(pb::GetRequest { name: "Bob".to_string() }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn watch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut client = start(&dir).await;
+        let mut events = client.watch(pb::WatchRequest {}).await.unwrap().into_inner();
+
+        client.upsert(upsert_person("Bob", "bob@example.com", "201-555-0123")).await.unwrap();
+        client.delete(pb::DeleteRequest { name: "Bob".to_string() }).await.unwrap();
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(pb::contact_event::Type::Upserted, event.r#type());
+        assert_eq!("Bob", event.name);
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(pb::contact_event::Type::Deleted, event.r#type());
+    }
+}
@@ -0,0 +1,219 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+
+use tokio::sync::broadcast;
+use tokio::task;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+use crate::error::{AddressBookError, BookError};
+use crate::grpc::AUTHOR_KEY;
+use crate::pb;
+use crate::pb::contact_event::Type as EventType;
+use crate::pb::contact_service_server::{ContactService, ContactServiceServer};
+use crate::pb::upsert_request::Kind as UpsertKind;
+use crate::redact;
+use crate::validate;
+use crate::{AddressBook, Details};
+
+// Events a slow `Watch` subscriber can fall behind before it gets an error.
+const EVENT_BUFFER: usize = 64;
+
+/// `ContactService` implementation serving one `AddressBook`.
+/// Every change is saved before the call returns.
+pub struct ContactServer {
+    book: Arc<Mutex<AddressBook>>,
+    events: broadcast::Sender<pb::ContactEvent>,
+    region: String,
+    author: String,
+}
+
+impl ContactServer {
+    pub fn new(book: AddressBook) -> ContactServer {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        ContactServer {
+            author: book.author().to_string(),
+            book: Arc::new(Mutex::new(book)),
+            events,
+            region: validate::DEFAULT_REGION.to_string(),
+        }
+    }
+
+    /// Sets the region used when an `UpsertRequest` does not give one.
+    pub fn set_region(&mut self, region: &str) {
+        self.region = region.to_string();
+    }
+
+    pub fn into_service(self) -> ContactServiceServer<ContactServer> {
+        ContactServiceServer::new(self)
+    }
+
+    // Runs `f` on the book, then saves what it changed. Both happen on a
+    // blocking thread, as they read and write the file. Changes that fail or
+    // cannot be saved are discarded, so the book served stays the one on disk.
+    async fn with_book<T, F>(&self, f: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut AddressBook) -> Result<T, BookError> + Send + 'static,
+    {
+        let book = Arc::clone(&self.book);
+        task::spawn_blocking(move || {
+            // A poisoned lock is recovered: the book has no invariant a panicking call could break.
+            let mut book = book.lock().unwrap_or_else(PoisonError::into_inner);
+            let result = f(&mut book).and_then(|value| book.save().map(|()| value));
+            if result.is_err() {
+                book.discard()?;
+            }
+            result
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(status)
+    }
+
+    // The author sent by the client, or the one the book was opened with.
+    fn author<'a, T>(&'a self, request: &'a Request<T>) -> &'a str {
+        request
+            .metadata()
+            .get(AUTHOR_KEY)
+            .and_then(|author| author.to_str().ok())
+            .unwrap_or(&self.author)
+    }
+
+    fn notify(&self, r#type: EventType, name: String, contact: pb::Contact) {
+        // No subscribers is not an error
+        let _ = self.events.send(pb::ContactEvent {
+            r#type: r#type.into(),
+            name,
+            contact: Some(contact),
+        });
+    }
+}
+
+// A `From` impl is not allowed: neither type is defined in this crate.
+fn status(e: BookError) -> Status {
+    match e {
+        BookError::Validation(_) | BookError::MissingArgument(_) | BookError::InvalidArgument { .. } => {
+            Status::invalid_argument(e.to_string())
+        }
+        BookError::KindConflict { .. } => Status::failed_precondition(e.to_string()),
+        BookError::NotFound(_) => Status::not_found(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+/// The events a `Watch` subscriber gets, ending in data loss if it falls behind.
+struct Events(BroadcastStream<pb::ContactEvent>);
+
+impl Stream for Events {
+    type Item = Result<pb::ContactEvent, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match Pin::new(&mut self.0).poll_next(cx) {
+            Poll::Ready(Some(Ok(event))) => Poll::Ready(Some(Ok(event))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(Status::data_loss(e.to_string())))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ContactService for ContactServer {
+    async fn get(&self, request: Request<pb::GetRequest>) -> Result<Response<pb::Contact>, Status> {
+        let name = request.into_inner().name;
+        let get = name.clone();
+        match self.with_book(move |book| book.get(&get)).await? {
+            Some(contact) => Ok(Response::new(contact)),
+            None => Err(Status::not_found(format!("no contact named {name}"))),
+        }
+    }
+
+    async fn list(&self, request: Request<pb::ListRequest>) -> Result<Response<pb::AddressBook>, Status> {
+        let book = pb::AddressBook {
+            contacts: self.with_book(|book| Ok(book.iter()?.collect())).await?,
+            ..Default::default()
+        };
+        if request.into_inner().redact {
+            Ok(Response::new(redact::redact(&book)))
+        } else {
+            Ok(Response::new(book))
+        }
+    }
+
+    async fn upsert(&self, request: Request<pb::UpsertRequest>) -> Result<Response<pb::Contact>, Status> {
+        let author = self.author(&request).to_string();
+        let request = request.into_inner();
+        let region = if request.region.is_empty() { self.region.clone() } else { request.region.clone() };
+        let name = request.name.clone();
+        let contact = self
+            .with_book(move |book| {
+                book.set_author(&author);
+                book.set_region(&region);
+                let details = Details {
+                    tags: request.tags,
+                    notes: request.notes,
+                    address: request.address,
+                    company: None,
+                };
+                match request.kind {
+                    Some(UpsertKind::Person(p)) => book.upsert_person(
+                        &request.name,
+                        p.email.as_deref(),
+                        p.phone.as_deref().map(|n| (n, p.r#type())),
+                        &Details {
+                            company: p.company.clone(),
+                            ..details
+                        },
+                    ),
+                    Some(UpsertKind::Company(c)) => book.upsert_company(
+                        &request.name,
+                        c.email.as_deref().map(|e| (e, c.department())),
+                        c.phone.as_deref().map(|n| (n, c.department())),
+                        &details,
+                    ),
+                    None => Err(BookError::MissingArgument("kind")),
+                }
+            })
+            .await?;
+        self.notify(EventType::Upserted, name, contact.clone());
+        Ok(Response::new(contact))
+    }
+
+    async fn delete(&self, request: Request<pb::DeleteRequest>) -> Result<Response<pb::DeleteResponse>, Status> {
+        let author = self.author(&request).to_string();
+        let name = request.into_inner().name;
+        let remove = name.clone();
+        let removed = self
+            .with_book(move |book| {
+                book.set_author(&author);
+                book.remove(&remove)
+            })
+            .await?;
+        let deleted = removed.is_some();
+        if let Some(contact) = removed {
+            self.notify(EventType::Deleted, name, contact);
+        }
+        Ok(Response::new(pb::DeleteResponse { deleted }))
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<pb::ContactEvent, Status>> + Send>>;
+
+    async fn watch(&self, _request: Request<pb::WatchRequest>) -> Result<Response<Self::WatchStream>, Status> {
+        Ok(Response::new(Box::pin(Events(BroadcastStream::new(self.events.subscribe())))))
+    }
+}
+
+/// Serves `book` on `addr` until the process is stopped.
+pub async fn serve(book: AddressBook, addr: SocketAddr, region: &str) -> Result<(), AddressBookError> {
+    let mut server = ContactServer::new(book);
+    server.set_region(region);
+    tonic::transport::Server::builder()
+        .add_service(server.into_service())
+        .serve(addr)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,508 @@
+// HTTP JSON access to an address book, for the `serve` subcommand.
+//
+//     GET    /contacts?tag=T   every contact (with tag T), as `list --format json` prints them
+//     GET    /contacts/{name}  one contact
+//     PUT    /contacts/{name}  creates or replaces a contact
+//     DELETE /contacts/{name}  deletes a contact
+//
+// Contacts are the JSON objects of `Output::value`; a PUT body needs "kind"
+// and ignores "name" and "last_updated". A contact's ETag comes from its
+// `last_updated`. Changing or deleting an existing contact needs
+// `If-Match` with its current ETag: without one the answer is 428, with a
+// stale one 412, so two clients cannot overwrite each other's changes.
+// `If-None-Match: *` makes a PUT create only.
+//
+// With tokens configured, requests need `Authorization: Bearer TOKEN`.
+// Read-only tokens can only GET, and see redacted contacts with `redact`.
+// Without any token everybody can read and change everything.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use axum::extract::{Path, Query, State};
+use axum::http::header::{AUTHORIZATION, ETAG, IF_MATCH, IF_NONE_MATCH, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use crate::arguments::{FormatType, TimeArgs, TimeType};
+use crate::error::{AddressBookError, BookError};
+use crate::grpc::AUTHOR_KEY;
+use crate::output::Output;
+use crate::pb;
+use crate::pb::company::Department;
+use crate::pb::contact::Kind;
+use crate::pb::person::phone_number::Type;
+use crate::{has_tag, redact, AddressBook};
+
+/// Who may use the API.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    /// Tokens that can read and change contacts.
+    pub tokens: Vec<String>,
+    /// Tokens that can only read contacts.
+    pub read_tokens: Vec<String>,
+    /// Redact the contacts read-only tokens see.
+    pub redact: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    ReadWrite,
+    ReadOnly,
+}
+
+impl Access {
+    fn role(&self, headers: &HeaderMap) -> Result<Role, ApiError> {
+        if self.tokens.is_empty() && self.read_tokens.is_empty() {
+            return Ok(Role::ReadWrite);
+        }
+        let token = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if self.tokens.iter().any(|t| t == token) => Ok(Role::ReadWrite),
+            Some(token) if self.read_tokens.iter().any(|t| t == token) => Ok(Role::ReadOnly),
+            _ => Err(ApiError::new(StatusCode::UNAUTHORIZED, "missing or unknown token")),
+        }
+    }
+
+    fn can_write(&self, headers: &HeaderMap) -> Result<(), ApiError> {
+        match self.role(headers)? {
+            Role::ReadWrite => Ok(()),
+            Role::ReadOnly => Err(ApiError::new(StatusCode::FORBIDDEN, "this token can only read contacts")),
+        }
+    }
+}
+
+struct Api {
+    book: Mutex<AddressBook>,
+    access: Access,
+    author: String,
+    output: Output,
+}
+
+impl Api {
+    // A poisoned lock is recovered: the book has no invariant a panicking call could break.
+    fn lock(&self) -> MutexGuard<'_, AddressBook> {
+        self.book.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // The contact as JSON, redacted if the caller only reads and `redact` is set.
+    fn contact(&self, role: Role, name: &str, contact: &pb::Contact) -> Value {
+        if role == Role::ReadOnly && self.access.redact {
+            self.output.value(name, &redact::redact(contact))
+        } else {
+            self.output.value(name, contact)
+        }
+    }
+}
+
+/// An error answer: the status and `{"error": message}`.
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> ApiError {
+        ApiError { status, message: message.into() }
+    }
+}
+
+impl From<BookError> for ApiError {
+    fn from(e: BookError) -> Self {
+        let status = match e {
+            BookError::Validation(_) | BookError::MissingArgument(_) | BookError::InvalidArgument { .. } => {
+                StatusCode::BAD_REQUEST
+            }
+            BookError::KindConflict { .. } => StatusCode::CONFLICT,
+            BookError::NotFound(_) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, Json(json!({ "error": self.message }))).into_response();
+        if self.status == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+/// The API serving `book`. Changes are saved before the answer is sent.
+pub fn router(book: AddressBook, access: Access) -> Router {
+    let utc = TimeArgs { time: TimeType::Rfc3339, tz: "UTC".to_string() };
+    let api = Api {
+        author: book.author().to_string(),
+        book: Mutex::new(book),
+        access,
+        output: Output::new(FormatType::Json, &utc).expect("UTC is a known time zone"),
+    };
+    Router::new()
+        .route("/contacts", get(list_contacts))
+        .route("/contacts/:name", get(get_contact).put(put_contact).delete(delete_contact))
+        .with_state(Arc::new(api))
+}
+
+/// Serves `book` on `listener` until the process is stopped.
+pub async fn serve(book: AddressBook, listener: TcpListener, access: Access) -> Result<(), AddressBookError> {
+    axum::serve(listener, router(book, access)).await?;
+    Ok(())
+}
+
+async fn list_contacts(
+    State(api): State<Arc<Api>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    let role = api.access.role(&headers)?;
+    let contacts: Vec<(String, pb::Contact)> = api.lock().iter()?.collect();
+    let values = contacts
+        .iter()
+        .filter(|(_, contact)| has_tag(contact, query.get("tag").map(String::as_str)))
+        .map(|(name, contact)| api.contact(role, name, contact))
+        .collect();
+    Ok(Json(Value::Array(values)))
+}
+
+async fn get_contact(
+    State(api): State<Arc<Api>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let role = api.access.role(&headers)?;
+    let contact = api.lock().get(&name)?.ok_or_else(|| BookError::NotFound(name.clone()))?;
+    Ok(with_etag(&contact, Json(api.contact(role, &name, &contact))))
+}
+
+async fn put_contact(
+    State(api): State<Arc<Api>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, ApiError> {
+    api.access.can_write(&headers)?;
+    let contact = contact_from_json(&body).map_err(|message| ApiError::new(StatusCode::BAD_REQUEST, message))?;
+    let mut book = api.lock();
+    let current = book.get(&name)?;
+    check_version(&headers, current.as_ref())?;
+    book.set_author(author(&headers).unwrap_or(&api.author));
+    let contact = book.replace(&name, contact)?;
+    book.save()?;
+    drop(book);
+
+    let status = if current.is_some() { StatusCode::OK } else { StatusCode::CREATED };
+    Ok(with_etag(&contact, (status, Json(api.contact(Role::ReadWrite, &name, &contact)))))
+}
+
+async fn delete_contact(
+    State(api): State<Arc<Api>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    api.access.can_write(&headers)?;
+    let mut book = api.lock();
+    let current = book.get(&name)?.ok_or_else(|| BookError::NotFound(name.clone()))?;
+    check_version(&headers, Some(&current))?;
+    book.set_author(author(&headers).unwrap_or(&api.author));
+    book.remove(&name)?;
+    book.save()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn author(headers: &HeaderMap) -> Option<&str> {
+    headers.get(AUTHOR_KEY).and_then(|value| value.to_str().ok())
+}
+
+/// The version of a contact in ETag form, e.g. "\"1700000000.123456789\"".
+pub fn etag(contact: &pb::Contact) -> String {
+    let at = contact.last_updated.unwrap_or_default();
+    format!("\"{}.{:09}\"", at.seconds, at.nanos)
+}
+
+fn with_etag(contact: &pb::Contact, response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    let etag = HeaderValue::from_str(&etag(contact)).expect("an ETag is ASCII");
+    response.headers_mut().insert(ETAG, etag);
+    response
+}
+
+// Checks the request's If-Match and If-None-Match against `current`, the
+// contact as it is now (`None` if there is none).
+fn check_version(headers: &HeaderMap, current: Option<&pb::Contact>) -> Result<(), ApiError> {
+    let header = |name| headers.get(name).and_then(|value: &HeaderValue| value.to_str().ok());
+    let matches = |tags: &str, etag: &str| tags.split(',').any(|tag| tag.trim() == "*" || tag.trim() == etag);
+    match (current, header(IF_MATCH)) {
+        (Some(_), _) if header(IF_NONE_MATCH).is_some_and(|tags| tags.trim() == "*") => {
+            Err(ApiError::new(StatusCode::PRECONDITION_FAILED, "the contact already exists"))
+        }
+        (Some(_), None) => Err(ApiError::new(
+            StatusCode::PRECONDITION_REQUIRED,
+            "send If-Match with the contact's ETag to change it",
+        )),
+        (Some(contact), Some(tags)) if !matches(tags, &etag(contact)) => Err(ApiError::new(
+            StatusCode::PRECONDITION_FAILED,
+            "the contact was changed since it was read",
+        )),
+        (None, Some(_)) => Err(ApiError::new(StatusCode::PRECONDITION_FAILED, "the contact does not exist")),
+        _ => Ok(()),
+    }
+}
+
+// The inverse of `Output::value`: fields other than "kind" may be missing or null.
+pub(crate) fn contact_from_json(value: &Value) -> Result<pb::Contact, String> {
+    if !value.is_object() {
+        return Err("the body must be a JSON object".to_string());
+    }
+    let kind = match string(value, "kind")?.as_str() {
+        "person" => Kind::Person(pb::Person {
+            emails: list(value, "emails")?.iter().map(|email| text(email, "emails")).collect::<Result<_, _>>()?,
+            phones: list(value, "phones")?
+                .iter()
+                .map(|pn| {
+                    Ok(pb::person::PhoneNumber {
+                        number: string(pn, "number")?,
+                        r#type: enum_value(pn, "type", Type::from_str_name, "TYPE_")?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            company: string(value, "company")?,
+            ..Default::default()
+        }),
+        "company" => Kind::Company(pb::Company {
+            emails: list(value, "emails")?
+                .iter()
+                .map(|em| {
+                    Ok(pb::company::EmailAddress {
+                        email: string(em, "email")?,
+                        department: enum_value(em, "department", Department::from_str_name, "DEPARTMENT_")?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            phones: list(value, "phones")?
+                .iter()
+                .map(|pn| {
+                    Ok(pb::company::PhoneNumber {
+                        number: string(pn, "number")?,
+                        department: enum_value(pn, "department", Department::from_str_name, "DEPARTMENT_")?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        }),
+        "" => return Err("missing \"kind\": \"person\" or \"company\"".to_string()),
+        other => return Err(format!("unknown kind {other:?}: expected \"person\" or \"company\"")),
+    };
+    Ok(pb::Contact {
+        last_updated: None,
+        kind: Some(kind),
+        tags: list(value, "tags")?.iter().map(|tag| text(tag, "tags")).collect::<Result<_, _>>()?,
+        notes: string(value, "notes")?,
+        addresses: list(value, "addresses")?
+            .iter()
+            .map(|a| {
+                Ok(pb::PostalAddress {
+                    street: string(a, "street")?,
+                    city: string(a, "city")?,
+                    region: string(a, "region")?,
+                    postal_code: string(a, "postal_code")?,
+                    country: string(a, "country")?,
+                })
+            })
+            .collect::<Result<_, String>>()?,
+    })
+}
+
+fn text(value: &Value, key: &str) -> Result<String, String> {
+    value.as_str().map(str::to_string).ok_or_else(|| format!("{key:?} must hold strings"))
+}
+
+// A string field; missing or null is empty.
+fn string(value: &Value, key: &str) -> Result<String, String> {
+    match value.get(key) {
+        None | Some(Value::Null) => Ok(String::new()),
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(_) => Err(format!("{key:?} must be a string")),
+    }
+}
+
+// A list field; missing or null is empty.
+fn list<'a>(value: &'a Value, key: &str) -> Result<&'a [Value], String> {
+    match value.get(key) {
+        None | Some(Value::Null) => Ok(&[]),
+        Some(Value::Array(values)) => Ok(values),
+        Some(_) => Err(format!("{key:?} must be a list")),
+    }
+}
+
+// An enum field written as `Output` writes it, e.g. "home" for TYPE_HOME.
+fn enum_value<E: Into<i32>>(
+    value: &Value,
+    key: &str,
+    from_name: fn(&str) -> Option<E>,
+    prefix: &str,
+) -> Result<i32, String> {
+    let name = string(value, key)?;
+    if name.is_empty() {
+        return Ok(0);
+    }
+    from_name(&format!("{prefix}{}", name.to_uppercase()))
+        .map(Into::into)
+        .ok_or_else(|| format!("unknown {key} {name:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+
+    struct Reply {
+        status: u16,
+        etag: Option<String>,
+        body: Value,
+    }
+
+    // Starts a server on a free local port and returns its address.
+    async fn start(dir: &tempfile::TempDir, access: Access) -> String {
+        let book = AddressBook::open(dir.path().join("addressbook.db")).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(book, listener, access));
+        addr
+    }
+
+    // One HTTP/1.1 request on its own connection.
+    async fn request(addr: &str, method: &str, path: &str, headers: &[(&str, &str)], body: Option<Value>) -> Reply {
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let mut request = format!("{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n");
+        for (name, value) in headers {
+            request += &format!("{name}: {value}\r\n");
+        }
+        request += &format!("Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}", body.len());
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let mut lines = head.lines();
+        let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+        let etag = lines
+            .filter_map(|line| line.split_once(": "))
+            .find(|(name, _)| name.eq_ignore_ascii_case("etag"))
+            .map(|(_, value)| value.to_string());
+        let body = if body.is_empty() { Value::Null } else { serde_json::from_str(body).unwrap() };
+        Reply { status, etag, body }
+    }
+
+    fn bob(email: &str) -> Value {
+        json!({
+            "kind": "person",
+            "emails": [email],
+            "phones": [{ "number": "201-555-0123", "type": "home" }],
+            "tags": ["friends"],
+        })
+    }
+
+    #[tokio::test]
+    async fn crud_with_etags() {
+        let dir = tempfile::tempdir().unwrap();
+        let addr = start(&dir, Access::default()).await;
+
+        let created = request(&addr, "PUT", "/contacts/Bob", &[], Some(bob("bob@example.com"))).await;
+        assert_eq!(201, created.status);
+        assert_eq!("+12015550123", created.body["phones"][0]["number"]);
+        let etag = created.etag.unwrap();
+
+        let read = request(&addr, "GET", "/contacts/Bob", &[], None).await;
+        assert_eq!(200, read.status);
+        assert_eq!(Some(&etag), read.etag.as_ref());
+        assert_eq!("home", read.body["phones"][0]["type"]);
+
+        let update = Some(bob("bob@example.org"));
+        assert_eq!(428, request(&addr, "PUT", "/contacts/Bob", &[], update.clone()).await.status);
+        let only_create = [("If-None-Match", "*")];
+        assert_eq!(412, request(&addr, "PUT", "/contacts/Bob", &only_create, update.clone()).await.status);
+        let updated = request(&addr, "PUT", "/contacts/Bob", &[("If-Match", &etag)], update.clone()).await;
+        assert_eq!(200, updated.status);
+        assert_eq!("bob@example.org", updated.body["emails"][0]);
+
+        // The first ETag is stale now
+        let stale = [("If-Match", etag.as_str())];
+        assert_eq!(412, request(&addr, "PUT", "/contacts/Bob", &stale, update).await.status);
+        assert_eq!(412, request(&addr, "DELETE", "/contacts/Bob", &stale, None).await.status);
+        let current = [("If-Match", updated.etag.as_deref().unwrap())];
+        assert_eq!(204, request(&addr, "DELETE", "/contacts/Bob", &current, None).await.status);
+        assert_eq!(404, request(&addr, "GET", "/contacts/Bob", &[], None).await.status);
+
+        let book = AddressBook::open(dir.path().join("addressbook.db")).unwrap();
+        assert_eq!(3, book.history("Bob").unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn list_by_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let addr = start(&dir, Access::default()).await;
+        request(&addr, "PUT", "/contacts/Bob", &[], Some(bob("bob@example.com"))).await;
+        request(&addr, "PUT", "/contacts/Acme", &[], Some(json!({ "kind": "company" }))).await;
+
+        let all = request(&addr, "GET", "/contacts", &[], None).await.body;
+        let friends = request(&addr, "GET", "/contacts?tag=Friends", &[], None).await.body;
+
+        assert_eq!(json!(["Acme", "Bob"]), json!([all[0]["name"], all[1]["name"]]));
+        assert_eq!(1, friends.as_array().unwrap().len());
+        assert_eq!("Bob", friends[0]["name"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_contacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let addr = start(&dir, Access::default()).await;
+
+        for body in [bob("not an email"), json!({ "emails": [] }), json!({ "kind": "robot" }), json!([1])] {
+            let reply = request(&addr, "PUT", "/contacts/Bob", &[], Some(body)).await;
+            assert_eq!(400, reply.status);
+            assert!(reply.body["error"].is_string());
+        }
+        let unknown_type = json!({ "kind": "person", "phones": [{ "number": "201-555-0123", "type": "pager" }] });
+        assert_eq!(400, request(&addr, "PUT", "/contacts/Bob", &[], Some(unknown_type)).await.status);
+        assert_eq!(404, request(&addr, "GET", "/contacts/Bob", &[], None).await.status);
+    }
+
+    #[tokio::test]
+    async fn tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let access = Access {
+            tokens: vec!["writer".to_string()],
+            read_tokens: vec!["reader".to_string()],
+            redact: true,
+        };
+        let addr = start(&dir, access).await;
+        let writer = [("Authorization", "Bearer writer")];
+        let reader = [("Authorization", "Bearer reader")];
+
+        assert_eq!(401, request(&addr, "GET", "/contacts", &[], None).await.status);
+        let wrong = [("Authorization", "Bearer guess")];
+        assert_eq!(401, request(&addr, "GET", "/contacts", &wrong, None).await.status);
+        assert_eq!(201, request(&addr, "PUT", "/contacts/Bob", &writer, Some(bob("bob@example.com"))).await.status);
+        assert_eq!(403, request(&addr, "PUT", "/contacts/Al", &reader, Some(bob("al@example.com"))).await.status);
+        assert_eq!(403, request(&addr, "DELETE", "/contacts/Bob", &reader, None).await.status);
+
+        let redacted = request(&addr, "GET", "/contacts/Bob", &reader, None).await.body;
+        assert_eq!("***************", redacted["emails"][0]);
+        let listed = request(&addr, "GET", "/contacts", &reader, None).await.body;
+        assert_eq!("***************", listed[0]["emails"][0]);
+        let full = request(&addr, "GET", "/contacts/Bob", &writer, None).await.body;
+        assert_eq!("bob@example.com", full["emails"][0]);
+    }
+}
@@ -1,224 +1,394 @@
 pub mod arguments;
-mod pb {
+pub mod batch;
+#[cfg(test)]
+mod compat;
+pub mod complete;
+pub mod error;
+pub mod grpc;
+pub mod http;
+pub mod output;
+pub mod sync;
+pub mod tui;
+/// The messages of addressbook.proto come from `addressbook_core`; the
+/// service and sync messages are generated here.
+pub mod pb {
+    pub use addressbook_core::pb::*;
     include!(concat!(env!("OUT_DIR"), "/addressbook.ab.rs"));
 }
 
-use std::fs;
-use std::io::{BufReader, Read, Write, BufWriter};
-use std::error::Error;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+
+use addressbook_core::{crypt, default_author};
+use clap::ValueEnum;
+use tokio::runtime::Runtime;
 use pb::company::Department;
-use pb::contact::Kind;
 use pb::person::phone_number::Type;
-use prost::Message;
-use prost_types::Timestamp;
-use std::time;
-
-const DB_FILE_PATH: &str = "addressbook.db";
-
-use arguments::{Cli, Commands, DepType, KindType, PhoneType};
-
-pub fn run(config: Cli) -> Result<(), Box<dyn Error>> {
-    let mut f = open_db_file(DB_FILE_PATH);
-    match &config.command {
-        Some(Commands::Add(x)) => {
-            let name = x.name.as_str();
-            let email = match &x.email {
-                Some(em) => em.as_str(),
-                None => ""
+
+pub use addressbook_core::{dedupe, redact, storage, validate, AddressBook, CryptError, Details, Field};
+pub use error::AddressBookError;
+
+use arguments::{AddArgs, BackendType, Cli, Commands, DepType, FormatType, KindType, PhoneType};
+use output::Output;
+use storage::Backend;
+
+/// Environment variable holding the passphrase of an encrypted address book.
+pub const PASSPHRASE_VAR: &str = "ADDRESSBOOK_PASSPHRASE";
+/// Environment variable holding the new passphrase for `rekey`.
+pub const NEW_PASSPHRASE_VAR: &str = "ADDRESSBOOK_NEW_PASSPHRASE";
+
+pub fn run(config: Cli) -> Result<(), AddressBookError> {
+    let Some(command) = &config.command else {
+        return Err(error::BookError::MissingArgument("command").into());
+    };
+    // Neither needs the book
+    match command {
+        Commands::Completions(x) => return Ok(complete::write_registration(x.shell, &mut io::stdout())?),
+        Commands::Man(x) => return Ok(complete::write_man(x.out.as_deref(), &mut io::stdout())?),
+        _ => {}
+    }
+    let author = config.author.clone().unwrap_or_else(default_author);
+    let output = Output::new(FormatType::Plain, &config.time)?;
+    if let Some(url) = &config.server {
+        return grpc::client::run(url, &author, command, output);
+    }
+
+    let passphrase = read_passphrase(&config.db)?;
+    let mut book = match &passphrase {
+        Some(passphrase) => AddressBook::open_encrypted(&config.db, passphrase)?,
+        None => AddressBook::open(&config.db)?,
+    };
+    book.set_author(&author);
+    match command {
+        Commands::Add(x) => {
+            add(&mut book, x)?;
+            book.save()?;
+        }
+        Commands::List(x) => {
+            let contacts = match x.as_of {
+                Some(at) => book.as_of(to_timestamp(at))?,
+                None => book.iter()?.collect(),
             };
-            let phone = match &x.phone {
-                Some(em) => em.as_str(),
-                None => ""
+            let contacts = contacts.into_iter().filter(|(_, c)| has_tag(c, x.tag.as_deref()));
+            print_contacts(contacts, x.redact, &output.with_format(x.format));
+        }
+        Commands::MigrateStorage(x) => {
+            let backend = match &x.backend {
+                Some(b) => str_to_backend(b),
+                None => Backend::detect(&x.to)?,
             };
-            match x.kind {
-                KindType::Cie | KindType::Company => {
-                    add_company(&mut f, name, email, x.dep.clone(), phone, x.dep.clone());
-                    }
-                KindType::Per | KindType::Person => {
-                    add_person(&mut f, name, email, phone, x.r#type.clone());
-                    }
+            let count = migrate_storage(&book, passphrase.as_deref(), &x.to, backend, x.decrypt)?;
+            println!("copied {count} contacts to {} ({backend:?})", x.to.display());
+        }
+        Commands::History(x) => {
+            for change in book.history(&x.name)? {
+                println!("{}", describe_change(&change, &output));
+                if let Some(contact) = change.after.or(change.before) {
+                    print_contacts([(change.name, contact)], x.redact, &output);
+                }
             }
+        }
+        Commands::Undo => {
+            match book.undo()? {
+                Some(change) => println!("undone: {}", describe_change(&change, &output)),
+                None => println!("nothing to undo"),
             }
-        Some(Commands::List(x)) => {
-            list_contacts(&mut f, x.redact);
+            book.save()?;
+        }
+        Commands::Rekey(x) => {
+            if x.decrypt {
+                book.rekey(None)?;
+            } else {
+                book.rekey(Some(&read_new_passphrase()?))?;
+            }
+            book.save()?;
+        }
+        Commands::Dedupe(x) => {
+            let contacts: Vec<(String, pb::Contact)> = book.iter()?.collect();
+            let groups = dedupe::find(&contacts);
+            if groups.is_empty() {
+                println!("no duplicates found");
             }
-        None => Err("Command not found")?
+            for (i, group) in groups.iter().enumerate() {
+                print_group(i + 1, group, &contacts, &output);
+                if x.dry_run {
+                    continue;
+                }
+                let keep = if x.yes {
+                    dedupe::newest(&group.names, &contacts)
+                } else {
+                    ask_keep(&group.names)?
+                };
+                if let Some(keep) = keep {
+                    let others: Vec<&str> = group.names.iter().filter(|n| *n != keep).map(String::as_str).collect();
+                    book.merge(keep, &others)?;
+                    println!("merged into {keep}");
+                }
+            }
+            book.save()?;
+        }
+        Commands::Tui(x) => tui::App::new(book, x.redact)?.run()?,
+        Commands::Completions(_) | Commands::Man(_) => unreachable!("handled before opening the book"),
+        Commands::Batch(x) => {
+            let report = match x.file.as_deref().filter(|path| *path != Path::new("-")) {
+                Some(path) => batch::run(&mut book, BufReader::new(File::open(path)?), x.keep_going)?,
+                None => batch::run(&mut book, io::stdin().lock(), x.keep_going)?,
+            };
+            for (line, result) in &report.lines {
+                match result {
+                    Ok(done) => println!("{line}: {done}"),
+                    Err(e) => println!("{line}: error: {e}"),
+                }
+            }
+            let failed = report.failed();
+            if failed > 0 {
+                return Err(AddressBookError::BatchFailed { failed, saved: report.saved });
+            }
+            let changes = report.lines.len();
+            println!("saved {changes} {}", if changes == 1 { "change" } else { "changes" });
+        }
+        Commands::Serve(x) => {
+            book.set_region(&x.region);
+            let access = http::Access {
+                tokens: x.tokens.clone(),
+                read_tokens: x.read_tokens.clone(),
+                redact: x.redact,
+            };
+            Runtime::new()?.block_on(async {
+                let listener = tokio::net::TcpListener::bind(&x.listen).await?;
+                println!("serving on http://{}", listener.local_addr()?);
+                http::serve(book, listener, access).await
+            })?;
+        }
+        Commands::Sync(x) => {
+            if let Some(path) = &x.peer.with {
+                let passphrase = read_passphrase(path)?;
+                let mut other = AddressBook::open_with(path, Backend::detect(path)?, passphrase.as_deref())?;
+                other.set_author(&author);
+                print_sync(&sync::with_book(&mut book, &mut other)?, &path.display().to_string());
+            } else if let Some(addr) = &x.peer.connect {
+                let mut stream = TcpStream::connect(addr)?;
+                print_sync(&sync::over_stream(&mut book, &mut stream, true)?, addr);
+            } else if let Some(addr) = &x.peer.listen {
+                let listener = TcpListener::bind(addr)?;
+                println!("waiting for sync on {}", listener.local_addr()?);
+                for stream in listener.incoming() {
+                    let mut stream = stream?;
+                    let peer = stream.peer_addr()?.to_string();
+                    match sync::over_stream(&mut book, &mut stream, false) {
+                        Ok(plan) => print_sync(&plan, &peer),
+                        Err(e) if !x.once => eprintln!("Error: sync with {peer}: {e}"),
+                        Err(e) => return Err(e),
+                    }
+                    if x.once {
+                        break;
+                    }
+                }
+            }
+        }
     }
     Ok(())
 }
 
-fn open_db_file(file_path: &str) -> fs::File {
-    // File::open only for reading
-    fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true) // Creates if file does not exists
-        .open(file_path)
-        .unwrap()
+/// Runs `add` on `book`, without saving it.
+pub fn add(book: &mut AddressBook, x: &AddArgs) -> Result<pb::Contact, AddressBookError> {
+    book.set_region(&x.region);
+    let name = x.name.as_str();
+    let email = x.email.as_deref();
+    let phone = x.phone.as_deref();
+    let details = Details {
+        tags: x.tags.clone(),
+        notes: x.notes.clone(),
+        address: x.address.as_deref().map(validate::address).transpose()?,
+        company: x.company.clone(),
+    };
+    let contact = match x.kind {
+        KindType::Cie | KindType::Company => {
+            let dep = str_to_department(x.dep.clone());
+            book.upsert_company(name, email.map(|e| (e, dep)), phone.map(|p| (p, dep)), &details)?
+        }
+        KindType::Per | KindType::Person => {
+            let phone_type = str_to_phone_type(x.r#type.clone());
+            book.upsert_person(name, email, phone.map(|p| (p, phone_type)), &details)?
+        }
+    };
+    Ok(contact)
 }
 
-fn read_from_db(f: &mut fs::File) -> pb::AddressBook {
-    let mut buf_reader = BufReader::new(f);
-    let mut contents = Vec::new();
-    buf_reader.read_to_end(&mut contents).unwrap();
-    pb::AddressBook::decode(contents.as_slice()).unwrap()
+/// Copies `book`, opened with `passphrase` if it is encrypted, into the book
+/// at `to`. The copy of an encrypted book is encrypted with the same
+/// passphrase unless `decrypt` is set, which a SQLite copy requires.
+pub fn migrate_storage(
+    book: &AddressBook,
+    passphrase: Option<&str>,
+    to: &Path,
+    backend: Backend,
+    decrypt: bool,
+) -> Result<usize, AddressBookError> {
+    let passphrase = passphrase.filter(|_| !decrypt);
+    if passphrase.is_some() && backend == Backend::Sqlite {
+        return Err(CryptError::Unsupported.into());
+    }
+    let mut target = AddressBook::open_with(to, backend, passphrase)?;
+    if passphrase.is_some() {
+        target.rekey(passphrase)?;
+    }
+    Ok(storage::copy(book.storage(), target.storage_mut())?)
 }
 
-fn write_to_db(f: &mut fs::File, book: pb::AddressBook) {
-    let mut buf_writer = BufWriter::new(f);
-    let contents = book.encode_to_vec();
-    buf_writer.write_all(&contents).unwrap();
-    buf_writer.flush().unwrap();
-}
-fn str_to_phone_type(t: PhoneType) ->i32 {
-    match t {
-        PhoneType::Home => 2,
-        PhoneType::Mobile => 1,
-        PhoneType::Work => 3,
-        _ => 0,
+fn print_sync(plan: &sync::Plan, remote: &str) {
+    for (side, actions) in [("local", &plan.local), (remote, &plan.remote)] {
+        if actions.is_empty() {
+            println!("{side}: no changes");
+        } else {
+            let actions: Vec<String> = actions
+                .iter()
+                .map(|(name, action)| match action {
+                    sync::Action::Refused(reason) => format!("{action} {name} ({reason})"),
+                    _ => format!("{action} {name}"),
+                })
+                .collect();
+            println!("{side}: {}", actions.join(", "));
+        }
     }
 }
-fn str_to_department(t: DepType) -> i32 {
-    match t {
-        DepType::Hr => 1,
-        DepType::Cs => 2,
-        _ => 0
+
+fn print_group(number: usize, group: &dedupe::Group, contacts: &[(String, pb::Contact)], output: &Output) {
+    let reasons: Vec<String> = group.reasons.iter().map(|r| r.to_string()).collect();
+    println!("{number}. {}", reasons.join(", "));
+    for (i, name) in group.names.iter().enumerate() {
+        let updated = contacts.iter().find(|(n, _)| n == name).and_then(|(_, c)| c.last_updated);
+        println!("   [{}] {name} (last updated {})", i + 1, output.timestamp(updated));
     }
 }
 
-fn add_person(f: &mut fs::File, name: &str, email: &str, phone: &str, phone_type: PhoneType) {
-    let mut book = read_from_db(f);
-    let mut person: pb::Person;
-    if book.contacts.contains_key(name) {
-        // If Kind exists
-        let kind = book.contacts.get(name).unwrap().kind.clone().unwrap();
-        match kind {
-            pb::contact::Kind::Person(p) => { person = p },
-            pb::contact::Kind::Company(_) => {panic!("Company")},
+// Reads the number of the name to keep from stdin, so answers can also be piped in.
+// An empty answer or the end of input skips the group.
+fn ask_keep(names: &[String]) -> Result<Option<&String>, AddressBookError> {
+    let mut stdin = io::stdin().lock();
+    loop {
+        print!("Keep which name? [1-{}, Enter to skip]: ", names.len());
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if stdin.read_line(&mut answer)? == 0 || answer.trim().is_empty() {
+            return Ok(None);
+        }
+        match answer.trim().parse::<usize>() {
+            Ok(n) if (1..=names.len()).contains(&n) => return Ok(Some(&names[n - 1])),
+            _ => println!("please answer a number from 1 to {}", names.len()),
         }
     }
-    else {
-        person = pb::Person::default();
+}
+
+/// Passphrase for the book at `path` if it is encrypted: $ADDRESSBOOK_PASSPHRASE,
+/// or asked on the terminal. `None` for a plain or missing book, even with
+/// the variable set: only `rekey` encrypts.
+pub fn read_passphrase(path: &Path) -> Result<Option<String>, AddressBookError> {
+    if !crypt::is_encrypted_file(path)? {
+        return Ok(None);
+    }
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+        return Ok(Some(passphrase));
+    }
+    match rpassword::prompt_password("Passphrase: ") {
+        Ok(passphrase) => Ok(Some(passphrase)),
+        Err(_) => Err(CryptError::PassphraseRequired.into()),
     }
-    person.email = email.to_string();
-    let mut nb = pb::person::PhoneNumber::default();
-    nb.number = phone.to_string();
-    nb.r#type = str_to_phone_type(phone_type);
-    person.phones.push(nb);
+}
 
-    let mut contact = pb::Contact::default();
-    let mut update_ts = Timestamp::default();
-    let duration = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap();
-    update_ts.seconds = duration.as_secs() as i64;
-    update_ts.nanos = duration.subsec_nanos() as i32;
+fn read_new_passphrase() -> Result<String, AddressBookError> {
+    if let Ok(passphrase) = env::var(NEW_PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+    if rpassword::prompt_password("Repeat new passphrase: ")? != passphrase {
+        return Err(CryptError::Mismatch.into());
+    }
+    Ok(passphrase)
+}
 
-    contact.last_updated = Some(update_ts);
-    contact.kind = Some(pb::contact::Kind::Person(person));
-    book.contacts.insert(String::from(name), contact);
+// The clap value names are the names the core maps, e.g. "home" or "cs".
+fn str_to_phone_type(t: PhoneType) -> Type {
+    addressbook_core::str_to_phone_type(t.to_possible_value().expect("no value is skipped").get_name())
+}
+fn str_to_department(t: DepType) -> Department {
+    addressbook_core::str_to_department(t.to_possible_value().expect("no value is skipped").get_name())
+}
+fn str_to_backend(t: &BackendType) -> Backend {
+    match t {
+        BackendType::Proto => Backend::Proto,
+        BackendType::Sqlite => Backend::Sqlite,
+    }
+}
 
-    write_to_db(f, book);
+// Whether the contact has `tag` (ignoring case); any contact matches no tag.
+pub(crate) fn has_tag(contact: &pb::Contact, tag: Option<&str>) -> bool {
+    tag.is_none_or(|tag| contact.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
 }
 
-fn add_company(f: &mut fs::File, name: &str, email: &str, email_dep: DepType, phone: &str, phone_dep: DepType) {
-    let mut book = read_from_db(f);
+pub(crate) fn format_address(address: &pb::PostalAddress) -> String {
+    let parts = [&address.street, &address.city, &address.region, &address.postal_code, &address.country];
+    parts.iter().filter(|part| !part.is_empty()).map(|part| part.as_str()).collect::<Vec<_>>().join(", ")
+}
 
-    let mut company: pb::Company;
-    if book.contacts.contains_key(name) {
-        // If Kind exists
-        let kind = book.contacts.get(name).unwrap().kind.clone().unwrap(); // .clone
-        match kind {
-            pb::contact::Kind::Company(comp) => {company = comp },
-            pb::contact::Kind::Person(_) => { panic!("Person") },
-        }
-    }
-    else {
-        company = pb::Company::default();
+fn to_timestamp(t: chrono::DateTime<chrono::FixedOffset>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: t.timestamp(),
+        nanos: t.timestamp_subsec_nanos() as i32,
     }
+}
 
-    let mut addr = pb::company::EmailAddress::default();
-    addr.email = email.to_string();
-    addr.department = str_to_department(email_dep);
-    company.emails.push(addr);
-
-    let mut nb = pb::company::PhoneNumber::default();
-    nb.number = phone.to_string();
-    nb.department = str_to_department(phone_dep);
-    company.phones.push(nb);
+// One-line summary of a change: "#3 2024-05-01T12:00:00Z alice updated Bob",
+// with the time as `output` shows timestamps.
+fn describe_change(change: &pb::Change, output: &Output) -> String {
+    let action = match (&change.before, &change.after) {
+        _ if change.undoes != 0 => format!("undid #{} on", change.undoes),
+        (None, _) => "created".to_string(),
+        (_, None) => "deleted".to_string(),
+        _ => "updated".to_string(),
+    };
+    format!("#{} {} {} {} {}", change.id, output.timestamp(change.at), change.author, action, change.name)
+}
 
-    let mut contact = pb::Contact::default();
-    let mut update_ts = Timestamp::default();
-    let duration = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap();
-    update_ts.seconds = duration.as_secs() as i64;
-    update_ts.nanos = duration.subsec_nanos() as i32;
+fn print_contacts(contacts: impl IntoIterator<Item = (String, pb::Contact)>, redact: bool, output: &Output) {
+    let contacts: Vec<(String, pb::Contact)> = contacts
+        .into_iter()
+        .map(|(name, contact)| if redact { (name, redact::redact(&contact)) } else { (name, contact) })
+        .collect();
+    print!("{}", output.render(&contacts));
+}
 
-    contact.last_updated = Some(update_ts);
-    contact.kind = Some(pb::contact::Kind::Company(company));
-    book.contacts.insert(String::from(name), contact);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::BookError;
 
-    write_to_db(f, book);
-}
+    #[test]
+    fn migrate_encrypted_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.db");
+        let mut book = AddressBook::open(&path).unwrap();
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        book.rekey(Some("correct horse")).unwrap();
+        book.save().unwrap();
+        let book = AddressBook::open_encrypted(&path, "correct horse").unwrap();
 
-fn list_contacts(f: &mut fs::File, redact: bool) {
-    let book = read_from_db(f);
-    let mut keys: Vec<&String>  = book.contacts.keys().collect();
-    keys.sort();
+        // Not written in plain by accident
+        let sqlite = dir.path().join("addressbook.sqlite");
+        assert!(matches!(
+            migrate_storage(&book, Some("correct horse"), &sqlite, Backend::Sqlite, false),
+            Err(AddressBookError::Book(BookError::Crypt(CryptError::Unsupported)))
+        ));
+        assert!(!sqlite.exists());
+        assert_eq!(1, migrate_storage(&book, Some("correct horse"), &sqlite, Backend::Sqlite, true).unwrap());
+        assert!(AddressBook::open(&sqlite).unwrap().get("Bob").unwrap().is_some());
 
-    for name in keys {
-        let mut contact = book.contacts.get(name).unwrap().clone();
-        if redact {
-            match &mut contact.kind {
-                &mut Some(Kind::Person(ref mut p)) => {
-                    p.phones.iter_mut().for_each(|pn| 
-                        pn.number = pn.number.chars().map(|_| '*').collect()
-                    );
-                    p.email = p.email.chars().map(|_| '*').collect()
-                }
-                &mut Some(Kind::Company(ref mut c)) => {
-                    c.phones.iter_mut().for_each(|cn|
-                        cn.number = cn.number.chars().map(|_| '*').collect()
-                    );
-
-                    c.emails.iter_mut().for_each(|ce|
-                        ce.email = ce.email.chars().map(|_| '*').collect()
-                    );
-                }
-                &mut None => {}
-                
-            }
-        }
-        
-        println!("last_updated: {:?}", chrono::DateTime::from_timestamp_nanos(contact.last_updated.unwrap().seconds * 1000000000));
-        match contact.kind {
-            Some(Kind::Person(p)) => {
-                    println!("kind: Person");
-                    println!("name: {}", name);
-                    println!("email: {}", p.email);
-                    println!("phones: \n ---- ");
-                    p.phones.iter().for_each(|pn| {
-                        if let Ok(phone_type) = Type::try_from(pn.r#type) {
-                            println!("phone: {} \n type: {:?}", pn.number, phone_type);
-                        }
-                    });
-                }
-                Some(Kind::Company(c)) => {
-                    println!("kind: Company");
-                    println!("name: {}", name);
-                    println!("emails: \n ----");
-                    c.emails.iter().for_each(|em| {
-                        if let Ok(department) = Department::try_from(em.department) {
-                            println!("email: {} \n department: {:?}", em.email, department);
-                        }
-                    });
-                    println!("phones: \n ---- ");
-                    c.phones.iter().for_each(|pn| {
-                        if let Ok(department) = Department::try_from(pn.department) {
-                            println!("phone: {} \n department: {:?}", pn.number, department);
-                        }
-                    });
-                }
-                None => {}
-            
-        }
-        println!("-----------------------");
+        let copy = dir.path().join("copy.db");
+        assert_eq!(1, migrate_storage(&book, Some("correct horse"), &copy, Backend::Proto, false).unwrap());
+        assert!(crypt::is_encrypted_file(&copy).unwrap());
+        assert!(AddressBook::open_encrypted(&copy, "correct horse").unwrap().get("Bob").unwrap().is_some());
     }
-}
\ No newline at end of file
+}
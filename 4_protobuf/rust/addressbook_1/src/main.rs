@@ -1,15 +1,17 @@
 use std::process;
 
 use addressbook_1::arguments::Cli;
-use clap::Parser;
-
-
+use addressbook_1::complete::COMPLETE_VAR;
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
 
 fn main() {
+    // Answers the shell and exits when called for completions
+    CompleteEnv::with_factory(Cli::command).var(COMPLETE_VAR).complete();
     let cli = Cli::parse();
 
     if let Err(e) = addressbook_1::run(cli) {
-        eprintln!("Application error: {e}");
+        eprintln!("Error: {e}");
         process::exit(1);
     }
 }
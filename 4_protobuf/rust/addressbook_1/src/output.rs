@@ -0,0 +1,470 @@
+// Rendering contacts and timestamps for `list` and the other commands that print them.
+//
+// JSON and YAML are built from the same `serde_json::Value` per contact, so
+// they always carry the same fields; YAML is written by hand with every
+// string double-quoted, which keeps it valid whatever the contact holds.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use prost_types::Timestamp;
+use serde_json::{json, Value};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::arguments::{FormatType, TimeArgs, TimeType};
+use crate::error::{AddressBookError, BookError};
+use crate::pb;
+use crate::pb::company::Department;
+use crate::pb::contact::Kind;
+use crate::pb::person::phone_number::Type;
+
+const TABLE_COLUMNS: [&str; 7] = ["NAME", "KIND", "UPDATED", "EMAILS", "PHONES", "COMPANY", "TAGS"];
+// Longer cells are cut with an ellipsis so one contact cannot push the others off screen.
+const MAX_CELL_WIDTH: usize = 40;
+
+enum Zone {
+    Local,
+    Named(Tz),
+}
+
+/// How to print contacts and timestamps.
+pub struct Output {
+    format: FormatType,
+    time: TimeType,
+    zone: Zone,
+    /// What relative timestamps are relative to.
+    now: DateTime<Utc>,
+}
+
+impl Output {
+    /// `tz` is an IANA time zone name, or "local".
+    pub fn new(format: FormatType, time: &TimeArgs) -> Result<Output, AddressBookError> {
+        let zone = if time.tz.eq_ignore_ascii_case("local") {
+            Zone::Local
+        } else {
+            Zone::Named(time.tz.parse().map_err(|_| BookError::InvalidArgument {
+                arg: "tz",
+                value: time.tz.clone(),
+            })?)
+        };
+        Ok(Output {
+            format,
+            time: time.time,
+            zone,
+            now: Utc::now(),
+        })
+    }
+
+    /// The same timestamps, with contacts in another format.
+    pub fn with_format(self, format: FormatType) -> Output {
+        Output { format, ..self }
+    }
+
+    /// A timestamp as RFC 3339 in the configured zone, or relative to now.
+    pub fn timestamp(&self, ts: Option<Timestamp>) -> String {
+        self.timestamp_to(ts, SecondsFormat::AutoSi)
+    }
+
+    // `precision` only applies to RFC 3339.
+    fn timestamp_to(&self, ts: Option<Timestamp>, precision: SecondsFormat) -> String {
+        match (self.time, to_datetime(ts)) {
+            (_, None) => "never".to_string(),
+            (TimeType::Rfc3339, Some(at)) => self.rfc3339(at, precision),
+            (TimeType::Relative, Some(at)) => relative(at, self.now),
+        }
+    }
+
+    fn rfc3339(&self, at: DateTime<Utc>, precision: SecondsFormat) -> String {
+        let at: DateTime<FixedOffset> = match &self.zone {
+            Zone::Local => at.with_timezone(&Local).fixed_offset(),
+            Zone::Named(tz) => at.with_timezone(tz).fixed_offset(),
+        };
+        at.to_rfc3339_opts(precision, true)
+    }
+
+    pub fn render(&self, contacts: &[(String, pb::Contact)]) -> String {
+        match self.format {
+            FormatType::Plain => contacts.iter().map(|(name, contact)| self.plain(name, contact)).collect(),
+            FormatType::Table => self.table(contacts),
+            FormatType::Json => {
+                let values: Vec<Value> = contacts.iter().map(|(name, contact)| self.value(name, contact)).collect();
+                serde_json::to_string_pretty(&values).expect("a JSON value always serializes") + "\n"
+            }
+            FormatType::Yaml => {
+                let values: Vec<Value> = contacts.iter().map(|(name, contact)| self.value(name, contact)).collect();
+                let mut out = String::new();
+                yaml_block(&mut out, &Value::Array(values), 0);
+                out
+            }
+        }
+    }
+
+    fn plain(&self, name: &str, contact: &pb::Contact) -> String {
+        let mut out = String::new();
+        writeln!(out, "last_updated: {}", self.timestamp(contact.last_updated)).unwrap();
+        match &contact.kind {
+            Some(Kind::Person(p)) => {
+                writeln!(out, "kind: Person").unwrap();
+                writeln!(out, "name: {}", name).unwrap();
+                writeln!(out, "emails: \n ----").unwrap();
+                p.emails.iter().for_each(|em| writeln!(out, "email: {}", em).unwrap());
+                writeln!(out, "phones: \n ---- ").unwrap();
+                p.phones.iter().for_each(|pn| {
+                    if let Ok(phone_type) = Type::try_from(pn.r#type) {
+                        writeln!(out, "phone: {} \n type: {:?}", pn.number, phone_type).unwrap();
+                    }
+                });
+                if !p.company.is_empty() {
+                    writeln!(out, "company: {}", p.company).unwrap();
+                }
+            }
+            Some(Kind::Company(c)) => {
+                writeln!(out, "kind: Company").unwrap();
+                writeln!(out, "name: {}", name).unwrap();
+                writeln!(out, "emails: \n ----").unwrap();
+                c.emails.iter().for_each(|em| {
+                    if let Ok(department) = Department::try_from(em.department) {
+                        writeln!(out, "email: {} \n department: {:?}", em.email, department).unwrap();
+                    }
+                });
+                writeln!(out, "phones: \n ---- ").unwrap();
+                c.phones.iter().for_each(|pn| {
+                    if let Ok(department) = Department::try_from(pn.department) {
+                        writeln!(out, "phone: {} \n department: {:?}", pn.number, department).unwrap();
+                    }
+                });
+            }
+            None => {}
+        }
+        contact.addresses.iter().for_each(|a| writeln!(out, "address: {}", crate::format_address(a)).unwrap());
+        if !contact.tags.is_empty() {
+            writeln!(out, "tags: {}", contact.tags.join(", ")).unwrap();
+        }
+        if !contact.notes.is_empty() {
+            writeln!(out, "notes: {}", contact.notes).unwrap();
+        }
+        writeln!(out, "-----------------------").unwrap();
+        out
+    }
+
+    fn table(&self, contacts: &[(String, pb::Contact)]) -> String {
+        let rows: Vec<[String; TABLE_COLUMNS.len()]> = contacts
+            .iter()
+            .map(|(name, contact)| {
+                let (kind, emails, phones, company) = match &contact.kind {
+                    Some(Kind::Person(p)) => (
+                        "person",
+                        p.emails.join(", "),
+                        p.phones.iter().map(|pn| pn.number.as_str()).collect::<Vec<_>>().join(", "),
+                        p.company.clone(),
+                    ),
+                    Some(Kind::Company(c)) => (
+                        "company",
+                        c.emails.iter().map(|em| em.email.as_str()).collect::<Vec<_>>().join(", "),
+                        c.phones.iter().map(|pn| pn.number.as_str()).collect::<Vec<_>>().join(", "),
+                        String::new(),
+                    ),
+                    None => ("", String::new(), String::new(), String::new()),
+                };
+                [
+                    name.clone(),
+                    kind.to_string(),
+                    // Whole seconds keep the column narrow
+                    self.timestamp_to(contact.last_updated, SecondsFormat::Secs),
+                    emails,
+                    phones,
+                    company,
+                    contact.tags.join(", "),
+                ]
+                .map(|cell| truncate(if cell.is_empty() { "-".to_string() } else { cell }))
+            })
+            .collect();
+
+        let mut widths = TABLE_COLUMNS.map(|header| header.width());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.width());
+            }
+        }
+        let mut out = String::new();
+        let header = TABLE_COLUMNS.map(str::to_string);
+        for row in std::iter::once(&header).chain(&rows) {
+            let mut line = String::new();
+            for (i, (cell, width)) in row.iter().zip(widths).enumerate() {
+                if i > 0 {
+                    line.push_str("  ");
+                }
+                line.push_str(cell);
+                line.push_str(&" ".repeat(width - cell.width()));
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    // The fields JSON and YAML show for a contact, also the contacts of the
+    // HTTP API (see `http`).
+    pub(crate) fn value(&self, name: &str, contact: &pb::Contact) -> Value {
+        let last_updated = to_datetime(contact.last_updated).map(|at| self.rfc3339(at, SecondsFormat::AutoSi));
+        let mut value = json!({ "name": name });
+        match &contact.kind {
+            Some(Kind::Person(p)) => {
+                value["kind"] = json!("person");
+                value["last_updated"] = json!(last_updated);
+                value["emails"] = json!(p.emails);
+                value["phones"] = p
+                    .phones
+                    .iter()
+                    .map(|pn| json!({ "number": pn.number, "type": enum_name(Type::try_from(pn.r#type).ok(), Type::as_str_name, "TYPE_") }))
+                    .collect();
+                value["company"] = json!(Some(&p.company).filter(|c| !c.is_empty()));
+            }
+            Some(Kind::Company(c)) => {
+                let department = |d: i32| enum_name(Department::try_from(d).ok(), Department::as_str_name, "DEPARTMENT_");
+                value["kind"] = json!("company");
+                value["last_updated"] = json!(last_updated);
+                value["emails"] = c
+                    .emails
+                    .iter()
+                    .map(|em| json!({ "email": em.email, "department": department(em.department) }))
+                    .collect();
+                value["phones"] = c
+                    .phones
+                    .iter()
+                    .map(|pn| json!({ "number": pn.number, "department": department(pn.department) }))
+                    .collect();
+            }
+            None => {
+                value["kind"] = Value::Null;
+                value["last_updated"] = json!(last_updated);
+            }
+        }
+        value["tags"] = json!(contact.tags);
+        value["notes"] = json!(contact.notes);
+        value["addresses"] = contact
+            .addresses
+            .iter()
+            .map(|a| {
+                json!({
+                    "street": a.street,
+                    "city": a.city,
+                    "region": a.region,
+                    "postal_code": a.postal_code,
+                    "country": a.country,
+                })
+            })
+            .collect();
+        value
+    }
+}
+
+fn to_datetime(ts: Option<Timestamp>) -> Option<DateTime<Utc>> {
+    ts.and_then(|ts| DateTime::from_timestamp(ts.seconds, u32::try_from(ts.nanos).ok()?))
+}
+
+/// "3 days ago", "in 2 hours", or "just now" within a minute.
+pub fn relative(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    const UNITS: [(u64, &str); 5] =
+        [(365 * 86_400, "year"), (30 * 86_400, "month"), (86_400, "day"), (3_600, "hour"), (60, "minute")];
+    let seconds = (now - at).num_seconds();
+    let Some((n, unit)) = UNITS
+        .iter()
+        .find(|(length, _)| seconds.unsigned_abs() >= *length)
+        .map(|(length, unit)| (seconds.unsigned_abs() / length, unit))
+    else {
+        return "just now".to_string();
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    if seconds < 0 {
+        format!("in {n} {unit}{plural}")
+    } else {
+        format!("{n} {unit}{plural} ago")
+    }
+}
+
+// "TYPE_HOME" as "home"; values the schema does not know as "unknown".
+fn enum_name<E>(value: Option<E>, name: fn(&E) -> &'static str, prefix: &str) -> String {
+    match value {
+        Some(e) => name(&e).trim_start_matches(prefix).to_lowercase(),
+        None => "unknown".to_string(),
+    }
+}
+
+fn truncate(cell: String) -> String {
+    if cell.width() <= MAX_CELL_WIDTH {
+        return cell;
+    }
+    let mut out = String::new();
+    let mut width = 0;
+    for c in cell.chars() {
+        width += c.width().unwrap_or(0);
+        // Leave room for the ellipsis
+        if width >= MAX_CELL_WIDTH {
+            break;
+        }
+        out.push(c);
+    }
+    out + "…"
+}
+
+// Writes `value` as a YAML block with each line indented by `indent`.
+fn yaml_block(out: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            for item in items {
+                out.push_str(&pad);
+                out.push('-');
+                yaml_after(out, item, indent + 2, true);
+            }
+        }
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                out.push_str(&pad);
+                out.push_str(key);
+                out.push(':');
+                yaml_after(out, value, indent + 2, false);
+            }
+        }
+        scalar => {
+            out.push_str(&pad);
+            out.push_str(&yaml_scalar(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+// Writes `value` after a "-" or "key:" that is already on the line.
+fn yaml_after(out: &mut String, value: &Value, indent: usize, in_sequence: bool) {
+    match value {
+        // "- key: value", with the other keys lined up below the first
+        Value::Object(map) if in_sequence && !map.is_empty() => {
+            let mut block = String::new();
+            yaml_block(&mut block, value, indent);
+            out.push(' ');
+            out.push_str(block.trim_start());
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            yaml_block(out, value, indent);
+        }
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            yaml_block(out, value, indent);
+        }
+        scalar => {
+            out.push(' ');
+            out.push_str(&yaml_scalar(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+// A JSON scalar is a valid YAML flow scalar; empty containers are written inline.
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+        scalar => scalar.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(format: FormatType, time: TimeType, tz: &str) -> Output {
+        let time = TimeArgs { time, tz: tz.to_string() };
+        let mut output = Output::new(format, &time).unwrap();
+        output.now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        output
+    }
+
+    fn contacts() -> Vec<(String, pb::Contact)> {
+        let bob = pb::Contact {
+            last_updated: Some(Timestamp { seconds: 1_699_740_000, nanos: 0 }),
+            kind: Some(Kind::Person(pb::Person {
+                emails: vec!["bob@example.com".to_string()],
+                phones: vec![pb::person::PhoneNumber {
+                    number: "+12015550123".to_string(),
+                    r#type: Type::Home.into(),
+                }],
+                company: "Acme".to_string(),
+                ..Default::default()
+            })),
+            tags: vec!["golf".to_string()],
+            ..Default::default()
+        };
+        let acme = pb::Contact {
+            last_updated: Some(Timestamp { seconds: 1_699_999_000, nanos: 0 }),
+            kind: Some(Kind::Company(pb::Company {
+                emails: vec![pb::company::EmailAddress {
+                    email: "hr@acme.com".to_string(),
+                    department: Department::Hr.into(),
+                }],
+                phones: Vec::new(),
+            })),
+            notes: "Quote \"this\"\nand that".to_string(),
+            ..Default::default()
+        };
+        vec![("Acme".to_string(), acme), ("Bob".to_string(), bob)]
+    }
+
+    #[test]
+    fn timestamps() {
+        let ts = Some(Timestamp { seconds: 1_699_740_000, nanos: 500_000_000 });
+
+        assert_eq!("2023-11-11T22:00:00.500Z", output(FormatType::Plain, TimeType::Rfc3339, "UTC").timestamp(ts));
+        assert_eq!(
+            "2023-11-11T23:00:00.500+01:00",
+            output(FormatType::Plain, TimeType::Rfc3339, "Europe/Paris").timestamp(ts)
+        );
+        assert_eq!("3 days ago", output(FormatType::Plain, TimeType::Relative, "UTC").timestamp(ts));
+        assert_eq!("never", output(FormatType::Plain, TimeType::Rfc3339, "UTC").timestamp(None));
+        assert!(Output::new(FormatType::Plain, &TimeArgs { time: TimeType::Rfc3339, tz: "Mars/Olympus".to_string() })
+            .is_err());
+    }
+
+    #[test]
+    fn relative_times() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let ago = |seconds: i64| relative(now - chrono::Duration::seconds(seconds), now);
+
+        assert_eq!("just now", ago(59));
+        assert_eq!("1 minute ago", ago(60));
+        assert_eq!("2 hours ago", ago(2 * 3_600 + 59));
+        assert_eq!("1 year ago", ago(400 * 86_400));
+        assert_eq!("in 3 days", ago(-3 * 86_400));
+    }
+
+    #[test]
+    fn table() {
+        let table = output(FormatType::Table, TimeType::Relative, "UTC").render(&contacts());
+
+        assert_eq!(
+            "NAME  KIND     UPDATED         EMAILS           PHONES        COMPANY  TAGS\n\
+             Acme  company  16 minutes ago  hr@acme.com      -             -        -\n\
+             Bob   person   3 days ago      bob@example.com  +12015550123  Acme     golf\n",
+            table
+        );
+        assert_eq!(format!("{}…", "x".repeat(39)), truncate("x".repeat(41)));
+    }
+
+    #[test]
+    fn json_and_yaml() {
+        let json = output(FormatType::Json, TimeType::Relative, "UTC").render(&contacts());
+        let value: Value = serde_json::from_str(&json).unwrap();
+
+        // JSON keeps RFC 3339 even when asked for relative times
+        assert_eq!("2023-11-11T22:00:00Z", value[1]["last_updated"]);
+        assert_eq!("home", value[1]["phones"][0]["type"]);
+        assert_eq!("hr", value[0]["emails"][0]["department"]);
+
+        let yaml = output(FormatType::Yaml, TimeType::Rfc3339, "UTC").render(&contacts());
+        assert!(yaml.starts_with("- name: \"Acme\"\n  kind: \"company\"\n"));
+        assert!(yaml.contains("  emails:\n    - email: \"hr@acme.com\"\n      department: \"hr\"\n  phones: []\n"));
+        assert!(yaml.contains("  notes: \"Quote \\\"this\\\"\\nand that\"\n"));
+    }
+}
@@ -0,0 +1,354 @@
+// Two-way sync between address books.
+//
+// Each side takes a `pb::SyncSnapshot`: its contacts, plus the deletions
+// found in its change log. `plan` compares two snapshots and decides, for
+// every name, which version both sides should end up with:
+//
+// - the contact with the newer `last_updated` wins; on a tie, the one with
+//   the greater encoding, so both sides pick the same one;
+// - a contact missing on one side is copied over, unless that side deleted
+//   it after its `last_updated`, in which case it is removed from the other.
+//
+// `plan(a, b)` is the mirror image of `plan(b, a)`, which lets the TCP
+// protocol exchange snapshots once and have each side apply its own half.
+//
+// Incoming contacts are checked like `AddressBook::replace` checks them. One
+// that is invalid here, such as a phone another contact already has, is
+// refused: the local contact stays as it is and the plan reports why.
+//
+// The TCP protocol is unencrypted: use it on a trusted network or tunnel it.
+// Each side sends `MAGIC`, the protocol version as a big-endian u32, then
+// its snapshot as a big-endian u32 length followed by the encoded message.
+// The side that connected sends first.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{Read, Write};
+
+use prost::Message;
+use prost_types::Timestamp;
+
+use crate::error::{AddressBookError, BookError};
+use crate::pb;
+use crate::pb::contact::Kind;
+use crate::AddressBook;
+
+pub const MAGIC: &[u8; 4] = b"ABSY";
+pub const PROTOCOL_VERSION: u32 = 1;
+// Larger snapshots are refused rather than allocated.
+const MAX_SNAPSHOT_LEN: u32 = 64 << 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Add(pb::Contact),
+    Update(pb::Contact),
+    Remove,
+    /// An add or update `apply` refused, and why.
+    Refused(String),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Add(_) => write!(f, "added"),
+            Action::Update(_) => write!(f, "updated"),
+            Action::Remove => write!(f, "removed"),
+            Action::Refused(_) => write!(f, "refused"),
+        }
+    }
+}
+
+/// What each side has to change, sorted by name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    pub local: Vec<(String, Action)>,
+    pub remote: Vec<(String, Action)>,
+}
+
+/// The contacts of `book` and the time each contact it no longer has was deleted.
+pub fn snapshot(book: &AddressBook) -> Result<pb::SyncSnapshot, AddressBookError> {
+    let mut snapshot = pb::SyncSnapshot {
+        contacts: book.iter()?.collect(),
+        ..Default::default()
+    };
+    for change in book.storage().changes()? {
+        match (&change.after, change.at) {
+            (None, Some(at)) => snapshot.deleted.insert(change.name, at),
+            _ => snapshot.deleted.remove(&change.name),
+        };
+    }
+    snapshot.deleted.retain(|name, _| !snapshot.contacts.contains_key(name));
+    Ok(snapshot)
+}
+
+/// Decides what `local` and `remote` each change to end up with the same contacts.
+pub fn plan(local: &pb::SyncSnapshot, remote: &pb::SyncSnapshot) -> Plan {
+    let names: BTreeSet<&String> = local
+        .contacts
+        .keys()
+        .chain(remote.contacts.keys())
+        .chain(local.deleted.keys())
+        .chain(remote.deleted.keys())
+        .collect();
+
+    let mut plan = Plan::default();
+    for name in names {
+        match (local.contacts.get(name), remote.contacts.get(name)) {
+            (Some(l), Some(r)) if l != r => {
+                if newer(l, r) {
+                    plan.remote.push((name.clone(), Action::Update(l.clone())));
+                } else {
+                    plan.local.push((name.clone(), Action::Update(r.clone())));
+                }
+            }
+            (Some(l), None) => match remote.deleted.get(name) {
+                Some(deleted) if stamp(Some(*deleted)) > stamp(l.last_updated) => {
+                    plan.local.push((name.clone(), Action::Remove))
+                }
+                _ => plan.remote.push((name.clone(), Action::Add(l.clone()))),
+            },
+            (None, Some(r)) => match local.deleted.get(name) {
+                Some(deleted) if stamp(Some(*deleted)) > stamp(r.last_updated) => {
+                    plan.remote.push((name.clone(), Action::Remove))
+                }
+                _ => plan.local.push((name.clone(), Action::Add(r.clone()))),
+            },
+            _ => {}
+        }
+    }
+    plan
+}
+
+// Whether `a` wins over `b`.
+fn newer(a: &pb::Contact, b: &pb::Contact) -> bool {
+    (stamp(a.last_updated), a.encode_to_vec()) > (stamp(b.last_updated), b.encode_to_vec())
+}
+
+fn stamp(ts: Option<Timestamp>) -> (i64, i32) {
+    ts.map(|t| (t.seconds, t.nanos)).unwrap_or((i64::MIN, 0))
+}
+
+/// Applies one side of a plan to `book` and saves it. Contacts that fail
+/// the checks are left out and their action becomes `Action::Refused`.
+pub fn apply(book: &mut AddressBook, actions: &mut [(String, Action)]) -> Result<(), AddressBookError> {
+    // Removals first, then companies, so the phones and companies the other
+    // contacts are checked against are already in place.
+    let mut order: Vec<usize> = (0..actions.len()).collect();
+    order.sort_by_key(|&i| match &actions[i].1 {
+        Action::Remove => 0,
+        Action::Add(c) | Action::Update(c) if matches!(c.kind, Some(Kind::Company(_))) => 1,
+        _ => 2,
+    });
+    for i in order {
+        let (name, action) = &mut actions[i];
+        match action {
+            Action::Add(contact) | Action::Update(contact) => match book.import(name, contact.clone()) {
+                Ok(_) => {}
+                Err(e @ (BookError::Validation(_) | BookError::InvalidArgument { .. })) => {
+                    *action = Action::Refused(e.to_string())
+                }
+                Err(e) => return Err(e.into()),
+            },
+            Action::Remove => {
+                book.remove(name)?;
+            }
+            Action::Refused(_) => {}
+        }
+    }
+    Ok(book.save()?)
+}
+
+/// Syncs two books on this machine.
+pub fn with_book(local: &mut AddressBook, remote: &mut AddressBook) -> Result<Plan, AddressBookError> {
+    let mut plan = plan(&snapshot(local)?, &snapshot(remote)?);
+    apply(local, &mut plan.local)?;
+    apply(remote, &mut plan.remote)?;
+    Ok(plan)
+}
+
+/// Syncs `book` with another instance at the other end of `stream`.
+/// `connected` tells whether this side opened the connection, and so sends first.
+/// Only `plan.local` is applied here; the other side applies `plan.remote` itself.
+pub fn over_stream(
+    book: &mut AddressBook,
+    stream: &mut (impl Read + Write),
+    connected: bool,
+) -> Result<Plan, AddressBookError> {
+    let local = snapshot(book)?;
+    let remote = if connected {
+        send(stream, &local)?;
+        receive(stream)?
+    } else {
+        let remote = receive(stream)?;
+        send(stream, &local)?;
+        remote
+    };
+    let mut plan = plan(&local, &remote);
+    apply(book, &mut plan.local)?;
+    Ok(plan)
+}
+
+fn send(stream: &mut impl Write, snapshot: &pb::SyncSnapshot) -> Result<(), AddressBookError> {
+    let payload = snapshot.encode_to_vec();
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|len| *len <= MAX_SNAPSHOT_LEN)
+        .ok_or_else(|| AddressBookError::SyncProtocol("the address book is too large to send".to_string()))?;
+    stream.write_all(MAGIC)?;
+    stream.write_all(&PROTOCOL_VERSION.to_be_bytes())?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(&payload)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn receive(stream: &mut impl Read) -> Result<pb::SyncSnapshot, AddressBookError> {
+    let mut header = [0; 12];
+    stream.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(AddressBookError::SyncProtocol("the other side is not an address book sync".to_string()));
+    }
+    let word = |i: usize| u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    if word(4) != PROTOCOL_VERSION {
+        return Err(AddressBookError::SyncProtocol(format!("unsupported protocol version {}", word(4))));
+    }
+    if word(8) > MAX_SNAPSHOT_LEN {
+        return Err(AddressBookError::SyncProtocol("the other address book is too large".to_string()));
+    }
+    let mut payload = vec![0; word(8) as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(pb::SyncSnapshot::decode(payload.as_slice())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use super::*;
+    use crate::pb::person::phone_number::Type;
+    use crate::Details;
+
+    fn open(dir: &tempfile::TempDir, name: &str) -> AddressBook {
+        AddressBook::open(dir.path().join(name)).unwrap()
+    }
+
+    fn names(book: &AddressBook) -> Vec<String> {
+        book.iter().unwrap().map(|(name, _)| name).collect()
+    }
+
+    fn contact(seconds: i64, email: &str) -> pb::Contact {
+        pb::Contact {
+            last_updated: Some(Timestamp { seconds, nanos: 0 }),
+            kind: Some(pb::contact::Kind::Person(pb::Person {
+                emails: vec![email.to_string()],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plan_is_symmetric() {
+        let mut a = pb::SyncSnapshot::default();
+        let mut b = pb::SyncSnapshot::default();
+        a.contacts.insert("Al".to_string(), contact(1, "al@example.com"));
+        a.contacts.insert("Bob".to_string(), contact(2, "bob@example.com"));
+        b.contacts.insert("Bob".to_string(), contact(3, "bob@example.org"));
+        a.contacts.insert("Tie".to_string(), contact(5, "tie@example.com"));
+        b.contacts.insert("Tie".to_string(), contact(5, "tie@example.org"));
+        b.deleted.insert("Al".to_string(), Timestamp { seconds: 2, nanos: 0 });
+        a.contacts.insert("Carol".to_string(), contact(4, "carol@example.com"));
+        b.deleted.insert("Carol".to_string(), Timestamp { seconds: 3, nanos: 0 });
+
+        let ab = plan(&a, &b);
+        let ba = plan(&b, &a);
+
+        assert_eq!(ab.local, ba.remote);
+        assert_eq!(ab.remote, ba.local);
+        assert_eq!(
+            vec![
+                ("Al".to_string(), Action::Remove),
+                ("Bob".to_string(), Action::Update(contact(3, "bob@example.org"))),
+                ("Tie".to_string(), Action::Update(contact(5, "tie@example.org"))),
+            ],
+            ab.local
+        );
+        assert_eq!(
+            vec![("Carol".to_string(), Action::Add(contact(4, "carol@example.com")))],
+            ab.remote
+        );
+    }
+
+    #[test]
+    fn sync_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut a = open(&dir, "a.db");
+        let mut b = open(&dir, "b.sqlite");
+        a.upsert_person("Al", Some("al@example.com"), None, &Details::default()).unwrap();
+        a.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        b.upsert_person("Carol", None, Some(("201-555-0123", Type::Home)), &Details::default()).unwrap();
+        with_book(&mut a, &mut b).unwrap();
+
+        a.remove("Al").unwrap();
+        b.upsert_person("Bob", Some("bob@example.org"), None, &Details::default()).unwrap();
+        let plan = with_book(&mut a, &mut b).unwrap();
+
+        assert_eq!(vec!["Bob"], plan.local.iter().map(|(n, _)| n).collect::<Vec<_>>());
+        assert_eq!(vec![("Al".to_string(), Action::Remove)], plan.remote);
+        assert_eq!(vec!["Bob", "Carol"], names(&a));
+        assert_eq!(names(&a), names(&b));
+        assert_eq!(a.iter().unwrap().collect::<Vec<_>>(), b.iter().unwrap().collect::<Vec<_>>());
+        assert_eq!(Plan::default(), with_book(&mut a, &mut b).unwrap());
+    }
+
+    #[test]
+    fn invalid_contacts_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut a = open(&dir, "a.db");
+        let mut b = open(&dir, "b.db");
+        a.upsert_person("Al", None, Some(("201-555-0123", Type::Home)), &Details::default()).unwrap();
+        b.upsert_person("Bob", None, Some(("201-555-0123", Type::Home)), &Details::default()).unwrap();
+        b.upsert_company("Acme", Some(("hr@acme.example", Default::default())), None, &Details::default()).unwrap();
+        let company = Details { company: Some("Acme".to_string()), ..Details::default() };
+        b.upsert_person("Carol", Some("carol@example.com"), None, &company).unwrap();
+
+        let plan = with_book(&mut a, &mut b).unwrap();
+
+        // Bob has Al's phone; Carol's company is added before her
+        assert!(matches!(&plan.local[1], (name, Action::Refused(_)) if name == "Bob"));
+        assert!(matches!(&plan.remote[0], (name, Action::Refused(_)) if name == "Al"));
+        assert_eq!(vec!["Acme", "Al", "Carol"], names(&a));
+        assert_eq!(vec!["Acme", "Bob", "Carol"], names(&b));
+    }
+
+    #[test]
+    fn sync_over_tcp() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut a = open(&dir, "a.db");
+        a.upsert_person("Al", Some("al@example.com"), None, &Details::default()).unwrap();
+        let mut b = open(&dir, "b.db");
+        b.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            over_stream(&mut b, &mut stream, false).unwrap();
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let plan = over_stream(&mut a, &mut stream, true).unwrap();
+        server.join().unwrap();
+
+        assert_eq!(1, plan.local.len());
+        assert_eq!(vec!["Al", "Bob"], names(&a));
+        assert_eq!(vec!["Al", "Bob"], names(&open(&dir, "b.db")));
+    }
+
+    #[test]
+    fn rejects_other_protocols() {
+        let mut stream = std::io::Cursor::new(b"GET / HTTP/1.1\r\n\r\n".to_vec());
+
+        assert!(matches!(receive(&mut stream), Err(AddressBookError::SyncProtocol(_))));
+    }
+}
@@ -0,0 +1,466 @@
+// Terminal browser for an address book (`tui` subcommand).
+//
+// `App` holds the state and reacts to keys; `draw` renders it. Edits go
+// through `AddressBook::set_field`, so they are validated and logged like
+// the CLI's, and every accepted edit is saved at once.
+
+use chrono::{DateTime, SecondsFormat};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::dedupe::normalize_name;
+use crate::error::{AddressBookError, BookError};
+use crate::pb;
+use crate::pb::contact::Kind;
+use crate::redact;
+use crate::Field;
+use crate::AddressBook;
+
+const HELP: &str = "↑/↓ move  / search  e edit  r redact  q quit";
+
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Browse,
+    /// Typing into the search box; the list is filtered as it changes.
+    Search,
+    /// Editing one email or phone of the selected contact.
+    Edit { field: Field, input: String },
+}
+
+pub struct App {
+    book: AddressBook,
+    contacts: Vec<(String, pb::Contact)>,
+    /// Indexes into `contacts` matching `search`.
+    shown: Vec<usize>,
+    list: ListState,
+    search: String,
+    mode: Mode,
+    redact: bool,
+    status: String,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(book: AddressBook, redact: bool) -> Result<App, AddressBookError> {
+        let mut app = App {
+            book,
+            contacts: Vec::new(),
+            shown: Vec::new(),
+            list: ListState::default(),
+            search: String::new(),
+            mode: Mode::Browse,
+            redact,
+            status: HELP.to_string(),
+            quit: false,
+        };
+        app.reload()?;
+        Ok(app)
+    }
+
+    /// Runs until the user quits, restoring the terminal afterwards.
+    pub fn run(mut self) -> Result<(), AddressBookError> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<(), AddressBookError> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&(String, pb::Contact)> {
+        self.list.selected().and_then(|i| self.shown.get(i)).map(|&i| &self.contacts[i])
+    }
+
+    // Reads the contacts again, keeping the selection on the same name if it is still shown.
+    fn reload(&mut self) -> Result<(), BookError> {
+        let selected = self.selected().map(|(name, _)| name.clone());
+        self.contacts = self.book.iter()?.collect();
+        self.filter();
+        if let Some(name) = selected {
+            if let Some(i) = self.shown.iter().position(|&i| self.contacts[i].0 == name) {
+                self.list.select(Some(i));
+            }
+        }
+        Ok(())
+    }
+
+    // Shows the contacts whose name, emails or phones contain the search text.
+    fn filter(&mut self) {
+        let search = normalize_name(&self.search);
+        let digits: String = self.search.chars().filter(char::is_ascii_digit).collect();
+        self.shown = (0..self.contacts.len())
+            .filter(|&i| {
+                let (name, contact) = &self.contacts[i];
+                let (emails, phones) = entries(contact);
+                search.is_empty()
+                    || normalize_name(name).contains(&search)
+                    || emails.iter().any(|e| e.to_lowercase().contains(&self.search.to_lowercase()))
+                    || (!digits.is_empty() && phones.iter().any(|p| p.contains(&digits)))
+            })
+            .collect();
+        let selected = self.list.selected().unwrap_or(0).min(self.shown.len().saturating_sub(1));
+        self.list.select(if self.shown.is_empty() { None } else { Some(selected) });
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        match &mut self.mode {
+            Mode::Browse => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::Char('/') => {
+                    self.mode = Mode::Search;
+                    self.status = "type to search, Enter to keep, Esc to clear".to_string();
+                }
+                KeyCode::Char('r') => {
+                    self.redact = !self.redact;
+                    self.status = format!("redaction {}", if self.redact { "on" } else { "off" });
+                }
+                KeyCode::Char('e') => self.start_edit(),
+                _ => {}
+            },
+            Mode::Search => match key.code {
+                KeyCode::Enter => self.browse(),
+                KeyCode::Esc => {
+                    self.search.clear();
+                    self.filter();
+                    self.browse();
+                }
+                KeyCode::Backspace => {
+                    self.search.pop();
+                    self.filter();
+                }
+                KeyCode::Char(c) => {
+                    self.search.push(c);
+                    self.filter();
+                }
+                _ => {}
+            },
+            Mode::Edit { field, input } => match key.code {
+                KeyCode::Esc => self.browse(),
+                KeyCode::Enter => {
+                    let (field, input) = (*field, input.clone());
+                    self.save_edit(field, &input);
+                }
+                KeyCode::Tab => {
+                    let field = *field;
+                    self.edit_field(self.next_field(field));
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            },
+        }
+    }
+
+    fn browse(&mut self) {
+        self.mode = Mode::Browse;
+        self.status = HELP.to_string();
+    }
+
+    fn start_edit(&mut self) {
+        if self.redact {
+            self.status = "turn redaction off (r) to edit".to_string();
+        } else if let Some(&field) = self.fields().first() {
+            self.edit_field(field);
+        }
+    }
+
+    // Fields in the order Tab visits them: emails, phones, then a new email and a new phone.
+    fn fields(&self) -> Vec<Field> {
+        let Some((_, contact)) = self.selected() else {
+            return Vec::new();
+        };
+        let (emails, phones) = entries(contact);
+        let mut fields: Vec<Field> = (0..emails.len()).map(Field::Email).collect();
+        fields.extend((0..phones.len()).map(Field::Phone));
+        fields.push(Field::Email(emails.len()));
+        fields.push(Field::Phone(phones.len()));
+        fields
+    }
+
+    fn next_field(&self, field: Field) -> Field {
+        let fields = self.fields();
+        let i = fields.iter().position(|f| *f == field).map_or(0, |i| (i + 1) % fields.len());
+        fields[i]
+    }
+
+    fn edit_field(&mut self, field: Field) {
+        let Some((_, contact)) = self.selected() else {
+            return;
+        };
+        let (emails, phones) = entries(contact);
+        let input = match field {
+            Field::Email(i) => emails.get(i),
+            Field::Phone(i) => phones.get(i),
+        };
+        self.mode = Mode::Edit {
+            field,
+            input: input.cloned().unwrap_or_default(),
+        };
+        self.status = "Enter to save (empty removes), Tab for the next field, Esc to cancel".to_string();
+    }
+
+    fn save_edit(&mut self, field: Field, input: &str) {
+        let Some((name, _)) = self.selected().cloned() else {
+            return;
+        };
+        let result = self
+            .book
+            .set_field(&name, field, input)
+            .and_then(|_| self.book.save())
+            .and_then(|_| self.reload());
+        match result {
+            Ok(()) => {
+                self.browse();
+                self.status = format!("saved {name}");
+            }
+            Err(e) => self.status = format!("error: {e}"),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, input, status] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, detail] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(main);
+
+        let items: Vec<ListItem> = self.shown.iter().map(|&i| ListItem::new(self.contacts[i].0.as_str())).collect();
+        let title = format!("Contacts ({}/{})", self.shown.len(), self.contacts.len());
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            list,
+            &mut self.list,
+        );
+
+        let lines = match self.selected() {
+            Some((name, contact)) => self.detail_lines(name, contact),
+            None => vec![Line::from("no contact")],
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title("Details")).wrap(Wrap { trim: false }),
+            detail,
+        );
+
+        let prompt = match &self.mode {
+            Mode::Browse if self.search.is_empty() => Line::default(),
+            Mode::Browse | Mode::Search => Line::from(vec!["search: ".bold(), Span::raw(self.search.as_str())]),
+            Mode::Edit { field, input } => {
+                Line::from(vec![format!("{}: ", field_label(*field)).bold(), Span::raw(input.as_str())])
+            }
+        };
+        frame.render_widget(prompt, input);
+        frame.render_widget(Line::from(self.status.as_str()).dim(), status);
+    }
+
+    fn detail_lines(&self, name: &str, contact: &pb::Contact) -> Vec<Line<'static>> {
+        let contact = if self.redact { redact::redact(contact) } else { contact.clone() };
+        let editing = match &self.mode {
+            Mode::Edit { field, .. } => Some(*field),
+            _ => None,
+        };
+        let entry = |field: Field, text: String| {
+            let line = Line::from(format!("  {}: {text}", field_label(field)));
+            if editing == Some(field) {
+                line.reversed()
+            } else {
+                line
+            }
+        };
+
+        let updated = contact.last_updated.unwrap_or_default();
+        let updated = DateTime::from_timestamp(updated.seconds, updated.nanos as u32).unwrap_or_default();
+        let mut lines = vec![
+            Line::from(name.to_string()).bold(),
+            Line::from(format!("last updated: {}", updated.to_rfc3339_opts(SecondsFormat::Secs, true))),
+        ];
+        match &contact.kind {
+            Some(Kind::Person(p)) => {
+                lines.push(Line::from("kind: person"));
+                for (i, em) in p.emails.iter().enumerate() {
+                    lines.push(entry(Field::Email(i), em.clone()));
+                }
+                for (i, pn) in p.phones.iter().enumerate() {
+                    let phone_type = pb::person::phone_number::Type::try_from(pn.r#type).unwrap_or_default();
+                    lines.push(entry(Field::Phone(i), format!("{} ({phone_type:?})", pn.number)));
+                }
+                if !p.company.is_empty() {
+                    lines.push(Line::from(format!("company: {}", p.company)));
+                }
+            }
+            Some(Kind::Company(c)) => {
+                lines.push(Line::from("kind: company"));
+                for (i, em) in c.emails.iter().enumerate() {
+                    let department = pb::company::Department::try_from(em.department).unwrap_or_default();
+                    lines.push(entry(Field::Email(i), format!("{} ({department:?})", em.email)));
+                }
+                for (i, pn) in c.phones.iter().enumerate() {
+                    let department = pb::company::Department::try_from(pn.department).unwrap_or_default();
+                    lines.push(entry(Field::Phone(i), format!("{} ({department:?})", pn.number)));
+                }
+            }
+            None => {}
+        }
+        for address in &contact.addresses {
+            lines.push(Line::from(format!("address: {}", crate::format_address(address))));
+        }
+        if !contact.tags.is_empty() {
+            lines.push(Line::from(format!("tags: {}", contact.tags.join(", "))));
+        }
+        if !contact.notes.is_empty() {
+            lines.push(Line::from(format!("notes: {}", contact.notes)));
+        }
+        if let Some(field) = editing {
+            let (emails, phones) = entries(&contact);
+            let new = match field {
+                Field::Email(i) => i == emails.len(),
+                Field::Phone(i) => i == phones.len(),
+            };
+            if new {
+                lines.push(entry(field, "(new)".to_string()));
+            }
+        }
+        lines
+    }
+}
+
+fn field_label(field: Field) -> String {
+    match field {
+        Field::Email(i) => format!("email {}", i + 1),
+        Field::Phone(i) => format!("phone {}", i + 1),
+    }
+}
+
+// Emails and phone numbers of a contact, in the order `Field` indexes them.
+fn entries(contact: &pb::Contact) -> (Vec<String>, Vec<String>) {
+    match &contact.kind {
+        Some(Kind::Person(p)) => (
+            p.emails.clone(),
+            p.phones.iter().map(|pn| pn.number.clone()).collect(),
+        ),
+        Some(Kind::Company(c)) => (
+            c.emails.iter().map(|em| em.email.clone()).collect(),
+            c.phones.iter().map(|pn| pn.number.clone()).collect(),
+        ),
+        None => (Vec::new(), Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    use super::*;
+    use crate::pb::company::Department;
+    use crate::pb::person::phone_number::Type;
+    use crate::Details;
+
+    fn app(dir: &tempfile::TempDir) -> App {
+        let mut book = AddressBook::open(dir.path().join("addressbook.db")).unwrap();
+        book.upsert_person("Bob", Some("bob@example.com"), Some(("201-555-0123", Type::Home)), &Details::default()).unwrap();
+        book.upsert_company("Acme", Some(("hr@acme.com", Department::Hr)), None, &Details::default()).unwrap();
+        book.save().unwrap();
+        App::new(book, false).unwrap()
+    }
+
+    fn keys(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\t' => KeyCode::Tab,
+                '\x08' => KeyCode::Backspace,
+                '\x1b' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            app.handle_key(KeyEvent::from(code));
+        }
+    }
+
+    fn screen(app: &mut App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer.content.chunks(80).map(|row| row.iter().map(|c| c.symbol()).collect::<String>() + "\n").collect()
+    }
+
+    #[test]
+    fn search() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app(&dir);
+
+        keys(&mut app, "/bo");
+        assert_eq!(vec!["Bob"], app.shown.iter().map(|&i| app.contacts[i].0.as_str()).collect::<Vec<_>>());
+        keys(&mut app, "\x08\x08201555");
+        assert_eq!("Bob", app.selected().unwrap().0);
+        keys(&mut app, "\x1b");
+        assert_eq!(2, app.shown.len());
+        assert_eq!(Mode::Browse, app.mode);
+    }
+
+    #[test]
+    fn edit_phone() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app(&dir);
+
+        keys(&mut app, "j");
+        assert_eq!("Bob", app.selected().unwrap().0);
+        // Email, then the first phone
+        keys(&mut app, "e\t");
+        assert!(screen(&mut app).contains("phone 1: +12015550123"));
+        keys(&mut app, "\x084\n");
+
+        assert_eq!(Mode::Browse, app.mode);
+        let book = AddressBook::open(dir.path().join("addressbook.db")).unwrap();
+        assert_eq!(Some("Bob".to_string()), book.storage().find_phone("+12015550124").unwrap());
+    }
+
+    #[test]
+    fn invalid_edit_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app(&dir);
+
+        // "bob@example.com@"
+        keys(&mut app, "je@\n");
+
+        assert!(matches!(app.mode, Mode::Edit { .. }));
+        assert!(app.status.starts_with("error: invalid email address"));
+    }
+
+    #[test]
+    fn redaction_toggle() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app(&dir);
+        keys(&mut app, "j");
+        assert!(screen(&mut app).contains("bob@example.com"));
+
+        keys(&mut app, "r");
+        let screen = screen(&mut app);
+        assert!(!screen.contains("bob@example.com"));
+        assert!(!screen.contains("+12015550123"));
+        keys(&mut app, "e");
+        assert_eq!(Mode::Browse, app.mode);
+    }
+}
@@ -0,0 +1,160 @@
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io::Result;
+use std::path::PathBuf;
+
+use prost::Message;
+
+#[path = "src/redact/descriptor.rs"]
+mod descriptor;
+
+fn main() -> Result<()> {
+    // Type names let `redact` look messages up in the descriptor set, which is
+    // embedded in the crate so it can read field options at runtime. `redact`
+    // also writes the `Debug` of every type, so the derived one is skipped.
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let descriptor_path = out_dir.join("addressbook_descriptor.bin");
+    prost_build::Config::new()
+        .enable_type_names()
+        .file_descriptor_set_path(&descriptor_path)
+        .skip_debug(["."])
+        .compile_protos(&["src/addressbook.proto"], &["src/"])?;
+    // The same types with the derived `Debug`, printed once redacted.
+    let plain = out_dir.join("plain");
+    fs::create_dir_all(&plain)?;
+    prost_build::Config::new()
+        .out_dir(plain)
+        .compile_protos(&["src/addressbook.proto"], &["src/"])?;
+
+    // Which `Debug` `redact` writes for each type, read from the descriptors
+    // so a new message is redacted without being listed by hand.
+    let set = descriptor::FileDescriptorSet::decode(fs::read(&descriptor_path)?.as_slice())?;
+    let mut types = Types::default();
+    for file in set.file.iter().filter(|file| file.package != "google.protobuf") {
+        for message in &file.message_type {
+            types.message(&[], message);
+        }
+        for enumeration in &file.enum_type {
+            types.enumeration(&[], &enumeration.name);
+        }
+    }
+    fs::write(out_dir.join("redact_debug.rs"), types.code())?;
+    println!("cargo:rerun-if-changed=src/addressbook.proto");
+    println!("cargo:rerun-if-changed=src/redact/descriptor.rs");
+    Ok(())
+}
+
+// The paths of the generated types, relative to `pb` and `plain`
+#[derive(Default)]
+struct Types {
+    messages: Vec<String>,
+    enumerations: Vec<String>,
+    // The oneof enum, its message and its field in the message
+    oneofs: Vec<(String, String, String)>,
+}
+
+impl Types {
+    fn message(&mut self, modules: &[String], message: &descriptor::DescriptorProto) {
+        if message.options.as_ref().is_some_and(|o| o.map_entry) {
+            return;
+        }
+        let name = path(modules, &message.name);
+        let map_entries: Vec<String> = message
+            .nested_type
+            .iter()
+            .filter(|nested| nested.options.as_ref().is_some_and(|o| o.map_entry))
+            .map(|nested| format!(".{}", nested.name))
+            .collect();
+        for field in &message.field {
+            if !field.options.as_ref().is_some_and(|o| o.debug_redact) {
+                continue;
+            }
+            // `redact` only rewrites strings, so anything else would print as it is
+            let map = field.r#type == descriptor::TYPE_MESSAGE && map_entries.iter().any(|e| field.type_name.ends_with(e));
+            if field.r#type != descriptor::TYPE_STRING || map {
+                panic!(
+                    "{}.{}: debug_redact is only handled on string fields",
+                    message.name, field.name
+                );
+            }
+        }
+
+        let mut inner = modules.to_vec();
+        inner.push(snake_case(&message.name));
+        for (i, oneof) in message.oneof_decl.iter().enumerate() {
+            // A proto3 `optional` field is a oneof of its own, which prost
+            // turns into an `Option`
+            let synthetic = message
+                .field
+                .iter()
+                .any(|f| f.oneof_index == Some(i as i32) && f.proto3_optional);
+            if !synthetic {
+                self.oneofs
+                    .push((path(&inner, &camel_case(&oneof.name)), name.clone(), oneof.name.clone()));
+            }
+        }
+        for nested in &message.nested_type {
+            self.message(&inner, nested);
+        }
+        for enumeration in &message.enum_type {
+            self.enumeration(&inner, &enumeration.name);
+        }
+        self.messages.push(name);
+    }
+
+    fn enumeration(&mut self, modules: &[String], name: &str) {
+        self.enumerations.push(path(modules, name));
+    }
+
+    fn code(&self) -> String {
+        let mut code = String::from("redacted_debug! {\n");
+        for message in &self.messages {
+            writeln!(code, "    pb::{message} => plain::{message},").unwrap();
+        }
+        code.push_str("}\n\nplain_debug! {\n");
+        for enumeration in &self.enumerations {
+            writeln!(code, "    pb::{enumeration} => plain::{enumeration},").unwrap();
+        }
+        code.push_str("}\n\noneof_debug! {\n");
+        for (oneof, message, field) in &self.oneofs {
+            writeln!(code, "    pb::{oneof} => pb::{message}, plain::{message}, {field},").unwrap();
+        }
+        code.push_str("}\n");
+        code
+    }
+}
+
+fn path(modules: &[String], name: &str) -> String {
+    let mut path = modules.join("::");
+    if !path.is_empty() {
+        path.push_str("::");
+    }
+    path.push_str(name);
+    path
+}
+
+// How prost names the module of a message: "PhoneNumber" -> "phone_number"
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+// How prost names the enum of a oneof: "kind" -> "Kind"
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
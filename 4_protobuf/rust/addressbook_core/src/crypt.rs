@@ -0,0 +1,210 @@
+// Passphrase-based encryption of the addressbook.db file.
+//
+// An encrypted file starts with `MAGIC`, the Argon2id cost parameters
+// (memory in KiB, iterations, parallelism; little-endian u32s), the salt and
+// the XChaCha20-Poly1305 nonce. The rest is the encrypted plain file, header
+// included (see `db`). The header is authenticated as associated data, so
+// changing any byte of the file makes decryption fail.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+pub const MAGIC: &[u8; 4] = b"ABEN";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const PARAMS_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + PARAMS_LEN + SALT_LEN + NONCE_LEN;
+// The most a file header may ask Argon2 for, far above the defaults (19 MiB,
+// 2 iterations, 1 lane) but bounded, so a crafted file cannot make opening it
+// take all the memory or run for hours.
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum CryptError {
+    /// The file is encrypted and no passphrase was given.
+    PassphraseRequired,
+    /// Decryption failed: the passphrase is wrong or the file was modified.
+    WrongPassphrase,
+    EmptyPassphrase,
+    /// The storage backend cannot be encrypted.
+    Unsupported,
+    /// The new passphrase and its confirmation differ.
+    Mismatch,
+    /// The header of an encrypted file is damaged, or asks for Argon2 costs
+    /// above the limits.
+    InvalidHeader,
+}
+
+impl fmt::Display for CryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptError::PassphraseRequired => {
+                write!(f, "the address book is encrypted: set ADDRESSBOOK_PASSPHRASE or run from a terminal")
+            }
+            CryptError::WrongPassphrase => {
+                write!(f, "wrong passphrase, or the encrypted address book was modified")
+            }
+            CryptError::EmptyPassphrase => write!(f, "the passphrase cannot be empty"),
+            CryptError::Unsupported => write!(f, "only protobuf address books can be encrypted"),
+            CryptError::Mismatch => write!(f, "the passphrases do not match"),
+            CryptError::InvalidHeader => write!(f, "the encrypted address book header is damaged"),
+        }
+    }
+}
+
+impl Error for CryptError {}
+
+/// Whether file contents are encrypted.
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+/// Whether the file at `path` is encrypted. A missing file is not.
+pub fn is_encrypted_file(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; MAGIC.len()];
+    match fs::File::open(path) {
+        Ok(f) => {
+            let n = f.take(MAGIC.len() as u64).read(&mut magic)?;
+            Ok(is_encrypted(&magic[..n]))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// A key derived from a passphrase, kept so every save does not pay for Argon2 again.
+#[derive(Clone)]
+pub struct Cipher {
+    params: Params,
+    salt: [u8; SALT_LEN],
+    aead: XChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// Derives a key with a fresh random salt and the default Argon2id costs.
+    pub fn new(passphrase: &str) -> Result<Cipher, CryptError> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Cipher::derive(passphrase, Params::default(), salt)
+    }
+
+    /// Decrypts file contents. Also returns the cipher, which encrypts later
+    /// saves with the same passphrase.
+    pub fn open(contents: &[u8], passphrase: &str) -> Result<(Cipher, Vec<u8>), CryptError> {
+        if !is_encrypted(contents) || contents.len() < HEADER_LEN {
+            return Err(CryptError::InvalidHeader);
+        }
+        let (header, ciphertext) = contents.split_at(HEADER_LEN);
+        let u32_at = |i: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&header[MAGIC.len() + 4 * i..][..4]);
+            u32::from_le_bytes(bytes)
+        };
+        let (m_cost, t_cost, p_cost) = (u32_at(0), u32_at(1), u32_at(2));
+        if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+            return Err(CryptError::InvalidHeader);
+        }
+        let params = Params::new(m_cost, t_cost, p_cost, None).map_err(|_| CryptError::InvalidHeader)?;
+        let mut salt = [0; SALT_LEN];
+        salt.copy_from_slice(&header[MAGIC.len() + PARAMS_LEN..][..SALT_LEN]);
+        let nonce = XNonce::from_slice(&header[HEADER_LEN - NONCE_LEN..]);
+
+        let cipher = Cipher::derive(passphrase, params, salt)?;
+        let plain = cipher
+            .aead
+            .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+            .map_err(|_| CryptError::WrongPassphrase)?;
+        Ok((cipher, plain))
+    }
+
+    /// Encrypts plain file contents under a fresh nonce.
+    pub fn seal(&self, plain: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut contents = Vec::with_capacity(HEADER_LEN + plain.len() + 16);
+        contents.extend_from_slice(MAGIC);
+        contents.extend_from_slice(&self.params.m_cost().to_le_bytes());
+        contents.extend_from_slice(&self.params.t_cost().to_le_bytes());
+        contents.extend_from_slice(&self.params.p_cost().to_le_bytes());
+        contents.extend_from_slice(&self.salt);
+        contents.extend_from_slice(&nonce);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, Payload { msg: plain, aad: &contents })
+            .expect("encrypting into a Vec cannot fail");
+        contents.extend_from_slice(&ciphertext);
+        contents
+    }
+
+    fn derive(passphrase: &str, params: Params, salt: [u8; SALT_LEN]) -> Result<Cipher, CryptError> {
+        if passphrase.is_empty() {
+            return Err(CryptError::EmptyPassphrase);
+        }
+        let mut key = Key::default();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|_| CryptError::InvalidHeader)?;
+        Ok(Cipher {
+            params,
+            salt,
+            aead: XChaCha20Poly1305::new(&key),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cipher = Cipher::new("correct horse").unwrap();
+
+        let contents = cipher.seal(b"ABDB plain");
+
+        assert!(is_encrypted(&contents));
+        let (_, plain) = Cipher::open(&contents, "correct horse").unwrap();
+        assert_eq!(b"ABDB plain".to_vec(), plain);
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let contents = Cipher::new("correct horse").unwrap().seal(b"ABDB plain");
+
+        assert_eq!(CryptError::WrongPassphrase, Cipher::open(&contents, "battery staple").err().unwrap());
+    }
+
+    #[test]
+    fn tampered_header() {
+        let mut contents = Cipher::new("correct horse").unwrap().seal(b"ABDB plain");
+        // Last byte of the salt
+        contents[HEADER_LEN - NONCE_LEN - 1] ^= 1;
+
+        assert_eq!(CryptError::WrongPassphrase, Cipher::open(&contents, "correct horse").err().unwrap());
+    }
+
+    #[test]
+    fn costs_over_the_limits() {
+        let contents = Cipher::new("correct horse").unwrap().seal(b"ABDB plain");
+        for (i, cost) in [MAX_M_COST + 1, MAX_T_COST + 1, MAX_P_COST + 1].into_iter().enumerate() {
+            let mut contents = contents.clone();
+            contents[MAGIC.len() + 4 * i..][..4].copy_from_slice(&cost.to_le_bytes());
+
+            assert_eq!(CryptError::InvalidHeader, Cipher::open(&contents, "correct horse").err().unwrap());
+        }
+    }
+
+    #[test]
+    fn empty_passphrase() {
+        assert_eq!(CryptError::EmptyPassphrase, Cipher::new("").err().unwrap());
+    }
+}
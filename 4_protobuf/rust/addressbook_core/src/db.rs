@@ -0,0 +1,175 @@
+// On-disk format of addressbook.db.
+//
+// Current files start with a header: the `MAGIC` bytes followed by the format
+// version as a little-endian u32, then the encoded `pb::AddressBook`.
+// Files written before the header existed are raw protobuf and count as version 0.
+// A legacy file can never start with `MAGIC`: its first byte is the key of
+// `AddressBook.contacts` (0x0A).
+
+use prost::{DecodeError, Message};
+
+use crate::error::AddressBookError;
+use crate::pb;
+
+pub const MAGIC: &[u8; 4] = b"ABDB";
+pub const CURRENT_VERSION: u32 = 3;
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// Upgrades a payload from version `n` to version `n + 1`.
+type Migration = fn(Vec<u8>) -> Result<Vec<u8>, AddressBookError>;
+
+/// `MIGRATIONS[n]` upgrades a version `n` payload. Append one entry per new
+/// format version and bump `CURRENT_VERSION`.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3];
+
+// Version 1 only adds the header; the payload is unchanged.
+fn v0_to_v1(payload: Vec<u8>) -> Result<Vec<u8>, AddressBookError> {
+    Ok(payload)
+}
+
+// Version 2 adds `AddressBook.changes`. The payload is unchanged, but the new
+// version stops older programs from rewriting the file and dropping the log.
+fn v1_to_v2(payload: Vec<u8>) -> Result<Vec<u8>, AddressBookError> {
+    Ok(payload)
+}
+
+// Version 3 moves `Person.email` into the new `Person.emails`.
+fn v2_to_v3(payload: Vec<u8>) -> Result<Vec<u8>, AddressBookError> {
+    let mut book = pb::AddressBook::decode(payload.as_slice())?;
+    book.contacts.values_mut().for_each(upgrade_contact);
+    for change in &mut book.changes {
+        change.before.iter_mut().chain(change.after.iter_mut()).for_each(upgrade_contact);
+    }
+    Ok(book.encode_to_vec())
+}
+
+/// Moves a person's legacy single `email` to the front of `emails`.
+/// Also used by the SQLite storage, which keeps contacts in the same encoding.
+pub fn upgrade_contact(contact: &mut pb::Contact) {
+    if let Some(pb::contact::Kind::Person(p)) = &mut contact.kind {
+        if !p.email.is_empty() {
+            p.emails.insert(0, std::mem::take(&mut p.email));
+        }
+    }
+}
+
+/// Splits the file contents into format version and payload.
+pub fn read_header(contents: &[u8]) -> Result<(u32, &[u8]), AddressBookError> {
+    if contents.is_empty() {
+        return Ok((CURRENT_VERSION, contents));
+    }
+    if !contents.starts_with(MAGIC) {
+        return Ok((0, contents));
+    }
+    if contents.len() < HEADER_LEN {
+        return Err(DecodeError::new("truncated header").into());
+    }
+    let mut version = [0; 4];
+    version.copy_from_slice(&contents[MAGIC.len()..HEADER_LEN]);
+    let version = u32::from_le_bytes(version);
+    Ok((version, &contents[HEADER_LEN..]))
+}
+
+/// Brings a payload of the given version up to `CURRENT_VERSION`.
+pub fn migrate(version: u32, mut payload: Vec<u8>) -> Result<Vec<u8>, AddressBookError> {
+    if version > CURRENT_VERSION {
+        return Err(AddressBookError::UnsupportedVersion(version));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        payload = migration(payload)?;
+    }
+    Ok(payload)
+}
+
+/// Decodes file contents of any supported version.
+/// Also returns the version found on disk, so callers can tell whether the file needs rewriting.
+pub fn decode(contents: &[u8]) -> Result<(u32, pb::AddressBook), AddressBookError> {
+    let (version, payload) = read_header(contents)?;
+    let payload = migrate(version, payload.to_vec())?;
+    Ok((version, pb::AddressBook::decode(payload.as_slice())?))
+}
+
+/// Encodes a book in the current format.
+pub fn encode(book: &pb::AddressBook) -> Vec<u8> {
+    let mut contents = Vec::with_capacity(HEADER_LEN + book.encoded_len());
+    contents.extend_from_slice(MAGIC);
+    contents.extend_from_slice(&CURRENT_VERSION.to_le_bytes());
+    book.encode(&mut contents).expect("Vec has enough capacity");
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::contact::Kind;
+
+    // Written by the CLI before the header existed: two `add`s for Alice and one for Acme.
+    // That code appended a whole book on every write, so this file holds three of them.
+    const V0_FIXTURE: &[u8] = include_bytes!("../tests/fixtures/v0.db");
+    // The same book, upgraded by opening it with the version 1 code.
+    const V1_FIXTURE: &[u8] = include_bytes!("../tests/fixtures/v1.db");
+
+    fn check_fixture_book(book: &pb::AddressBook) {
+        assert_eq!(2, book.contacts.len());
+
+        match &book.contacts["Alice Smith"].kind {
+            Some(Kind::Person(p)) => {
+                assert_eq!(vec!["alice@example.com"], p.emails);
+                assert!(p.email.is_empty());
+                let numbers: Vec<&str> = p.phones.iter().map(|pn| pn.number.as_str()).collect();
+                assert_eq!(vec!["+15550100", "+15550101"], numbers);
+            }
+            _ => panic!("expected a person"),
+        }
+        match &book.contacts["Acme Corp"].kind {
+            Some(Kind::Company(c)) => {
+                assert_eq!("support@acme.com", c.emails[0].email);
+                assert_eq!("+15550200", c.phones[0].number);
+            }
+            _ => panic!("expected a company"),
+        }
+        assert!(book.contacts["Acme Corp"].last_updated.is_some());
+    }
+
+    #[test]
+    fn load_v0_fixture() {
+        let (version, book) = decode(V0_FIXTURE).unwrap();
+
+        assert_eq!(0, version);
+        check_fixture_book(&book);
+    }
+
+    #[test]
+    fn load_v1_fixture() {
+        let (version, book) = decode(V1_FIXTURE).unwrap();
+
+        assert_eq!(1, version);
+        check_fixture_book(&book);
+    }
+
+    #[test]
+    fn upgrade_keeps_contents() {
+        let (_, old) = decode(V0_FIXTURE).unwrap();
+
+        let (version, new) = decode(&encode(&old)).unwrap();
+
+        assert_eq!(CURRENT_VERSION, version);
+        assert_eq!(old, new);
+    }
+
+    #[test]
+    fn empty_file() {
+        let (version, book) = decode(&[]).unwrap();
+
+        assert_eq!(CURRENT_VERSION, version);
+        assert!(book.contacts.is_empty());
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut contents = MAGIC.to_vec();
+        contents.extend_from_slice(&(CURRENT_VERSION + 1).to_le_bytes());
+
+        assert!(decode(&contents).is_err());
+    }
+}
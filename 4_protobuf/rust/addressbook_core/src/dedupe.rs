@@ -0,0 +1,316 @@
+// Finding and merging contacts that are likely the same person or company.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::pb;
+use crate::pb::contact::Kind;
+
+/// Why contacts were put in the same group.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reason {
+    /// The names are equal once normalized (see `normalize_name`).
+    SameName(String),
+    SharedPhone(String),
+    SharedEmail(String),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::SameName(name) => write!(f, "same name \"{name}\""),
+            Reason::SharedPhone(number) => write!(f, "shared phone {number}"),
+            Reason::SharedEmail(email) => write!(f, "shared email {email}"),
+        }
+    }
+}
+
+/// Contacts of the same kind that look like duplicates of each other.
+#[derive(Debug, PartialEq)]
+pub struct Group {
+    /// Sorted by name.
+    pub names: Vec<String>,
+    pub reasons: Vec<Reason>,
+}
+
+/// Lowercases the name and drops punctuation and extra spaces,
+/// so "ACME  Corp." and "Acme Corp" compare equal.
+pub fn normalize_name(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// What two contacts may share to be duplicates. The kind is part of every key,
+// as a person and a company are never merged.
+fn keys(contact: &pb::Contact, name: &str) -> Vec<(char, Reason)> {
+    let (kind, phones, emails): (char, Vec<String>, Vec<String>) = match &contact.kind {
+        Some(Kind::Person(p)) => (
+            'p',
+            p.phones.iter().map(|pn| pn.number.clone()).collect(),
+            p.emails.clone(),
+        ),
+        Some(Kind::Company(c)) => (
+            'c',
+            c.phones.iter().map(|pn| pn.number.clone()).collect(),
+            c.emails.iter().map(|em| em.email.clone()).collect(),
+        ),
+        None => return Vec::new(),
+    };
+    let mut keys = vec![(kind, Reason::SameName(normalize_name(name)))];
+    keys.extend(phones.into_iter().map(|number| (kind, Reason::SharedPhone(number))));
+    keys.extend(
+        emails
+            .into_iter()
+            .filter(|email| !email.is_empty())
+            .map(|email| (kind, Reason::SharedEmail(email.to_lowercase()))),
+    );
+    keys
+}
+
+/// Groups of two or more likely duplicates, sorted by their first name.
+pub fn find(contacts: &[(String, pb::Contact)]) -> Vec<Group> {
+    // Union-find over the contact indexes
+    let mut parent: Vec<usize> = (0..contacts.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    let mut first_with_key: HashMap<String, usize> = HashMap::new();
+    let mut links: Vec<(usize, Reason)> = Vec::new();
+    for (i, (name, contact)) in contacts.iter().enumerate() {
+        for (kind, reason) in keys(contact, name) {
+            let key = format!("{kind}{reason:?}");
+            match first_with_key.get(&key) {
+                Some(&j) => {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[a] = b;
+                    links.push((i, reason));
+                }
+                None => {
+                    first_with_key.insert(key, i);
+                }
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Group> = HashMap::new();
+    for (i, (name, _)) in contacts.iter().enumerate() {
+        let r = root(&mut parent, i);
+        let group = groups.entry(r).or_insert_with(|| Group { names: Vec::new(), reasons: Vec::new() });
+        group.names.push(name.clone());
+    }
+    for (i, reason) in links {
+        let group = groups.get_mut(&root(&mut parent, i)).expect("every contact has a group");
+        if !group.reasons.contains(&reason) {
+            group.reasons.push(reason);
+        }
+    }
+
+    let mut groups: Vec<Group> = groups.into_values().filter(|g| g.names.len() > 1).collect();
+    for group in &mut groups {
+        group.names.sort();
+    }
+    groups.sort_by(|a, b| a.names.cmp(&b.names));
+    groups
+}
+
+/// Merges contacts of the same kind into the first one: phones, emails, tags
+/// and addresses are united, notes and the person's company are the newest
+/// ones given, and `last_updated` is the newest of all. Returns `None` if the
+/// kinds differ.
+pub fn merge(contacts: &[pb::Contact]) -> Option<pb::Contact> {
+    let (first, rest) = contacts.split_first()?;
+    let mut merged = first.clone();
+    let mut newest = stamp(first);
+    for contact in rest {
+        match (&mut merged.kind, &contact.kind) {
+            (Some(Kind::Person(into)), Some(Kind::Person(from))) => {
+                for email in &from.emails {
+                    if !into.emails.iter().any(|em| em.eq_ignore_ascii_case(email)) {
+                        into.emails.push(email.clone());
+                    }
+                }
+                if !from.company.is_empty() && (into.company.is_empty() || stamp(contact) > newest) {
+                    into.company = from.company.clone();
+                }
+                for phone in &from.phones {
+                    if !into.phones.iter().any(|pn| pn.number == phone.number) {
+                        into.phones.push(phone.clone());
+                    }
+                }
+            }
+            (Some(Kind::Company(into)), Some(Kind::Company(from))) => {
+                for email in &from.emails {
+                    if !into.emails.iter().any(|em| em.email.eq_ignore_ascii_case(&email.email)) {
+                        into.emails.push(email.clone());
+                    }
+                }
+                for phone in &from.phones {
+                    if !into.phones.iter().any(|pn| pn.number == phone.number) {
+                        into.phones.push(phone.clone());
+                    }
+                }
+            }
+            _ => return None,
+        }
+        for tag in &contact.tags {
+            if !merged.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                merged.tags.push(tag.clone());
+            }
+        }
+        for address in &contact.addresses {
+            if !merged.addresses.contains(address) {
+                merged.addresses.push(address.clone());
+            }
+        }
+        if !contact.notes.is_empty() && (merged.notes.is_empty() || stamp(contact) > newest) {
+            merged.notes = contact.notes.clone();
+        }
+        if stamp(contact) > newest {
+            newest = stamp(contact);
+            merged.last_updated = contact.last_updated;
+        }
+    }
+    Some(merged)
+}
+
+// `last_updated` in a comparable form; a missing one is the oldest.
+fn stamp(contact: &pb::Contact) -> (i64, i32) {
+    contact.last_updated.map(|t| (t.seconds, t.nanos)).unwrap_or((i64::MIN, 0))
+}
+
+/// The name in `names` whose contact was updated last.
+pub fn newest<'a>(names: &'a [String], contacts: &[(String, pb::Contact)]) -> Option<&'a String> {
+    names.iter().max_by_key(|name| {
+        contacts
+            .iter()
+            .find(|(n, _)| n == *name)
+            .map(|(_, c)| stamp(c))
+            .unwrap_or((i64::MIN, 0))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::Timestamp;
+
+    fn company(seconds: i64, email: &str, phone: &str) -> pb::Contact {
+        pb::Contact {
+            last_updated: Some(Timestamp { seconds, nanos: 0 }),
+            kind: Some(Kind::Company(pb::Company {
+                emails: vec![pb::company::EmailAddress {
+                    email: email.to_string(),
+                    department: 0,
+                }],
+                phones: vec![pb::company::PhoneNumber {
+                    number: phone.to_string(),
+                    department: 0,
+                }],
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn person(seconds: i64, email: &str, phone: &str) -> pb::Contact {
+        pb::Contact {
+            last_updated: Some(Timestamp { seconds, nanos: 0 }),
+            kind: Some(Kind::Person(pb::Person {
+                emails: vec![email.to_string()],
+                phones: vec![pb::person::PhoneNumber {
+                    number: phone.to_string(),
+                    r#type: 0,
+                }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!("acme corp", normalize_name("ACME  Corp."));
+        assert_eq!(normalize_name("Acme Corp"), normalize_name(" acme-corp "));
+    }
+
+    #[test]
+    fn find_groups() {
+        let contacts = vec![
+            ("ACME Corp".to_string(), company(1, "hr@acme.com", "+15550200")),
+            ("Acme Corp.".to_string(), company(2, "cs@acme.com", "+15550201")),
+            ("Bob".to_string(), person(1, "bob@example.com", "+15550100")),
+            ("Robert".to_string(), person(2, "BOB@example.com", "+15550101")),
+            // Same email as Bob, but a company
+            ("Bob Inc".to_string(), company(1, "bob@example.com", "+15550300")),
+            ("Carol".to_string(), person(1, "carol@example.com", "+15550400")),
+        ];
+
+        let groups = find(&contacts);
+
+        assert_eq!(
+            vec![
+                Group {
+                    names: vec!["ACME Corp".to_string(), "Acme Corp.".to_string()],
+                    reasons: vec![Reason::SameName("acme corp".to_string())],
+                },
+                Group {
+                    names: vec!["Bob".to_string(), "Robert".to_string()],
+                    reasons: vec![Reason::SharedEmail("bob@example.com".to_string())],
+                },
+            ],
+            groups
+        );
+        assert_eq!("Acme Corp.", newest(&groups[0].names, &contacts).unwrap());
+    }
+
+    #[test]
+    fn merge_unions() {
+        let merged = merge(&[
+            company(1, "hr@acme.com", "+15550200"),
+            company(3, "HR@acme.com", "+15550201"),
+            company(2, "cs@acme.com", "+15550200"),
+        ])
+        .unwrap();
+
+        assert_eq!(Some(Timestamp { seconds: 3, nanos: 0 }), merged.last_updated);
+        match merged.kind {
+            Some(Kind::Company(c)) => {
+                let emails: Vec<&str> = c.emails.iter().map(|em| em.email.as_str()).collect();
+                assert_eq!(vec!["hr@acme.com", "cs@acme.com"], emails);
+                let phones: Vec<&str> = c.phones.iter().map(|pn| pn.number.as_str()).collect();
+                assert_eq!(vec!["+15550200", "+15550201"], phones);
+            }
+            _ => panic!("expected a company"),
+        }
+    }
+
+    #[test]
+    fn merge_people() {
+        let mut bob = person(2, "bob@example.com", "+15550100");
+        bob.tags = vec!["work".to_string()];
+        bob.notes = "old notes".to_string();
+        let mut rob = person(3, "rob@example.com", "+15550101");
+        rob.tags = vec!["Work".to_string(), "golf".to_string()];
+        rob.notes = "new notes".to_string();
+
+        let merged = merge(&[bob, rob]).unwrap();
+
+        assert_eq!(vec!["work", "golf"], merged.tags);
+        assert_eq!("new notes", merged.notes);
+        match merged.kind {
+            Some(Kind::Person(p)) => {
+                assert_eq!(vec!["bob@example.com", "rob@example.com"], p.emails);
+                assert_eq!(2, p.phones.len());
+            }
+            _ => panic!("expected a person"),
+        }
+        assert!(merge(&[person(1, "", ""), company(1, "", "")]).is_none());
+    }
+}
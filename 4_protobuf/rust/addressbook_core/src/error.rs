@@ -0,0 +1,102 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::crypt::CryptError;
+use crate::validate::ValidationError;
+
+#[derive(Debug)]
+pub enum AddressBookError {
+    /// The database file could not be opened, read or written.
+    Io(io::Error),
+    /// The database file is not a valid address book.
+    Decode(prost::DecodeError),
+    /// The SQLite storage failed.
+    Sqlite(rusqlite::Error),
+    /// The database file was written by a newer version of the program.
+    UnsupportedVersion(u32),
+    /// The book was opened read-only, and saving or upgrading it would change it.
+    ReadOnly,
+    /// The name already belongs to a contact of the other kind.
+    KindConflict { name: String, existing: &'static str },
+    /// No contact has this name.
+    NotFound(String),
+    /// The contact has no such email or phone entry.
+    NoSuchField(String),
+    /// Contacts to merge into this one are not all of the same kind.
+    MixedKinds(String),
+    MissingArgument(&'static str),
+    InvalidArgument { arg: &'static str, value: String },
+    Validation(ValidationError),
+    Crypt(CryptError),
+}
+
+impl fmt::Display for AddressBookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressBookError::Io(e) => write!(f, "cannot access the address book: {e}"),
+            AddressBookError::Decode(e) => write!(f, "the address book file is damaged: {e}"),
+            AddressBookError::Sqlite(e) => write!(f, "SQLite storage error: {e}"),
+            AddressBookError::UnsupportedVersion(version) => write!(
+                f,
+                "the address book has format version {version}, which is newer than this program supports"
+            ),
+            AddressBookError::ReadOnly => write!(f, "the address book is open read-only"),
+            AddressBookError::KindConflict { name, existing } => {
+                write!(f, "{name} is already saved as a {existing}")
+            }
+            AddressBookError::NotFound(name) => write!(f, "no contact named {name}"),
+            AddressBookError::NoSuchField(field) => write!(f, "no such email or phone: {field}"),
+            AddressBookError::MixedKinds(name) => {
+                write!(f, "cannot merge into {name}: a person and a company cannot be merged")
+            }
+            AddressBookError::MissingArgument(arg) => write!(f, "missing argument: {arg}"),
+            AddressBookError::InvalidArgument { arg, value } => write!(f, "invalid value for {arg}: {value}"),
+            AddressBookError::Validation(e) => e.fmt(f),
+            AddressBookError::Crypt(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for AddressBookError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AddressBookError::Io(e) => Some(e),
+            AddressBookError::Decode(e) => Some(e),
+            AddressBookError::Sqlite(e) => Some(e),
+            AddressBookError::Validation(e) => Some(e),
+            AddressBookError::Crypt(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AddressBookError {
+    fn from(e: io::Error) -> Self {
+        AddressBookError::Io(e)
+    }
+}
+
+impl From<prost::DecodeError> for AddressBookError {
+    fn from(e: prost::DecodeError) -> Self {
+        AddressBookError::Decode(e)
+    }
+}
+
+impl From<rusqlite::Error> for AddressBookError {
+    fn from(e: rusqlite::Error) -> Self {
+        AddressBookError::Sqlite(e)
+    }
+}
+
+impl From<ValidationError> for AddressBookError {
+    fn from(e: ValidationError) -> Self {
+        AddressBookError::Validation(e)
+    }
+}
+
+impl From<CryptError> for AddressBookError {
+    fn from(e: CryptError) -> Self {
+        AddressBookError::Crypt(e)
+    }
+}
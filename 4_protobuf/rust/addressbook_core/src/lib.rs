@@ -0,0 +1,59 @@
+// The address book shared by the `addressbook` and `addressbook_1` frontends:
+// the protobuf schema and its redaction, the file formats and storage backends,
+// validation, and `AddressBook`, which every command goes through.
+
+pub mod crypt;
+pub mod db;
+pub mod dedupe;
+pub mod error;
+pub mod redact;
+pub mod storage;
+mod store;
+pub mod validate;
+pub mod pb {
+    include!(concat!(env!("OUT_DIR"), "/addressbook.ab.rs"));
+}
+
+use pb::company::Department;
+use pb::person::phone_number::Type;
+
+pub use crypt::CryptError;
+pub use error::AddressBookError;
+pub use store::{default_author, AddressBook, Details, Field, MAX_CHANGES};
+
+/// The phone type named on the command line: "home", "mobile" or "work".
+/// Anything else is left unspecified.
+pub fn str_to_phone_type(s: &str) -> Type {
+    match s {
+        "home" => Type::Home,
+        "mobile" => Type::Mobile,
+        "work" => Type::Work,
+        _ => Type::Unspecified,
+    }
+}
+
+/// The department named on the command line: "hr" or "cs" (customer service).
+/// Anything else is left unspecified.
+pub fn str_to_department(s: &str) -> Department {
+    match s {
+        "hr" => Department::Hr,
+        "cs" => Department::CustomerService,
+        _ => Department::Unspecified,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_map_to_enums() {
+        assert_eq!(Type::Home, str_to_phone_type("home"));
+        assert_eq!(Type::Mobile, str_to_phone_type("mobile"));
+        assert_eq!(Type::Work, str_to_phone_type("work"));
+        assert_eq!(Type::Unspecified, str_to_phone_type("fax"));
+        assert_eq!(Department::Hr, str_to_department("hr"));
+        assert_eq!(Department::CustomerService, str_to_department("cs"));
+        assert_eq!(Department::Unspecified, str_to_department(""));
+    }
+}
@@ -0,0 +1,277 @@
+// Redaction driven by the `[debug_redact = true]` field option in addressbook.proto.
+// Works like `redact_private_info` in py/main.py: walk the message descriptors and
+// replace every annotated string with '*', so newly annotated fields need no code here.
+// The `Debug` of every message goes through it, so `{:?}` never prints those fields.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use prost::bytes::Buf;
+use prost::encoding::{decode_key, decode_varint, encode_key, encode_varint, WireType};
+use prost::{DecodeError, Message, Name};
+
+use crate::pb;
+
+const FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/addressbook_descriptor.bin"));
+
+mod descriptor;
+
+enum FieldRule {
+    Redact,
+    Message(String),
+}
+
+// Fully qualified message name (".addressbook.ab.Person") -> field number -> rule.
+// Fields without a rule are copied unchanged.
+type Rules = HashMap<String, HashMap<u32, FieldRule>>;
+
+fn rules() -> &'static Rules {
+    static RULES: OnceLock<Rules> = OnceLock::new();
+    RULES.get_or_init(|| {
+        let set = descriptor::FileDescriptorSet::decode(FILE_DESCRIPTOR_SET)
+            .expect("embedded descriptor set is valid");
+        let mut rules = Rules::new();
+        for file in &set.file {
+            let scope = if file.package.is_empty() {
+                String::new()
+            } else {
+                format!(".{}", file.package)
+            };
+            for msg in &file.message_type {
+                collect_rules(&scope, msg, &mut rules);
+            }
+        }
+        rules
+    })
+}
+
+fn collect_rules(scope: &str, msg: &descriptor::DescriptorProto, rules: &mut Rules) {
+    let full_name = format!("{}.{}", scope, msg.name);
+    let mut fields = HashMap::new();
+    for field in &msg.field {
+        let redact = field.options.as_ref().is_some_and(|o| o.debug_redact);
+        if redact && field.r#type == descriptor::TYPE_STRING {
+            fields.insert(field.number as u32, FieldRule::Redact);
+        } else if field.r#type == descriptor::TYPE_MESSAGE {
+            fields.insert(field.number as u32, FieldRule::Message(field.type_name.clone()));
+        }
+    }
+    for nested in &msg.nested_type {
+        collect_rules(&full_name, nested, rules);
+    }
+    rules.insert(full_name, fields);
+}
+
+/// Returns a copy of `msg` with every `debug_redact` string replaced by '*'.
+pub fn redact<M: Message + Name + Default>(msg: &M) -> M {
+    M::decode(redacted_bytes(msg).as_slice()).expect("redacted message is valid")
+}
+
+// `msg` encoded, with every `debug_redact` string replaced by '*'.
+fn redacted_bytes<M: Message + Name>(msg: &M) -> Vec<u8> {
+    let type_name = format!(".{}", M::full_name());
+    let mut out = Vec::new();
+    redact_message(rules(), &type_name, &msg.encode_to_vec(), &mut out)
+        .expect("re-encoding a valid message");
+    out
+}
+
+// Copies an encoded message field by field, rewriting redacted strings and
+// descending into nested messages (including map entries) known to the descriptors.
+fn redact_message(
+    rules: &Rules,
+    type_name: &str,
+    mut buf: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), DecodeError> {
+    let fields = rules.get(type_name);
+    while buf.has_remaining() {
+        let (tag, wire_type) = decode_key(&mut buf)?;
+        encode_key(tag, wire_type, out);
+        match wire_type {
+            WireType::Varint => encode_varint(decode_varint(&mut buf)?, out),
+            WireType::SixtyFourBit => copy_bytes(&mut buf, 8, out)?,
+            WireType::ThirtyTwoBit => copy_bytes(&mut buf, 4, out)?,
+            WireType::LengthDelimited => {
+                let len = decode_varint(&mut buf)? as usize;
+                if buf.remaining() < len {
+                    return Err(DecodeError::new("buffer underflow"));
+                }
+                let (value, rest) = buf.split_at(len);
+                buf = rest;
+                match fields.and_then(|f| f.get(&tag)) {
+                    Some(FieldRule::Redact) => {
+                        let stars: String = String::from_utf8_lossy(value).chars().map(|_| '*').collect();
+                        encode_varint(stars.len() as u64, out);
+                        out.extend_from_slice(stars.as_bytes());
+                    }
+                    Some(FieldRule::Message(nested)) => {
+                        let mut inner = Vec::new();
+                        redact_message(rules, nested, value, &mut inner)?;
+                        encode_varint(inner.len() as u64, out);
+                        out.extend_from_slice(&inner);
+                    }
+                    None => {
+                        encode_varint(len as u64, out);
+                        out.extend_from_slice(value);
+                    }
+                }
+            }
+            WireType::StartGroup | WireType::EndGroup => {
+                return Err(DecodeError::new("groups are not supported"))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn copy_bytes(buf: &mut &[u8], n: usize, out: &mut Vec<u8>) -> Result<(), DecodeError> {
+    if buf.remaining() < n {
+        return Err(DecodeError::new("buffer underflow"));
+    }
+    out.extend_from_slice(&buf[..n]);
+    buf.advance(n);
+    Ok(())
+}
+
+// The same types with the derived `Debug`, which the ones of `pb` print once redacted.
+mod plain {
+    include!(concat!(env!("OUT_DIR"), "/plain/addressbook.ab.rs"));
+}
+
+// A message prints as its redacted copy, decoded as its `plain` twin.
+macro_rules! redacted_debug {
+    ($($message:ty => $plain:ty),* $(,)?) => {$(
+        impl fmt::Debug for $message {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let plain = <$plain>::decode(redacted_bytes(self).as_slice()).expect("redacted message is valid");
+                fmt::Debug::fmt(&plain, f)
+            }
+        }
+    )*};
+}
+
+// An enum has nothing to hide, it prints as its `plain` twin.
+macro_rules! plain_debug {
+    ($($enumeration:ty => $plain:ty),* $(,)?) => {$(
+        impl fmt::Debug for $enumeration {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let plain = <$plain>::try_from(*self as i32).expect("same values in both enums");
+                fmt::Debug::fmt(&plain, f)
+            }
+        }
+    )*};
+}
+
+// A oneof is no message of its own: it prints as the field of a redacted message.
+macro_rules! oneof_debug {
+    ($($oneof:ty => $message:ty, $plain:ty, $field:ident),* $(,)?) => {$(
+        impl fmt::Debug for $oneof {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                type Message = $message;
+                let message = Message { $field: Some(self.clone()), ..Default::default() };
+                let plain = <$plain>::decode(redacted_bytes(&message).as_slice()).expect("redacted message is valid");
+                fmt::Debug::fmt(&plain.$field.expect("the oneof is set"), f)
+            }
+        }
+    )*};
+}
+
+// Every message, enum and oneof of addressbook.proto, listed by build.rs.
+include!(concat!(env!("OUT_DIR"), "/redact_debug.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_person() {
+        let person = pb::Person {
+            emails: vec!["bob@example.com".to_string()],
+            phones: vec![pb::person::PhoneNumber {
+                number: "+1 555".to_string(),
+                r#type: 1,
+            }],
+            ..Default::default()
+        };
+
+        let redacted = redact(&person);
+
+        assert_eq!("***************", redacted.emails[0]);
+        assert_eq!("******", redacted.phones[0].number);
+        assert_eq!(1, redacted.phones[0].r#type);
+    }
+
+    #[test]
+    fn redact_nested_in_book() {
+        let company = pb::Company {
+            emails: vec![pb::company::EmailAddress {
+                email: "hr@acme.com".to_string(),
+                department: 1,
+            }],
+            phones: vec![],
+        };
+        let address = pb::PostalAddress {
+            street: "1 Main St".to_string(),
+            city: "Springfield".to_string(),
+            ..Default::default()
+        };
+        let mut book = pb::AddressBook::default();
+        book.contacts.insert(
+            "Acme".to_string(),
+            pb::Contact {
+                last_updated: Some(prost_types::Timestamp { seconds: 10, nanos: 5 }),
+                kind: Some(pb::contact::Kind::Company(company)),
+                notes: "call before noon".to_string(),
+                addresses: vec![address],
+                ..Default::default()
+            },
+        );
+
+        let redacted = redact(&book);
+        let contact = &redacted.contacts["Acme"];
+
+        assert_eq!(Some(prost_types::Timestamp { seconds: 10, nanos: 5 }), contact.last_updated);
+        match &contact.kind {
+            Some(pb::contact::Kind::Company(c)) => assert_eq!("***********", c.emails[0].email),
+            _ => panic!("expected a company"),
+        }
+        assert_eq!("****************", contact.notes);
+        assert_eq!("*********", contact.addresses[0].street);
+        assert_eq!("Springfield", contact.addresses[0].city);
+    }
+
+    #[test]
+    fn debug_is_redacted() {
+        let contact = pb::Contact {
+            kind: Some(pb::contact::Kind::Person(pb::Person {
+                emails: vec!["bob@example.com".to_string()],
+                phones: vec![pb::person::PhoneNumber {
+                    number: "+1 555".to_string(),
+                    r#type: pb::person::phone_number::Type::Mobile as i32,
+                }],
+                ..Default::default()
+            })),
+            notes: "call before noon".to_string(),
+            addresses: vec![pb::PostalAddress {
+                street: "1 Main St".to_string(),
+                city: "Springfield".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        for output in [format!("{:?}", contact), format!("{:#?}", contact)] {
+            for private in ["bob@example.com", "+1 555", "call before noon", "1 Main St"] {
+                assert!(!output.contains(private), "{private} in {output}");
+            }
+            assert!(output.contains("***************"));
+            assert!(output.contains("Springfield"));
+            assert!(output.contains("Mobile"));
+        }
+        assert!(!format!("{:?}", contact.kind).contains("bob@example.com"));
+        assert_eq!("Mobile", format!("{:?}", pb::person::phone_number::Type::Mobile));
+    }
+}
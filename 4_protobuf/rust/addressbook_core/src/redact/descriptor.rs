@@ -0,0 +1,80 @@
+// Minimal mirror of google/protobuf/descriptor.proto, shared with build.rs.
+// prost_types::FieldOptions has no `debug_redact` field, so it would be dropped on decode.
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    pub file: Vec<FileDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FileDescriptorProto {
+    #[prost(string, tag = "2")]
+    pub package: String,
+    #[prost(message, repeated, tag = "4")]
+    pub message_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "5")]
+    pub enum_type: Vec<EnumDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub field: Vec<FieldDescriptorProto>,
+    #[prost(message, repeated, tag = "3")]
+    pub nested_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "4")]
+    pub enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, optional, tag = "7")]
+    pub options: Option<MessageOptions>,
+    #[prost(message, repeated, tag = "8")]
+    pub oneof_decl: Vec<OneofDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FieldDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(int32, tag = "3")]
+    pub number: i32,
+    #[prost(int32, tag = "5")]
+    pub r#type: i32,
+    #[prost(string, tag = "6")]
+    pub type_name: String,
+    #[prost(message, optional, tag = "8")]
+    pub options: Option<FieldOptions>,
+    #[prost(int32, optional, tag = "9")]
+    pub oneof_index: Option<i32>,
+    #[prost(bool, tag = "17")]
+    pub proto3_optional: bool,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FieldOptions {
+    #[prost(bool, tag = "16")]
+    pub debug_redact: bool,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MessageOptions {
+    #[prost(bool, tag = "7")]
+    pub map_entry: bool,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EnumDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct OneofDescriptorProto {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+// FieldDescriptorProto.Type
+pub const TYPE_STRING: i32 = 9;
+pub const TYPE_MESSAGE: i32 = 11;
@@ -0,0 +1,365 @@
+// Backends that keep the contacts of an `AddressBook`.
+
+mod proto;
+mod sqlite;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::crypt::CryptError;
+use crate::error::AddressBookError;
+use crate::pb;
+
+pub use proto::ProtoStorage;
+pub use sqlite::SqliteStorage;
+
+pub trait Storage: Send {
+    fn get(&self, name: &str) -> Result<Option<pb::Contact>, AddressBookError>;
+
+    /// Inserts or replaces the contact stored under `name`.
+    fn put(&mut self, name: &str, contact: &pb::Contact) -> Result<(), AddressBookError>;
+
+    fn remove(&mut self, name: &str) -> Result<Option<pb::Contact>, AddressBookError>;
+
+    /// All contacts sorted by name.
+    fn list(&self) -> Result<Vec<(String, pb::Contact)>, AddressBookError>;
+
+    /// Name of the contact that has the E.164 phone `number`, if any.
+    fn find_phone(&self, number: &str) -> Result<Option<String>, AddressBookError>;
+
+    /// Appends a change to the log and returns the id it was given.
+    /// The `id` set on `change` is ignored.
+    fn log(&mut self, change: pb::Change) -> Result<u64, AddressBookError>;
+
+    /// The whole change log, oldest first.
+    fn changes(&self) -> Result<Vec<pb::Change>, AddressBookError>;
+
+    /// Drops the oldest changes so that at most `keep` remain. The ids of the
+    /// others do not change, and new changes never reuse a dropped id.
+    fn trim_log(&mut self, keep: usize) -> Result<(), AddressBookError>;
+
+    /// Makes every change since the last flush durable.
+    fn flush(&mut self) -> Result<(), AddressBookError>;
+
+    /// Drops every change since the last flush, e.g. after it failed.
+    fn discard(&mut self) -> Result<(), AddressBookError>;
+
+    /// Encrypts the storage with `passphrase` from the next flush on, or
+    /// stores it in plain when `None`.
+    fn set_passphrase(&mut self, _passphrase: Option<&str>) -> Result<(), AddressBookError> {
+        Err(CryptError::Unsupported.into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    /// The whole book encoded as one protobuf message (see `db`).
+    Proto,
+    /// One SQLite row per contact. SQLite files cannot be encrypted: they
+    /// are kept in plain, and opening one with a passphrase fails.
+    Sqlite,
+}
+
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+impl Backend {
+    /// Picks the backend of an existing file from its contents, and of a new
+    /// file from its extension (".sqlite" or ".sqlite3", protobuf otherwise).
+    pub fn detect(path: &Path) -> Result<Backend, AddressBookError> {
+        let mut magic = [0; SQLITE_MAGIC.len()];
+        match fs::File::open(path) {
+            Ok(mut f) => {
+                let n = f.read(&mut magic)?;
+                if n == magic.len() && &magic == SQLITE_MAGIC {
+                    Ok(Backend::Sqlite)
+                } else {
+                    Ok(Backend::Proto)
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("sqlite") | Some("sqlite3") => Ok(Backend::Sqlite),
+                    _ => Ok(Backend::Proto),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Opens the storage at `path`. `passphrase` is required for encrypted files
+/// and only decrypts them: plain files, new ones included, ignore it.
+/// `Storage::set_passphrase` encrypts. SQLite files cannot be encrypted, so
+/// they refuse a passphrase rather than keep the contacts in plain unnoticed.
+pub fn open(path: &Path, backend: Backend, passphrase: Option<&str>) -> Result<Box<dyn Storage>, AddressBookError> {
+    Ok(match (backend, passphrase) {
+        (Backend::Proto, _) => Box::new(ProtoStorage::open(path, passphrase)?),
+        (Backend::Sqlite, None) => Box::new(SqliteStorage::open(path)?),
+        (Backend::Sqlite, Some(_)) => return Err(CryptError::Unsupported.into()),
+    })
+}
+
+/// Opens the storage at `path` like `open`, but never writes to the file:
+/// flushing changes fails with `AddressBookError::ReadOnly`, and so does
+/// opening a SQLite file that needs an upgrade.
+pub fn open_read_only(
+    path: &Path,
+    backend: Backend,
+    passphrase: Option<&str>,
+) -> Result<Box<dyn Storage>, AddressBookError> {
+    Ok(match (backend, passphrase) {
+        (Backend::Proto, _) => Box::new(ProtoStorage::open_read_only(path, passphrase)?),
+        (Backend::Sqlite, None) => Box::new(SqliteStorage::open_read_only(path)?),
+        (Backend::Sqlite, Some(_)) => return Err(CryptError::Unsupported.into()),
+    })
+}
+
+/// Copies every contact and the change log from one storage to another and
+/// flushes the destination. Contacts already in the destination under the
+/// same name are replaced; the log is appended to the destination's.
+pub fn copy(from: &dyn Storage, to: &mut dyn Storage) -> Result<usize, AddressBookError> {
+    let contacts = from.list()?;
+    for (name, contact) in &contacts {
+        to.put(name, contact)?;
+    }
+    // Ids may shift in the destination, so undo links are renumbered.
+    let mut ids = HashMap::new();
+    for mut change in from.changes()? {
+        let id = change.id;
+        change.undoes = ids.get(&change.undoes).copied().unwrap_or(0);
+        ids.insert(id, to.log(change)?);
+    }
+    to.flush()?;
+    Ok(contacts.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::contact::Kind;
+
+    fn contact(number: &str) -> pb::Contact {
+        pb::Contact {
+            last_updated: Some(prost_types::Timestamp { seconds: 1_700_000_000, nanos: 42 }),
+            kind: Some(Kind::Person(pb::Person {
+                emails: vec!["bob@example.com".to_string()],
+                phones: vec![pb::person::PhoneNumber {
+                    number: number.to_string(),
+                    r#type: 1,
+                }],
+                ..Default::default()
+            })),
+            tags: vec!["work".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn check_storage(storage: &mut dyn Storage) {
+        storage.put("Bob", &contact("+12015550123")).unwrap();
+        storage.put("Al", &contact("+12015550124")).unwrap();
+        storage.flush().unwrap();
+
+        assert_eq!(Some(contact("+12015550123")), storage.get("Bob").unwrap());
+        assert_eq!(None, storage.get("Carol").unwrap());
+        let names: Vec<String> = storage.list().unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(vec!["Al", "Bob"], names);
+        assert_eq!(Some("Bob".to_string()), storage.find_phone("+12015550123").unwrap());
+        assert_eq!(None, storage.find_phone("+12015550125").unwrap());
+
+        storage.put("Bob", &contact("+12015550125")).unwrap();
+        assert_eq!(None, storage.find_phone("+12015550123").unwrap());
+        assert_eq!(Some("Bob".to_string()), storage.find_phone("+12015550125").unwrap());
+
+        assert!(storage.remove("Bob").unwrap().is_some());
+        assert_eq!(None, storage.find_phone("+12015550125").unwrap());
+        assert!(storage.remove("Bob").unwrap().is_none());
+
+        assert_eq!(1, storage.log(change("Bob", 0)).unwrap());
+        assert_eq!(2, storage.log(change("Al", 1)).unwrap());
+        storage.flush().unwrap();
+        let changes = storage.changes().unwrap();
+        assert_eq!(vec![1, 2], changes.iter().map(|c| c.id).collect::<Vec<_>>());
+        assert_eq!("Al", changes[1].name);
+        assert_eq!(1, changes[1].undoes);
+
+        storage.put("Carol", &contact("+12015550126")).unwrap();
+        storage.remove("Al").unwrap();
+        storage.log(change("Carol", 0)).unwrap();
+        storage.discard().unwrap();
+        assert_eq!(None, storage.get("Carol").unwrap());
+        assert_eq!(Some(contact("+12015550124")), storage.get("Al").unwrap());
+        assert_eq!(2, storage.changes().unwrap().len());
+
+        storage.trim_log(1).unwrap();
+        assert_eq!(3, storage.log(change("Carol", 0)).unwrap());
+        storage.flush().unwrap();
+        let changes = storage.changes().unwrap();
+        assert_eq!(vec![2, 3], changes.iter().map(|c| c.id).collect::<Vec<_>>());
+    }
+
+    fn change(name: &str, undoes: u64) -> pb::Change {
+        pb::Change {
+            name: name.to_string(),
+            author: "tester".to_string(),
+            after: Some(contact("+12015550123")),
+            undoes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn proto_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = ProtoStorage::open(&dir.path().join("addressbook.db"), None).unwrap();
+
+        check_storage(&mut storage);
+    }
+
+    #[test]
+    fn sqlite_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = SqliteStorage::open(&dir.path().join("addressbook.sqlite")).unwrap();
+
+        check_storage(&mut storage);
+    }
+
+    #[test]
+    fn copy_between_backends() {
+        let dir = tempfile::tempdir().unwrap();
+        let proto_path = dir.path().join("addressbook.db");
+        let sqlite_path = dir.path().join("addressbook.sqlite");
+        let mut proto = open(&proto_path, Backend::detect(&proto_path).unwrap(), None).unwrap();
+        proto.put("Bob", &contact("+12015550123")).unwrap();
+        proto.log(change("Bob", 0)).unwrap();
+        proto.log(change("Bob", 1)).unwrap();
+        proto.flush().unwrap();
+
+        let mut sqlite = open(&sqlite_path, Backend::detect(&sqlite_path).unwrap(), None).unwrap();
+        assert_eq!(1, copy(proto.as_ref(), sqlite.as_mut()).unwrap());
+        drop(sqlite);
+
+        assert_eq!(Backend::Sqlite, Backend::detect(&sqlite_path).unwrap());
+        assert_eq!(Backend::Proto, Backend::detect(&proto_path).unwrap());
+        let sqlite = SqliteStorage::open(&sqlite_path).unwrap();
+        assert_eq!(proto.list().unwrap(), sqlite.list().unwrap());
+        assert_eq!(proto.changes().unwrap(), sqlite.changes().unwrap());
+    }
+
+    #[test]
+    fn encrypted_proto_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.db");
+        // A passphrase does not encrypt a new file
+        let mut storage = ProtoStorage::open(&path, Some("correct horse")).unwrap();
+        check_storage(&mut storage);
+        assert!(!crate::crypt::is_encrypted(&fs::read(&path).unwrap()));
+        storage.set_passphrase(Some("correct horse")).unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+
+        assert!(!fs::read(&path).unwrap().windows(7).any(|w| w == b"example"));
+        assert!(matches!(
+            ProtoStorage::open(&path, None),
+            Err(AddressBookError::Crypt(CryptError::PassphraseRequired))
+        ));
+        assert!(matches!(
+            ProtoStorage::open(&path, Some("battery staple")),
+            Err(AddressBookError::Crypt(CryptError::WrongPassphrase))
+        ));
+        let mut storage = ProtoStorage::open(&path, Some("correct horse")).unwrap();
+        assert_eq!(1, storage.list().unwrap().len());
+
+        storage.set_passphrase(None).unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+        assert_eq!(1, ProtoStorage::open(&path, None).unwrap().list().unwrap().len());
+        // Nor does it stop a plain file from opening
+        assert_eq!(1, ProtoStorage::open(&path, Some("correct horse")).unwrap().list().unwrap().len());
+    }
+
+    #[test]
+    fn passphrase_refused_by_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.sqlite");
+
+        for result in [
+            open(&path, Backend::Sqlite, Some("correct horse")),
+            open_read_only(&path, Backend::Sqlite, Some("correct horse")),
+        ] {
+            assert!(matches!(result, Err(AddressBookError::Crypt(CryptError::Unsupported))));
+        }
+        let mut storage = open(&path, Backend::Sqlite, None).unwrap();
+        storage.put("Bob", &contact("+12015550123")).unwrap();
+        assert!(matches!(
+            storage.set_passphrase(Some("correct horse")),
+            Err(AddressBookError::Crypt(CryptError::Unsupported))
+        ));
+    }
+
+    #[test]
+    fn sqlite_rolls_back_without_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.sqlite");
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        storage.put("Bob", &contact("+12015550123")).unwrap();
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(None, storage.get("Bob").unwrap());
+    }
+
+    #[test]
+    fn sqlite_upgrades_legacy_email() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.sqlite");
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        let mut legacy = contact("+12015550123");
+        if let Some(Kind::Person(p)) = &mut legacy.kind {
+            p.emails.clear();
+            p.email = "bob@example.com".to_string();
+        }
+        storage.put("Bob", &legacy).unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+        rusqlite::Connection::open(&path).unwrap().execute_batch("PRAGMA user_version = 2").unwrap();
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(Some(contact("+12015550123")), storage.get("Bob").unwrap());
+    }
+
+    #[test]
+    fn read_only_proto_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.db");
+        let legacy = include_bytes!("../../tests/fixtures/v0.db");
+        fs::write(&path, legacy).unwrap();
+
+        let mut storage = open_read_only(&path, Backend::Proto, None).unwrap();
+        assert!(!storage.list().unwrap().is_empty());
+        storage.flush().unwrap();
+        storage.put("Bob", &contact("+12015550123")).unwrap();
+
+        assert!(matches!(storage.flush(), Err(AddressBookError::ReadOnly)));
+        assert_eq!(legacy.as_slice(), fs::read(&path).unwrap());
+    }
+
+    #[test]
+    fn read_only_sqlite_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addressbook.sqlite");
+        assert!(open_read_only(&path, Backend::Sqlite, None).is_err());
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        storage.put("Bob", &contact("+12015550123")).unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+
+        let mut storage = open_read_only(&path, Backend::Sqlite, None).unwrap();
+        assert_eq!(Some(contact("+12015550123")), storage.get("Bob").unwrap());
+        assert!(storage.put("Al", &contact("+12015550124")).is_err());
+
+        rusqlite::Connection::open(&path).unwrap().execute_batch("PRAGMA user_version = 2").unwrap();
+        assert!(matches!(open_read_only(&path, Backend::Sqlite, None), Err(AddressBookError::ReadOnly)));
+    }
+}
@@ -0,0 +1,173 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::Storage;
+use crate::crypt::{self, Cipher, CryptError};
+use crate::db;
+use crate::error::AddressBookError;
+use crate::pb;
+use crate::pb::contact::Kind;
+
+/// The original addressbook.db format: the whole book is kept in memory and
+/// re-encoded into the file on every flush, encrypted if it has a passphrase
+/// (see `crypt`).
+pub struct ProtoStorage {
+    path: PathBuf,
+    book: pb::AddressBook,
+    dirty: bool,
+    cipher: Option<Cipher>,
+    // The book and cipher as last flushed, kept from the first change after
+    // a flush so `discard` can go back to them.
+    flushed: Option<(pb::AddressBook, Option<Cipher>)>,
+    read_only: bool,
+}
+
+impl ProtoStorage {
+    /// Loads the book at `path`, or starts an empty one if the file does not exist.
+    /// Files written by an older version are upgraded in place.
+    ///
+    /// `passphrase` is required for an encrypted file and only decrypts it:
+    /// plain and new files stay plain until `set_passphrase` is called.
+    pub fn open(path: &Path, passphrase: Option<&str>) -> Result<ProtoStorage, AddressBookError> {
+        let mut storage = ProtoStorage::load(path, passphrase)?;
+        storage.flush()?;
+        Ok(storage)
+    }
+
+    /// Loads the book at `path` like `open`, but never writes it: an older
+    /// file is only upgraded in memory, and `flush` fails if anything changed.
+    pub fn open_read_only(path: &Path, passphrase: Option<&str>) -> Result<ProtoStorage, AddressBookError> {
+        let mut storage = ProtoStorage::load(path, passphrase)?;
+        storage.dirty = false;
+        storage.read_only = true;
+        Ok(storage)
+    }
+
+    fn load(path: &Path, passphrase: Option<&str>) -> Result<ProtoStorage, AddressBookError> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let (cipher, contents) = match (crypt::is_encrypted(&contents), passphrase) {
+            (true, Some(passphrase)) => {
+                let (cipher, plain) = Cipher::open(&contents, passphrase)?;
+                (Some(cipher), plain)
+            }
+            (true, None) => return Err(CryptError::PassphraseRequired.into()),
+            (false, _) => (None, contents),
+        };
+        let (version, book) = db::decode(&contents)?;
+        Ok(ProtoStorage {
+            path: path.to_path_buf(),
+            book,
+            dirty: version < db::CURRENT_VERSION,
+            cipher,
+            flushed: None,
+            read_only: false,
+        })
+    }
+
+    // Called before every change, to keep what was flushed.
+    fn touch(&mut self) {
+        if self.flushed.is_none() {
+            self.flushed = Some((self.book.clone(), self.cipher.clone()));
+        }
+        self.dirty = true;
+    }
+}
+
+impl Storage for ProtoStorage {
+    fn get(&self, name: &str) -> Result<Option<pb::Contact>, AddressBookError> {
+        Ok(self.book.contacts.get(name).cloned())
+    }
+
+    fn put(&mut self, name: &str, contact: &pb::Contact) -> Result<(), AddressBookError> {
+        self.touch();
+        self.book.contacts.insert(name.to_string(), contact.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Result<Option<pb::Contact>, AddressBookError> {
+        if self.book.contacts.contains_key(name) {
+            self.touch();
+        }
+        Ok(self.book.contacts.remove(name))
+    }
+
+    fn list(&self) -> Result<Vec<(String, pb::Contact)>, AddressBookError> {
+        let mut contacts: Vec<(String, pb::Contact)> =
+            self.book.contacts.iter().map(|(name, c)| (name.clone(), c.clone())).collect();
+        contacts.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(contacts)
+    }
+
+    fn find_phone(&self, number: &str) -> Result<Option<String>, AddressBookError> {
+        let owner = self.book.contacts.iter().find(|(_, contact)| match &contact.kind {
+            Some(Kind::Person(p)) => p.phones.iter().any(|pn| pn.number == number),
+            Some(Kind::Company(c)) => c.phones.iter().any(|pn| pn.number == number),
+            None => false,
+        });
+        Ok(owner.map(|(name, _)| name.clone()))
+    }
+
+    fn log(&mut self, mut change: pb::Change) -> Result<u64, AddressBookError> {
+        self.touch();
+        change.id = self.book.changes.last().map_or(1, |last| last.id + 1);
+        let id = change.id;
+        self.book.changes.push(change);
+        Ok(id)
+    }
+
+    fn changes(&self) -> Result<Vec<pb::Change>, AddressBookError> {
+        Ok(self.book.changes.clone())
+    }
+
+    fn trim_log(&mut self, keep: usize) -> Result<(), AddressBookError> {
+        let excess = self.book.changes.len().saturating_sub(keep);
+        if excess > 0 {
+            self.touch();
+            self.book.changes.drain(..excess);
+        }
+        Ok(())
+    }
+
+    /// The new contents are written to a temporary file first, so a failed
+    /// flush leaves the old book intact.
+    fn flush(&mut self) -> Result<(), AddressBookError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if self.read_only {
+            return Err(AddressBookError::ReadOnly);
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let contents = db::encode(&self.book);
+        match &self.cipher {
+            Some(cipher) => fs::write(&tmp, cipher.seal(&contents))?,
+            None => fs::write(&tmp, contents)?,
+        }
+        fs::rename(&tmp, &self.path)?;
+        self.dirty = false;
+        self.flushed = None;
+        Ok(())
+    }
+
+    fn discard(&mut self) -> Result<(), AddressBookError> {
+        if let Some((book, cipher)) = self.flushed.take() {
+            self.book = book;
+            self.cipher = cipher;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn set_passphrase(&mut self, passphrase: Option<&str>) -> Result<(), AddressBookError> {
+        let cipher = passphrase.map(Cipher::new).transpose()?;
+        self.touch();
+        self.cipher = cipher;
+        Ok(())
+    }
+}
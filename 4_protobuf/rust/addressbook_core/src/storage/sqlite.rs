@@ -0,0 +1,236 @@
+use std::path::Path;
+
+use prost::Message;
+use prost_types::Timestamp;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+
+use super::Storage;
+use crate::db;
+use crate::error::AddressBookError;
+use crate::pb;
+use crate::pb::contact::Kind;
+
+// `contact` holds the encoded `pb::Contact`; `name` and `last_updated`
+// (nanoseconds since the Unix epoch) are copied out of it so they can be indexed.
+// `phones` indexes every number for duplicate detection.
+// `changes` is the change log; its row id is the change id.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS contacts (
+        name TEXT PRIMARY KEY NOT NULL,
+        last_updated INTEGER,
+        contact BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS contacts_last_updated ON contacts (last_updated);
+    CREATE TABLE IF NOT EXISTS phones (
+        number TEXT NOT NULL,
+        name TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS phones_number ON phones (number);
+    CREATE INDEX IF NOT EXISTS phones_name ON phones (name);
+    CREATE TABLE IF NOT EXISTS changes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        change BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS changes_name ON changes (name);
+";
+// Version 2 added `changes`; version 3 moved `Person.email` into `Person.emails`.
+const SCHEMA_VERSION: u32 = 3;
+
+/// Embedded SQLite database with one row per contact, so a change only
+/// writes the rows it touches.
+///
+/// Changes are made inside a transaction that `flush` commits; dropping the
+/// storage without flushing rolls them back.
+pub struct SqliteStorage {
+    conn: Connection,
+    in_transaction: bool,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<SqliteStorage, AddressBookError> {
+        let conn = Connection::open(path)?;
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(AddressBookError::UnsupportedVersion(version));
+        }
+        conn.execute_batch("BEGIN")?;
+        conn.execute_batch(SCHEMA)?;
+        if version < 3 {
+            upgrade_blobs(
+                &conn,
+                "SELECT name, contact FROM contacts",
+                "UPDATE contacts SET contact = ?2 WHERE name = ?1",
+                |contact| {
+                    let mut contact = pb::Contact::decode(contact)?;
+                    db::upgrade_contact(&mut contact);
+                    Ok(contact.encode_to_vec())
+                },
+            )?;
+            upgrade_blobs(
+                &conn,
+                "SELECT id, change FROM changes",
+                "UPDATE changes SET change = ?2 WHERE id = ?1",
+                |change| {
+                    let mut change = pb::Change::decode(change)?;
+                    change.before.iter_mut().chain(change.after.iter_mut()).for_each(db::upgrade_contact);
+                    Ok(change.encode_to_vec())
+                },
+            )?;
+        }
+        conn.execute_batch(&format!("PRAGMA user_version = {SCHEMA_VERSION}; COMMIT"))?;
+        Ok(SqliteStorage { conn, in_transaction: false })
+    }
+
+    /// Opens the database at `path` without ever writing it. It must exist
+    /// and have the current schema, as upgrading it would be a write.
+    pub fn open_read_only(path: &Path) -> Result<SqliteStorage, AddressBookError> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        match version {
+            SCHEMA_VERSION => Ok(SqliteStorage { conn, in_transaction: false }),
+            version if version > SCHEMA_VERSION => Err(AddressBookError::UnsupportedVersion(version)),
+            _ => Err(AddressBookError::ReadOnly),
+        }
+    }
+
+    fn begin(&mut self) -> Result<(), AddressBookError> {
+        if !self.in_transaction {
+            self.conn.execute_batch("BEGIN")?;
+            self.in_transaction = true;
+        }
+        Ok(())
+    }
+}
+
+// Rewrites every blob selected by `select` (key, blob) with `update` (?1 key, ?2 blob).
+fn upgrade_blobs(
+    conn: &Connection,
+    select: &str,
+    update: &str,
+    upgrade: impl Fn(&[u8]) -> Result<Vec<u8>, AddressBookError>,
+) -> Result<(), AddressBookError> {
+    let rows: Vec<(rusqlite::types::Value, Vec<u8>)> = conn
+        .prepare(select)?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    for (key, blob) in rows {
+        conn.execute(update, params![key, upgrade(&blob)?])?;
+    }
+    Ok(())
+}
+
+fn timestamp_nanos(ts: &Option<Timestamp>) -> Option<i64> {
+    ts.as_ref().map(|ts| ts.seconds * 1_000_000_000 + ts.nanos as i64)
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, name: &str) -> Result<Option<pb::Contact>, AddressBookError> {
+        let contact: Option<Vec<u8>> = self
+            .conn
+            .query_row("SELECT contact FROM contacts WHERE name = ?1", [name], |row| row.get(0))
+            .optional()?;
+        match contact {
+            Some(contact) => Ok(Some(pb::Contact::decode(contact.as_slice())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&mut self, name: &str, contact: &pb::Contact) -> Result<(), AddressBookError> {
+        self.begin()?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO contacts (name, last_updated, contact) VALUES (?1, ?2, ?3)",
+            params![name, timestamp_nanos(&contact.last_updated), contact.encode_to_vec()],
+        )?;
+        self.conn.execute("DELETE FROM phones WHERE name = ?1", [name])?;
+        let numbers: Vec<&str> = match &contact.kind {
+            Some(Kind::Person(p)) => p.phones.iter().map(|pn| pn.number.as_str()).collect(),
+            Some(Kind::Company(c)) => c.phones.iter().map(|pn| pn.number.as_str()).collect(),
+            None => Vec::new(),
+        };
+        for number in numbers {
+            self.conn.execute("INSERT INTO phones (number, name) VALUES (?1, ?2)", [number, name])?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Result<Option<pb::Contact>, AddressBookError> {
+        let contact = self.get(name)?;
+        if contact.is_some() {
+            self.begin()?;
+            self.conn.execute("DELETE FROM contacts WHERE name = ?1", [name])?;
+            self.conn.execute("DELETE FROM phones WHERE name = ?1", [name])?;
+        }
+        Ok(contact)
+    }
+
+    fn list(&self) -> Result<Vec<(String, pb::Contact)>, AddressBookError> {
+        let mut stmt = self.conn.prepare("SELECT name, contact FROM contacts ORDER BY name")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut contacts = Vec::new();
+        for row in rows {
+            let (name, contact): (String, Vec<u8>) = row?;
+            contacts.push((name, pb::Contact::decode(contact.as_slice())?));
+        }
+        Ok(contacts)
+    }
+
+    fn find_phone(&self, number: &str) -> Result<Option<String>, AddressBookError> {
+        Ok(self
+            .conn
+            .query_row("SELECT name FROM phones WHERE number = ?1 LIMIT 1", [number], |row| row.get(0))
+            .optional()?)
+    }
+
+    fn log(&mut self, mut change: pb::Change) -> Result<u64, AddressBookError> {
+        self.begin()?;
+        // The row id is only known after the insert, so the blob is stored without it
+        change.id = 0;
+        self.conn.execute(
+            "INSERT INTO changes (name, change) VALUES (?1, ?2)",
+            params![change.name, change.encode_to_vec()],
+        )?;
+        Ok(self.conn.last_insert_rowid() as u64)
+    }
+
+    fn changes(&self) -> Result<Vec<pb::Change>, AddressBookError> {
+        let mut stmt = self.conn.prepare("SELECT id, change FROM changes ORDER BY id")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut changes = Vec::new();
+        for row in rows {
+            let (id, change): (i64, Vec<u8>) = row?;
+            let mut change = pb::Change::decode(change.as_slice())?;
+            change.id = id as u64;
+            changes.push(change);
+        }
+        Ok(changes)
+    }
+
+    fn trim_log(&mut self, keep: usize) -> Result<(), AddressBookError> {
+        let count: i64 = self.conn.query_row("SELECT COUNT(*) FROM changes", [], |row| row.get(0))?;
+        if count as usize > keep {
+            self.begin()?;
+            self.conn.execute(
+                "DELETE FROM changes WHERE id NOT IN (SELECT id FROM changes ORDER BY id DESC LIMIT ?1)",
+                params![keep as i64],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), AddressBookError> {
+        if self.in_transaction {
+            self.conn.execute_batch("COMMIT")?;
+            self.in_transaction = false;
+        }
+        Ok(())
+    }
+
+    fn discard(&mut self) -> Result<(), AddressBookError> {
+        if self.in_transaction {
+            self.conn.execute_batch("ROLLBACK")?;
+            self.in_transaction = false;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,871 @@
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::path::Path;
+use std::time;
+
+use prost_types::Timestamp;
+
+use crate::dedupe;
+use crate::error::AddressBookError;
+use crate::pb;
+use crate::pb::company::Department;
+use crate::pb::contact::Kind;
+use crate::pb::person::phone_number::Type;
+use crate::storage::{self, Backend, Storage};
+use crate::validate::{self, ValidationError};
+
+/// Changes kept in the log: `save` drops the oldest ones past this many.
+pub const MAX_CHANGES: usize = 10_000;
+
+/// An address book on top of one of the `storage` backends.
+///
+/// Changes become durable when `save` is called. Every change is also
+/// recorded in the storage's change log, which `history`, `undo` and `as_of`
+/// read, and sync reads for deletions. The log keeps the latest
+/// `MAX_CHANGES`: older changes can no longer be undone or looked up, and a
+/// contact deleted that long ago may come back from a book that still has it.
+pub struct AddressBook {
+    storage: Box<dyn Storage>,
+    region: String,
+    author: String,
+}
+
+/// An email or phone of a contact, by position. The position one past the
+/// last one means a new entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Email(usize),
+    Phone(usize),
+}
+
+/// What `upsert_person` and `upsert_company` can set besides emails and phones.
+#[derive(Clone, Debug, Default)]
+pub struct Details {
+    /// Added to the contact's tags unless already there (ignoring case).
+    pub tags: Vec<String>,
+    /// Replaces the notes; an empty string clears them.
+    pub notes: Option<String>,
+    /// Added to the contact's addresses unless already there.
+    pub address: Option<pb::PostalAddress>,
+    /// Persons only: name of the company contact the person belongs to.
+    pub company: Option<String>,
+}
+
+impl Details {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.notes.is_none() && self.address.is_none() && self.company.is_none()
+    }
+
+    // Sets the fields every kind of contact has.
+    fn apply(&self, contact: &mut pb::Contact) {
+        for tag in &self.tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !contact.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                contact.tags.push(tag.to_string());
+            }
+        }
+        if let Some(notes) = &self.notes {
+            contact.notes = notes.clone();
+        }
+        if let Some(address) = &self.address {
+            if !contact.addresses.contains(address) {
+                contact.addresses.push(address.clone());
+            }
+        }
+    }
+}
+
+/// The user running the program, from `$USER` or `%USERNAME%`.
+pub fn default_author() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+impl AddressBook {
+    /// Opens the book at `path`, picking the backend with `Backend::detect`.
+    /// A missing file is created empty by the first `save`.
+    pub fn open(path: impl AsRef<Path>) -> Result<AddressBook, AddressBookError> {
+        let path = path.as_ref();
+        AddressBook::open_with(path, Backend::detect(path)?, None)
+    }
+
+    /// Opens a book that may be encrypted with `passphrase`. A plain book
+    /// opens as it is, and a missing one is created plain: `rekey` encrypts.
+    pub fn open_encrypted(path: impl AsRef<Path>, passphrase: &str) -> Result<AddressBook, AddressBookError> {
+        let path = path.as_ref();
+        AddressBook::open_with(path, Backend::detect(path)?, Some(passphrase))
+    }
+
+    pub fn open_with(
+        path: impl AsRef<Path>,
+        backend: Backend,
+        passphrase: Option<&str>,
+    ) -> Result<AddressBook, AddressBookError> {
+        Ok(AddressBook::from_storage(storage::open(path.as_ref(), backend, passphrase)?))
+    }
+
+    /// Opens a book only to read it, see `storage::open_read_only`: an older
+    /// file is not upgraded on disk, and `save` fails once anything changed.
+    pub fn open_read_only(
+        path: impl AsRef<Path>,
+        backend: Backend,
+        passphrase: Option<&str>,
+    ) -> Result<AddressBook, AddressBookError> {
+        Ok(AddressBook::from_storage(storage::open_read_only(path.as_ref(), backend, passphrase)?))
+    }
+
+    pub fn from_storage(storage: Box<dyn Storage>) -> AddressBook {
+        AddressBook {
+            storage,
+            region: validate::DEFAULT_REGION.to_string(),
+            author: default_author(),
+        }
+    }
+
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    pub fn storage_mut(&mut self) -> &mut dyn Storage {
+        self.storage.as_mut()
+    }
+
+    /// Sets the region used to read phone numbers without a country code.
+    pub fn set_region(&mut self, region: &str) {
+        self.region = region.to_string();
+    }
+
+    /// Sets who is recorded in the change log for the next changes.
+    pub fn set_author(&mut self, author: &str) {
+        self.author = author.to_string();
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<pb::Contact>, AddressBookError> {
+        self.storage.get(name)
+    }
+
+    /// Contacts sorted by name.
+    pub fn iter(&self) -> Result<impl Iterator<Item = (String, pb::Contact)>, AddressBookError> {
+        Ok(self.storage.list()?.into_iter())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Option<pb::Contact>, AddressBookError> {
+        let removed = self.storage.remove(name)?;
+        if removed.is_some() {
+            self.log(now(), name, removed.clone(), None, 0)?;
+        }
+        Ok(removed)
+    }
+
+    /// Replaces one email or phone of an existing contact with `value`, or
+    /// adds it when `field` is one past the last. An empty `value` removes
+    /// the entry. New entries get the unspecified type or department.
+    pub fn set_field(&mut self, name: &str, field: Field, value: &str) -> Result<pb::Contact, AddressBookError> {
+        let Some(mut contact) = self.get(name)? else {
+            return Err(AddressBookError::NotFound(name.to_string()));
+        };
+        let value = value.trim();
+        let value = match field {
+            _ if value.is_empty() => None,
+            Field::Email(_) => Some(validate::email(value)?),
+            Field::Phone(i) => {
+                let number = validate::phone(value, &self.region)?;
+                let numbers = phone_numbers(&contact);
+                let unchanged = numbers.get(i) == Some(&number.as_str());
+                if !unchanged {
+                    if let Some(owner) = self.storage.find_phone(&number)? {
+                        return Err(ValidationError::DuplicatePhone { number, contact: owner }.into());
+                    }
+                }
+                Some(number)
+            }
+        };
+
+        match (&mut contact.kind, field) {
+            (Some(Kind::Person(p)), Field::Email(i)) => edit(&mut p.emails, i, value, |email| email, |email| email),
+            (Some(Kind::Person(p)), Field::Phone(i)) => {
+                edit(&mut p.phones, i, value, |number| pb::person::PhoneNumber { number, r#type: 0 }, |pn| &mut pn.number)
+            }
+            (Some(Kind::Company(c)), Field::Email(i)) => {
+                edit(&mut c.emails, i, value, |email| pb::company::EmailAddress { email, department: 0 }, |em| &mut em.email)
+            }
+            (Some(Kind::Company(c)), Field::Phone(i)) => {
+                edit(&mut c.phones, i, value, |number| pb::company::PhoneNumber { number, department: 0 }, |pn| &mut pn.number)
+            }
+            _ => Err(AddressBookError::NoSuchField(format!("{field:?} of {name}"))),
+        }?;
+        self.insert(name, contact)
+    }
+
+    /// Stores `contact` as it is, `last_updated` included, and logs the change.
+    /// Unlike the upserts, nothing is validated.
+    pub fn put(&mut self, name: &str, contact: pb::Contact) -> Result<(), AddressBookError> {
+        let before = self.storage.get(name)?;
+        self.storage.put(name, &contact)?;
+        self.log(now(), name, before, Some(contact), 0)?;
+        Ok(())
+    }
+
+    /// Replaces the whole contact, or creates it, checking it like the upserts
+    /// do: emails must be valid, phones are normalized and must not belong to
+    /// another contact, and a person's company must exist. The kind may change.
+    pub fn replace(&mut self, name: &str, mut contact: pb::Contact) -> Result<pb::Contact, AddressBookError> {
+        if contact.kind.is_none() {
+            return Err(AddressBookError::MissingArgument("kind"));
+        }
+        self.check(name, &mut contact)?;
+        self.insert(name, contact)
+    }
+
+    /// Stores a contact from another book with its `last_updated`, like `put`,
+    /// after checking it like `replace`. A contact without a kind is stored too.
+    pub fn import(&mut self, name: &str, mut contact: pb::Contact) -> Result<pb::Contact, AddressBookError> {
+        self.check(name, &mut contact)?;
+        self.put(name, contact.clone())?;
+        Ok(contact)
+    }
+
+    /// Merges the contacts `others` into `keep` (see `dedupe::merge`) and
+    /// removes them. Each name must exist, and all must be of the same kind.
+    pub fn merge(&mut self, keep: &str, others: &[&str]) -> Result<pb::Contact, AddressBookError> {
+        let mut contacts = Vec::new();
+        for name in std::iter::once(&keep).chain(others) {
+            match self.storage.get(name)? {
+                Some(contact) => contacts.push(contact),
+                None => return Err(AddressBookError::NotFound(name.to_string())),
+            }
+        }
+        let Some(merged) = dedupe::merge(&contacts) else {
+            return Err(AddressBookError::MixedKinds(keep.to_string()));
+        };
+
+        let at = now();
+        for (name, contact) in others.iter().zip(&contacts[1..]) {
+            if *name != keep {
+                self.storage.remove(name)?;
+                self.log(at, name, Some(contact.clone()), None, 0)?;
+            }
+        }
+        self.storage.put(keep, &merged)?;
+        self.log(at, keep, Some(contacts[0].clone()), Some(merged.clone()), 0)?;
+        Ok(merged)
+    }
+
+    /// Changes of the contact `name`, oldest first.
+    pub fn history(&self, name: &str) -> Result<Vec<pb::Change>, AddressBookError> {
+        Ok(self.storage.changes()?.into_iter().filter(|c| c.name == name).collect())
+    }
+
+    /// Reverts the latest change that has not been undone yet and logs the undo
+    /// as a new change. Undos themselves are never reverted, so repeated calls
+    /// walk further back. Returns the reverted change, or `None` if there is none.
+    /// A contact brought back is checked like `replace` does, so it cannot take
+    /// a phone another contact has since been given.
+    pub fn undo(&mut self) -> Result<Option<pb::Change>, AddressBookError> {
+        let changes = self.storage.changes()?;
+        let undone: HashSet<u64> = changes.iter().map(|c| c.undoes).collect();
+        let Some(change) = changes.into_iter().rev().find(|c| c.undoes == 0 && !undone.contains(&c.id)) else {
+            return Ok(None);
+        };
+
+        let at = now();
+        let restored = match change.before.clone() {
+            Some(mut contact) => {
+                self.check(&change.name, &mut contact)?;
+                contact.last_updated = Some(at);
+                Some(contact)
+            }
+            None => None,
+        };
+        let current = match &restored {
+            Some(contact) => {
+                let current = self.storage.get(&change.name)?;
+                self.storage.put(&change.name, contact)?;
+                current
+            }
+            None => self.storage.remove(&change.name)?,
+        };
+        self.log(at, &change.name, current, restored, change.id)?;
+        Ok(Some(change))
+    }
+
+    /// The contacts as they were at `at`, sorted by name. Contacts that
+    /// predate the change log are assumed to have always existed.
+    pub fn as_of(&self, at: Timestamp) -> Result<Vec<(String, pb::Contact)>, AddressBookError> {
+        let mut contacts: BTreeMap<String, pb::Contact> = self.storage.list()?.into_iter().collect();
+        let at = (at.seconds, at.nanos);
+        for change in self.storage.changes()?.into_iter().rev() {
+            let changed = change.at.map(|t| (t.seconds, t.nanos)).unwrap_or_default();
+            if changed <= at {
+                break;
+            }
+            match change.before {
+                Some(contact) => contacts.insert(change.name, contact),
+                None => contacts.remove(&change.name),
+            };
+        }
+        Ok(contacts.into_iter().collect())
+    }
+
+    /// Creates the person or adds the email, phone and details to an existing one.
+    pub fn upsert_person(
+        &mut self,
+        name: &str,
+        email: Option<&str>,
+        phone: Option<(&str, Type)>,
+        details: &Details,
+    ) -> Result<pb::Contact, AddressBookError> {
+        if email.is_none() && phone.is_none() && details.is_empty() {
+            return Err(ValidationError::EmptyAdd.into());
+        }
+        let email = email.map(validate::email).transpose()?;
+        let phone = phone.map(|(p, t)| self.phone(p).map(|p| (p, t))).transpose()?;
+        if let Some(company) = &details.company {
+            self.check_company(company)?;
+        }
+
+        let mut contact = self.get(name)?.unwrap_or_default();
+        let mut person = match contact.kind.take() {
+            Some(Kind::Person(p)) => p,
+            Some(Kind::Company(_)) => {
+                return Err(AddressBookError::KindConflict { name: name.to_string(), existing: "company" })
+            }
+            None => pb::Person::default(),
+        };
+        if let Some(email) = email {
+            if !person.emails.iter().any(|e| e.eq_ignore_ascii_case(&email)) {
+                person.emails.push(email);
+            }
+        }
+        if let Some((number, phone_type)) = phone {
+            person.phones.push(pb::person::PhoneNumber {
+                number,
+                r#type: phone_type.into(),
+            });
+        }
+        if let Some(company) = &details.company {
+            person.company = company.clone();
+        }
+        contact.kind = Some(Kind::Person(person));
+        details.apply(&mut contact);
+        self.insert(name, contact)
+    }
+
+    /// Creates the company or adds the email, phone and details to an existing one.
+    pub fn upsert_company(
+        &mut self,
+        name: &str,
+        email: Option<(&str, Department)>,
+        phone: Option<(&str, Department)>,
+        details: &Details,
+    ) -> Result<pb::Contact, AddressBookError> {
+        if email.is_none() && phone.is_none() && details.is_empty() {
+            return Err(ValidationError::EmptyAdd.into());
+        }
+        if let Some(company) = &details.company {
+            return Err(AddressBookError::InvalidArgument {
+                arg: "company",
+                value: format!("{company} (only a person can belong to a company)"),
+            });
+        }
+        let email = email.map(|(e, d)| validate::email(e).map(|e| (e, d))).transpose()?;
+        let phone = phone.map(|(p, d)| self.phone(p).map(|p| (p, d))).transpose()?;
+
+        let mut contact = self.get(name)?.unwrap_or_default();
+        let mut company = match contact.kind.take() {
+            Some(Kind::Company(c)) => c,
+            Some(Kind::Person(_)) => {
+                return Err(AddressBookError::KindConflict { name: name.to_string(), existing: "person" })
+            }
+            None => pb::Company::default(),
+        };
+        if let Some((email, department)) = email {
+            if !company.emails.iter().any(|e| e.email.eq_ignore_ascii_case(&email)) {
+                company.emails.push(pb::company::EmailAddress {
+                    email,
+                    department: department.into(),
+                });
+            }
+        }
+        if let Some((number, department)) = phone {
+            company.phones.push(pb::company::PhoneNumber {
+                number,
+                department: department.into(),
+            });
+        }
+        contact.kind = Some(Kind::Company(company));
+        details.apply(&mut contact);
+        self.insert(name, contact)
+    }
+
+    /// Encrypts the book with a new passphrase, or stores it in plain when
+    /// `None`, on the next `save`. A plain book can be encrypted this way.
+    pub fn rekey(&mut self, passphrase: Option<&str>) -> Result<(), AddressBookError> {
+        self.storage.set_passphrase(passphrase)
+    }
+
+    pub fn save(&mut self) -> Result<(), AddressBookError> {
+        self.storage.trim_log(MAX_CHANGES)?;
+        self.storage.flush()
+    }
+
+    /// Drops the changes made since the last `save`, so the book matches its
+    /// file again, e.g. after `save` failed.
+    pub fn discard(&mut self) -> Result<(), AddressBookError> {
+        self.storage.discard()
+    }
+
+    // Checks a whole contact for `replace` and `import`, normalizing its emails and phones.
+    fn check(&self, name: &str, contact: &mut pb::Contact) -> Result<(), AddressBookError> {
+        match &mut contact.kind {
+            Some(Kind::Person(p)) => {
+                for email in &mut p.emails {
+                    *email = validate::email(email)?;
+                }
+                for pn in &mut p.phones {
+                    pn.number = self.own_phone(name, &pn.number)?;
+                }
+                if !p.company.is_empty() {
+                    self.check_company(&p.company)?;
+                }
+            }
+            Some(Kind::Company(c)) => {
+                for em in &mut c.emails {
+                    em.email = validate::email(&em.email)?;
+                }
+                for pn in &mut c.phones {
+                    pn.number = self.own_phone(name, &pn.number)?;
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    // Normalizes a new number and checks nobody has it yet.
+    fn phone(&self, phone: &str) -> Result<String, AddressBookError> {
+        let number = validate::phone(phone, &self.region)?;
+        if let Some(contact) = self.storage.find_phone(&number)? {
+            return Err(ValidationError::DuplicatePhone { number, contact }.into());
+        }
+        Ok(number)
+    }
+
+    // Normalizes a number of the contact `name`, which nobody else may have.
+    fn own_phone(&self, name: &str, phone: &str) -> Result<String, AddressBookError> {
+        let number = validate::phone(phone, &self.region)?;
+        match self.storage.find_phone(&number)? {
+            Some(contact) if contact != name => Err(ValidationError::DuplicatePhone { number, contact }.into()),
+            _ => Ok(number),
+        }
+    }
+
+    // Checks `company` names a company contact, for a person to belong to.
+    fn check_company(&self, company: &str) -> Result<(), AddressBookError> {
+        match self.get(company)?.and_then(|c| c.kind) {
+            Some(Kind::Company(_)) => Ok(()),
+            _ => Err(AddressBookError::InvalidArgument {
+                arg: "company",
+                value: format!("{company} (not a company in the address book)"),
+            }),
+        }
+    }
+
+    fn insert(&mut self, name: &str, mut contact: pb::Contact) -> Result<pb::Contact, AddressBookError> {
+        let at = now();
+        contact.last_updated = Some(at);
+        let before = self.storage.get(name)?;
+        self.storage.put(name, &contact)?;
+        self.log(at, name, before, Some(contact.clone()), 0)?;
+        Ok(contact)
+    }
+
+    fn log(
+        &mut self,
+        at: Timestamp,
+        name: &str,
+        before: Option<pb::Contact>,
+        after: Option<pb::Contact>,
+        undoes: u64,
+    ) -> Result<u64, AddressBookError> {
+        self.storage.log(pb::Change {
+            id: 0,
+            at: Some(at),
+            author: self.author.clone(),
+            name: name.to_string(),
+            before,
+            after,
+            undoes,
+        })
+    }
+}
+
+fn phone_numbers(contact: &pb::Contact) -> Vec<&str> {
+    match &contact.kind {
+        Some(Kind::Person(p)) => p.phones.iter().map(|pn| pn.number.as_str()).collect(),
+        Some(Kind::Company(c)) => c.phones.iter().map(|pn| pn.number.as_str()).collect(),
+        None => Vec::new(),
+    }
+}
+
+// Replaces, removes (`value` is `None`) or appends (`i` is the length) an entry of a list.
+fn edit<T>(
+    list: &mut Vec<T>,
+    i: usize,
+    value: Option<String>,
+    new: impl FnOnce(String) -> T,
+    text: impl FnOnce(&mut T) -> &mut String,
+) -> Result<(), AddressBookError> {
+    let len = list.len();
+    match value {
+        Some(value) if i < len => *text(&mut list[i]) = value,
+        None if i < len => {
+            list.remove(i);
+        }
+        Some(value) if i == len => list.push(new(value)),
+        None if i == len => {}
+        _ => return Err(AddressBookError::NoSuchField(format!("entry {}", i + 1))),
+    }
+    Ok(())
+}
+
+fn now() -> Timestamp {
+    Timestamp::from(time::SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_empty() -> (tempfile::TempDir, AddressBook) {
+        let dir = tempfile::tempdir().unwrap();
+        let book = AddressBook::open(dir.path().join("addressbook.db")).unwrap();
+        (dir, book)
+    }
+
+    #[test]
+    fn upsert_and_save() {
+        let (dir, mut book) = open_empty();
+
+        book.upsert_person("Bob", Some("bob@example.com"), Some(("201-555-0123", Type::Home)), &Details::default())
+            .unwrap();
+        book.upsert_person("Bob", None, Some(("201-555-0124", Type::Work)), &Details::default())
+            .unwrap();
+        book.upsert_company("Acme", Some(("hr@acme.com", Department::Hr)), None, &Details::default())
+            .unwrap();
+        book.save().unwrap();
+
+        let book = AddressBook::open(dir.path().join("addressbook.db")).unwrap();
+        let names: Vec<String> = book.iter().unwrap().map(|(name, _)| name).collect();
+        assert_eq!(vec!["Acme", "Bob"], names);
+        match book.get("Bob").unwrap().unwrap().kind {
+            Some(Kind::Person(p)) => {
+                assert_eq!(vec!["bob@example.com"], p.emails);
+                assert_eq!(2, p.phones.len());
+                assert_eq!("+12015550124", p.phones[1].number);
+            }
+            _ => panic!("expected a person"),
+        }
+    }
+
+    #[test]
+    fn details() {
+        let (_dir, mut book) = open_empty();
+        let address = validate::address("1 Main St; Springfield").unwrap();
+        book.upsert_company("Acme", None, None, &Details { tags: vec!["client".to_string()], ..Default::default() })
+            .unwrap();
+        let details = Details {
+            tags: vec!["work".to_string(), "golf".to_string()],
+            notes: Some("met at the fair".to_string()),
+            address: Some(address.clone()),
+            company: Some("Acme".to_string()),
+        };
+        book.upsert_person("Bob", Some("bob@example.com"), None, &details).unwrap();
+        let details = Details { tags: vec!["Work".to_string()], address: Some(address), ..Default::default() };
+        book.upsert_person("Bob", Some("bob@example.org"), None, &details).unwrap();
+
+        let contact = book.get("Bob").unwrap().unwrap();
+        assert_eq!(vec!["work", "golf"], contact.tags);
+        assert_eq!("met at the fair", contact.notes);
+        assert_eq!(1, contact.addresses.len());
+        match contact.kind {
+            Some(Kind::Person(p)) => {
+                assert_eq!(vec!["bob@example.com", "bob@example.org"], p.emails);
+                assert_eq!("Acme", p.company);
+            }
+            _ => panic!("expected a person"),
+        }
+    }
+
+    #[test]
+    fn company_link() {
+        let (_dir, mut book) = open_empty();
+        book.upsert_person("Al", Some("al@example.com"), None, &Details::default()).unwrap();
+        let at = |company: &str| Details { company: Some(company.to_string()), ..Default::default() };
+
+        for company in ["Acme", "Al"] {
+            assert!(matches!(
+                book.upsert_person("Bob", None, None, &at(company)),
+                Err(AddressBookError::InvalidArgument { arg: "company", .. })
+            ));
+        }
+        assert!(matches!(
+            book.upsert_company("Acme", None, None, &at("Al")),
+            Err(AddressBookError::InvalidArgument { arg: "company", .. })
+        ));
+        assert!(book.get("Bob").unwrap().is_none());
+    }
+
+    #[test]
+    fn duplicate_phone() {
+        let (_dir, mut book) = open_empty();
+
+        book.upsert_person("Bob", None, Some(("201-555-0123", Type::Home)), &Details::default()).unwrap();
+
+        assert!(matches!(
+            book.upsert_company("Acme", None, Some(("+1 201 555 0123", Department::Hr)), &Details::default()),
+            Err(AddressBookError::Validation(ValidationError::DuplicatePhone { .. }))
+        ));
+    }
+
+    #[test]
+    fn duplicate_email() {
+        let (_dir, mut book) = open_empty();
+
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        book.upsert_person("Bob", Some("Bob@Example.com"), None, &Details::default()).unwrap();
+        book.upsert_company("Acme", Some(("hr@acme.com", Department::Hr)), None, &Details::default()).unwrap();
+        book.upsert_company("Acme", Some(("HR@acme.com", Department::CustomerService)), None, &Details::default())
+            .unwrap();
+
+        match book.get("Bob").unwrap().unwrap().kind {
+            Some(Kind::Person(p)) => assert_eq!(vec!["bob@example.com"], p.emails),
+            _ => panic!("expected a person"),
+        }
+        match book.get("Acme").unwrap().unwrap().kind {
+            Some(Kind::Company(c)) => {
+                assert_eq!(1, c.emails.len());
+                assert_eq!("hr@acme.com", c.emails[0].email);
+                assert_eq!(Department::Hr as i32, c.emails[0].department);
+            }
+            _ => panic!("expected a company"),
+        }
+    }
+
+    #[test]
+    fn replace() {
+        let (_dir, mut book) = open_empty();
+        book.upsert_person("Bob", None, Some(("201-555-0123", Type::Home)), &Details::default()).unwrap();
+        let person = |email: &str, number: &str| pb::Contact {
+            kind: Some(Kind::Person(pb::Person {
+                emails: vec![email.to_string()],
+                phones: vec![pb::person::PhoneNumber { number: number.to_string(), r#type: 0 }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let contact = book.replace("Bob", person(" bob@example.com", "(201) 555-0123")).unwrap();
+        assert!(contact.last_updated.is_some());
+        match contact.kind {
+            Some(Kind::Person(p)) => {
+                assert_eq!(vec!["bob@example.com"], p.emails);
+                assert_eq!("+12015550123", p.phones[0].number);
+            }
+            _ => panic!("expected a person"),
+        }
+
+        assert!(matches!(
+            book.replace("Al", person("al@example.com", "201-555-0123")),
+            Err(AddressBookError::Validation(ValidationError::DuplicatePhone { .. }))
+        ));
+        assert!(matches!(
+            book.replace("Al", person("not an email", "201-555-0124")),
+            Err(AddressBookError::Validation(ValidationError::InvalidEmail(_)))
+        ));
+        assert!(matches!(book.replace("Al", pb::Contact::default()), Err(AddressBookError::MissingArgument("kind"))));
+        assert!(book.get("Al").unwrap().is_none());
+    }
+
+    #[test]
+    fn kind_conflict() {
+        let (_dir, mut book) = open_empty();
+
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+
+        assert!(matches!(
+            book.upsert_company("Bob", Some(("bob@example.com", Department::Hr)), None, &Details::default()),
+            Err(AddressBookError::KindConflict { .. })
+        ));
+    }
+
+    #[test]
+    fn remove() {
+        let (_dir, mut book) = open_empty();
+
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+
+        assert!(book.remove("Bob").unwrap().is_some());
+        assert!(book.get("Bob").unwrap().is_none());
+    }
+
+    #[test]
+    fn merge() {
+        let (_dir, mut book) = open_empty();
+
+        book.upsert_company("ACME Corp", None, Some(("201-555-0123", Department::Hr)), &Details::default()).unwrap();
+        book.upsert_company("Acme Corp.", Some(("cs@acme.com", Department::CustomerService)), None, &Details::default())
+            .unwrap();
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        let merged = book.merge("Acme Corp.", &["ACME Corp"]).unwrap();
+
+        let names: Vec<String> = book.iter().unwrap().map(|(name, _)| name).collect();
+        assert_eq!(vec!["Acme Corp.", "Bob"], names);
+        assert_eq!(Some(merged), book.get("Acme Corp.").unwrap());
+        assert_eq!(Some("Acme Corp.".to_string()), book.storage().find_phone("+12015550123").unwrap());
+        assert_eq!(2, book.history("ACME Corp").unwrap().len());
+        assert!(matches!(book.merge("Bob", &["Acme Corp."]), Err(AddressBookError::MixedKinds(_))));
+        assert!(matches!(book.merge("Bob", &["Carol"]), Err(AddressBookError::NotFound(_))));
+    }
+
+    #[test]
+    fn set_field() {
+        let (_dir, mut book) = open_empty();
+        book.upsert_person("Bob", Some("bob@example.com"), Some(("201-555-0123", Type::Home)), &Details::default()).unwrap();
+        book.upsert_person("Al", None, Some(("201-555-0124", Type::Home)), &Details::default()).unwrap();
+
+        book.set_field("Bob", Field::Email(0), "rob@example.com").unwrap();
+        book.set_field("Bob", Field::Phone(0), "(201) 555-0123").unwrap();
+        book.set_field("Bob", Field::Phone(1), "201-555-0125").unwrap();
+        assert!(matches!(
+            book.set_field("Bob", Field::Phone(0), "201-555-0124"),
+            Err(AddressBookError::Validation(ValidationError::DuplicatePhone { .. }))
+        ));
+        assert!(matches!(
+            book.set_field("Bob", Field::Email(0), "rob"),
+            Err(AddressBookError::Validation(ValidationError::InvalidEmail(_)))
+        ));
+        assert!(matches!(book.set_field("Bob", Field::Phone(5), "201-555-0126"), Err(AddressBookError::NoSuchField(_))));
+        book.set_field("Bob", Field::Phone(0), "").unwrap();
+
+        match book.get("Bob").unwrap().unwrap().kind {
+            Some(Kind::Person(p)) => {
+                assert_eq!(vec!["rob@example.com"], p.emails);
+                let numbers: Vec<&str> = p.phones.iter().map(|pn| pn.number.as_str()).collect();
+                assert_eq!(vec!["+12015550125"], numbers);
+            }
+            _ => panic!("expected a person"),
+        }
+    }
+
+    #[test]
+    fn history() {
+        let (_dir, mut book) = open_empty();
+        book.set_author("alice");
+
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        book.upsert_person("Bob", Some("bob@example.org"), None, &Details::default()).unwrap();
+        book.upsert_person("Carol", Some("carol@example.com"), None, &Details::default()).unwrap();
+        book.remove("Bob").unwrap();
+
+        let history = book.history("Bob").unwrap();
+        assert_eq!(3, history.len());
+        assert!(history[0].before.is_none());
+        assert_eq!(history[0].after, history[1].before);
+        assert!(history[2].after.is_none());
+        assert!(history.iter().all(|c| c.author == "alice"));
+    }
+
+    #[test]
+    fn undo() {
+        let (_dir, mut book) = open_empty();
+        let email = |book: &AddressBook| match book.get("Bob").unwrap().and_then(|c| c.kind) {
+            Some(Kind::Person(p)) => p.emails.last().cloned(),
+            _ => None,
+        };
+
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        book.upsert_person("Bob", Some("bob@example.org"), None, &Details::default()).unwrap();
+        book.remove("Bob").unwrap();
+
+        assert_eq!(3, book.undo().unwrap().unwrap().id);
+        assert_eq!(Some("bob@example.org".to_string()), email(&book));
+        assert_eq!(2, book.undo().unwrap().unwrap().id);
+        assert_eq!(Some("bob@example.com".to_string()), email(&book));
+        assert_eq!(1, book.undo().unwrap().unwrap().id);
+        assert_eq!(None, email(&book));
+        assert!(book.undo().unwrap().is_none());
+        // Undos are logged too
+        assert_eq!(6, book.history("Bob").unwrap().len());
+    }
+
+    #[test]
+    fn undo_checks_phones() {
+        let (_dir, mut book) = open_empty();
+
+        let bob = book.upsert_person("Bob", None, Some(("201-555-0123", Type::Home)), &Details::default()).unwrap();
+        book.remove("Bob").unwrap();
+        // Given Bob's phone by a program that keeps no log, such as an older Python CLI
+        book.storage_mut().put("Al", &bob).unwrap();
+
+        assert!(matches!(
+            book.undo(),
+            Err(AddressBookError::Validation(ValidationError::DuplicatePhone { .. }))
+        ));
+        assert!(book.get("Bob").unwrap().is_none());
+    }
+
+    #[test]
+    fn log_is_trimmed() {
+        let (_dir, mut book) = open_empty();
+
+        for i in 0..MAX_CHANGES + 2 {
+            book.upsert_person("Bob", None, None, &Details { notes: Some(i.to_string()), ..Details::default() }).unwrap();
+        }
+        book.save().unwrap();
+
+        let changes = book.history("Bob").unwrap();
+        assert_eq!(MAX_CHANGES, changes.len());
+        assert_eq!(3, changes[0].id);
+        assert_eq!(MAX_CHANGES as u64 + 2, changes[MAX_CHANGES - 1].id);
+    }
+
+    #[test]
+    fn rekey_plain_book() {
+        let (dir, mut book) = open_empty();
+        let path = dir.path().join("addressbook.db");
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        book.save().unwrap();
+
+        // The passphrase of a plain book is not in the way of encrypting it
+        let mut book = AddressBook::open_encrypted(&path, "correct horse").unwrap();
+        book.rekey(Some("correct horse")).unwrap();
+        book.save().unwrap();
+
+        assert!(matches!(
+            AddressBook::open(&path),
+            Err(AddressBookError::Crypt(crate::CryptError::PassphraseRequired))
+        ));
+        assert!(AddressBook::open_encrypted(&path, "correct horse").unwrap().get("Bob").unwrap().is_some());
+    }
+
+    #[test]
+    fn as_of() {
+        let (_dir, mut book) = open_empty();
+
+        book.upsert_person("Bob", Some("bob@example.com"), None, &Details::default()).unwrap();
+        let first = book.get("Bob").unwrap().unwrap();
+        let at = first.last_updated.unwrap();
+        book.upsert_person("Bob", Some("bob@example.org"), None, &Details::default()).unwrap();
+        book.upsert_person("Carol", Some("carol@example.com"), None, &Details::default()).unwrap();
+
+        assert_eq!(vec![("Bob".to_string(), first)], book.as_of(at).unwrap());
+        assert!(book.as_of(Timestamp { seconds: 0, nanos: 0 }).unwrap().is_empty());
+        assert_eq!(book.iter().unwrap().collect::<Vec<_>>(), book.as_of(now()).unwrap());
+    }
+}
@@ -0,0 +1,139 @@
+// Checks run on user input before it is stored in the book.
+// Both frontends use these through `addressbook_core` rather than keeping
+// their own copy, and so do the `AddressBook` methods that store outside
+// input: the upserts, `set_field`, `replace`, `import` and `undo`.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use email_address::EmailAddress;
+use phonenumber::country;
+use phonenumber::Mode;
+
+use crate::pb;
+
+/// Region used to read phone numbers written without a country code.
+pub const DEFAULT_REGION: &str = "US";
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// Neither an email nor a phone number was given.
+    EmptyAdd,
+    InvalidEmail(String),
+    InvalidPhone(String),
+    InvalidRegion(String),
+    /// A postal address without a street, or with too many parts.
+    InvalidAddress(String),
+    /// The number is already stored for a contact.
+    DuplicatePhone { number: String, contact: String },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::EmptyAdd => write!(f, "nothing to add: give an email or a phone number"),
+            ValidationError::InvalidEmail(email) => write!(f, "invalid email address: {email}"),
+            ValidationError::InvalidPhone(phone) => write!(f, "invalid phone number: {phone}"),
+            ValidationError::InvalidRegion(region) => write!(f, "unknown region: {region}"),
+            ValidationError::InvalidAddress(address) => write!(
+                f,
+                "invalid address: {address} (expected \"street; city; region; postal code; country\")"
+            ),
+            ValidationError::DuplicatePhone { number, contact } => {
+                write!(f, "phone number {number} already belongs to {contact}")
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Checks the email syntax (RFC 5322) and returns it trimmed.
+pub fn email(email: &str) -> Result<String, ValidationError> {
+    let email = email.trim();
+    if EmailAddress::is_valid(email) {
+        Ok(email.to_string())
+    } else {
+        Err(ValidationError::InvalidEmail(email.to_string()))
+    }
+}
+
+/// Parses a phone number and returns it in E.164 form ("+15550100").
+/// Numbers without a country code are read in `region` (ISO 3166 code, e.g. "US").
+pub fn phone(phone: &str, region: &str) -> Result<String, ValidationError> {
+    let id = country::Id::from_str(&region.to_uppercase())
+        .map_err(|_| ValidationError::InvalidRegion(region.to_string()))?;
+    let number = phonenumber::parse(Some(id), phone)
+        .map_err(|_| ValidationError::InvalidPhone(phone.to_string()))?;
+    if !number.is_valid() {
+        return Err(ValidationError::InvalidPhone(phone.to_string()));
+    }
+    Ok(number.format().mode(Mode::E164).to_string())
+}
+
+/// Parses "street; city; region; postal code; country". Everything after
+/// the street is optional, e.g. "1 Main St; Springfield".
+pub fn address(address: &str) -> Result<pb::PostalAddress, ValidationError> {
+    let parts: Vec<String> = address.split(';').map(|part| part.trim().to_string()).collect();
+    if parts[0].is_empty() || parts.len() > 5 {
+        return Err(ValidationError::InvalidAddress(address.to_string()));
+    }
+    let mut parts = parts.into_iter();
+    let mut next = || parts.next().unwrap_or_default();
+    Ok(pb::PostalAddress {
+        street: next(),
+        city: next(),
+        region: next(),
+        postal_code: next(),
+        country: next(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_email() {
+        assert_eq!(Ok("bob@example.com".to_string()), email(" bob@example.com "));
+    }
+
+    #[test]
+    fn invalid_email() {
+        assert_eq!(
+            Err(ValidationError::InvalidEmail("bob.example.com".to_string())),
+            email("bob.example.com")
+        );
+        assert!(email("").is_err());
+    }
+
+    #[test]
+    fn phone_to_e164() {
+        assert_eq!(Ok("+12015550123".to_string()), phone("(201) 555-0123", "US"));
+        assert_eq!(Ok("+447400123456".to_string()), phone("07400 123456", "gb"));
+        assert_eq!(Ok("+447400123456".to_string()), phone("+44 7400 123456", "US"));
+    }
+
+    #[test]
+    fn parse_address() {
+        let address = address(" 1 Main St ; Springfield;IL; 62701 ; US").unwrap();
+        assert_eq!("1 Main St", address.street);
+        assert_eq!("IL", address.region);
+        assert_eq!("US", address.country);
+
+        assert_eq!("", super::address("1 Main St").unwrap().city);
+        assert!(super::address("; Springfield").is_err());
+        assert!(super::address("a;b;c;d;e;f").is_err());
+    }
+
+    #[test]
+    fn invalid_phone() {
+        assert!(phone("12", "US").is_err());
+        assert!(phone("not a number", "US").is_err());
+        assert_eq!(
+            Err(ValidationError::InvalidRegion("XX".to_string())),
+            phone("2015550123", "XX")
+        );
+    }
+}
@@ -0,0 +1,67 @@
+// What was done to a post, by whom and when. The state a post goes to is
+// whatever type the crate keeps its states in, and so is what was done, an
+// `Action` unless the crate names its events otherwise.
+
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::policy::Actor;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Not logged: only the text changes, not the state.
+    AddText,
+    /// Not logged: only the publication time changes, not the state.
+    Schedule,
+    RequestReview,
+    Approve,
+    Reject,
+    Publish,
+    Unpublish,
+    Archive,
+}
+
+/// The verb of the action, as in "cannot approve a draft".
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Action::AddText => "add text to",
+            Action::Schedule => "schedule",
+            Action::RequestReview => "request a review of",
+            Action::Approve => "approve",
+            Action::Reject => "reject",
+            Action::Publish => "publish",
+            Action::Unpublish => "unpublish",
+            Action::Archive => "archive",
+        })
+    }
+}
+
+/// A transition of a post to a state of type `S`, on an action of type `A`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry<S, A = Action> {
+    pub action: A,
+    /// The name of the actor.
+    pub actor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// The state the post went to.
+    pub state: S,
+    pub at: SystemTime,
+}
+
+impl<S, A> Entry<S, A> {
+    /// `action` by `actor`, which took the post to `state` just now.
+    pub fn new(action: A, actor: &Actor, comment: Option<&str>, state: S) -> Self {
+        Entry {
+            action,
+            actor: actor.name.clone(),
+            comment: comment.map(String::from),
+            state,
+            at: SystemTime::now(),
+        }
+    }
+}
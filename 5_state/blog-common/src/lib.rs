@@ -0,0 +1,8 @@
+// What the three blog crates share: who works on posts and which approvals
+// publish one (`policy`), the log of what was done to a post (`audit`), and
+// the directory posts are saved in (`repository`). Each crate brings its own
+// post and state types.
+
+pub mod audit;
+pub mod policy;
+pub mod repository;
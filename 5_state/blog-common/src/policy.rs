@@ -0,0 +1,103 @@
+// Who works on posts, and which approvals publish a post in review.
+
+use serde::{Deserialize, Serialize};
+
+/// Someone who writes, approves or rejects posts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actor {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+impl Actor {
+    pub fn new(name: &str) -> Self {
+        Actor {
+            name: name.to_string(),
+            roles: Vec::new(),
+        }
+    }
+
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.roles.push(role.to_string());
+        self
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+/// What a post in review needs to be published: a number of approvals,
+/// each from a different reviewer, and maybe an approval from a reviewer
+/// in each of some roles. A rejected post starts over.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalPolicy {
+    approvals: u8,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    required_roles: Vec<String>,
+}
+
+/// Approvals by two reviewers.
+impl Default for ApprovalPolicy {
+    fn default() -> Self {
+        ApprovalPolicy::approvals(2)
+    }
+}
+
+impl ApprovalPolicy {
+    /// Publishes after approvals by `n` reviewers.
+    pub fn approvals(n: u8) -> Self {
+        ApprovalPolicy {
+            approvals: n,
+            required_roles: Vec::new(),
+        }
+    }
+
+    /// Also needs an approval from a reviewer with `role`.
+    pub fn require_role(mut self, role: &str) -> Self {
+        self.required_roles.push(role.to_string());
+        self
+    }
+
+    /// Whether `approvals` publish the post. A reviewer counts once however
+    /// often they appear.
+    pub fn is_met(&self, approvals: &[Actor]) -> bool {
+        let reviewers = approvals.iter().enumerate().filter(|&(i, a)| !approvals[..i].iter().any(|b| b.name == a.name)).count();
+        reviewers >= usize::from(self.approvals)
+            && self.required_roles.iter().all(|role| approvals.iter().any(|r| r.has_role(role)))
+    }
+}
+
+/// The first reviewer in `approvals` who approved more than once, which a
+/// post loaded from a file may have although no post can get there.
+pub fn repeated_approval(approvals: &[Actor]) -> Option<&str> {
+    approvals
+        .iter()
+        .enumerate()
+        .find(|&(i, a)| approvals[..i].iter().any(|b| b.name == a.name))
+        .map(|(_, a)| a.name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies() {
+        let alice = Actor::new("Alice");
+        let bob = Actor::new("Bob").with_role("legal");
+        let carol = Actor::new("Carol");
+
+        let two = ApprovalPolicy::approvals(2);
+        assert!(!two.is_met(std::slice::from_ref(&alice)));
+        assert!(!two.is_met(&[alice.clone(), alice.clone()]));
+        assert!(two.is_met(&[alice.clone(), carol.clone()]));
+        assert_eq!(None, repeated_approval(&[alice.clone(), carol.clone()]));
+        assert_eq!(Some("Alice"), repeated_approval(&[alice.clone(), carol.clone(), alice.clone()]));
+
+        let legal = ApprovalPolicy::approvals(1).require_role("legal");
+        assert!(!legal.is_met(&[alice, carol]));
+        assert!(legal.is_met(&[bob]));
+    }
+}
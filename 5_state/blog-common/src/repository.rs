@@ -0,0 +1,141 @@
+// Posts saved on disk: a directory with one JSON file per post, named after
+// the id of the post (`<dir>/<id>.json`). The crates differ in what a post is
+// and how one is read back, which `Stored` tells the repository.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// The id is empty or has other characters than letters, digits, `-`
+    /// and `_`.
+    InvalidId(String),
+    NotFound(String),
+    /// The file of the post does not hold a post.
+    Format { id: String, error: serde_json::Error },
+    Io(io::Error),
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepositoryError::InvalidId(id) => write!(f, "invalid post id {id:?}"),
+            RepositoryError::NotFound(id) => write!(f, "no post {id:?}"),
+            RepositoryError::Format { id, error } => write!(f, "post {id:?} is damaged: {error}"),
+            RepositoryError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for RepositoryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RepositoryError::Format { error, .. } => Some(error),
+            RepositoryError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RepositoryError {
+    fn from(e: io::Error) -> Self {
+        RepositoryError::Io(e)
+    }
+}
+
+/// A post that can be kept in a `Repository`.
+pub trait Stored: Serialize + Sized {
+    /// What `Repository::list` picks posts by.
+    type State: PartialEq;
+    /// What reading a post back needs besides its JSON, such as the workflow
+    /// its states come from.
+    type Context: Clone;
+
+    /// The context of a repository that was not given one.
+    fn default_context() -> Self::Context;
+
+    fn state(&self) -> Self::State;
+
+    /// Reads back a post saved as `json`.
+    fn from_json(json: &[u8], context: &Self::Context) -> Result<Self, serde_json::Error>;
+}
+
+pub struct Repository<P: Stored> {
+    dir: PathBuf,
+    context: P::Context,
+}
+
+impl<P: Stored> Repository<P> {
+    /// Opens the repository in `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, RepositoryError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Repository {
+            dir,
+            context: P::default_context(),
+        })
+    }
+
+    /// Reads the posts back with `context` rather than the default one.
+    pub fn with_context(mut self, context: P::Context) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Saves `post` as `id`, replacing the post saved before as `id`.
+    pub fn save(&self, id: &str, post: &P) -> Result<(), RepositoryError> {
+        let path = self.path(id)?;
+        let json = serde_json::to_vec_pretty(post).map_err(|error| RepositoryError::Format { id: id.to_string(), error })?;
+        // Written aside then renamed, so a crash leaves the old post whole
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Loads the post saved as `id`, in the state it was saved in.
+    pub fn load(&self, id: &str) -> Result<P, RepositoryError> {
+        let json = match fs::read(self.path(id)?) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(RepositoryError::NotFound(id.to_string())),
+            Err(e) => return Err(e.into()),
+        };
+        P::from_json(&json, &self.context).map_err(|error| RepositoryError::Format { id: id.to_string(), error })
+    }
+
+    /// The ids of all the posts, sorted.
+    pub fn ids(&self) -> Result<Vec<String>, RepositoryError> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                ids.extend(path.file_stem().and_then(|stem| stem.to_str()).map(String::from));
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// The ids of the posts in `state`, sorted.
+    pub fn list(&self, state: P::State) -> Result<Vec<String>, RepositoryError> {
+        let mut ids = Vec::new();
+        for id in self.ids()? {
+            if self.load(&id)?.state() == state {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn path(&self, id: &str) -> Result<PathBuf, RepositoryError> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(RepositoryError::InvalidId(id.to_string()));
+        }
+        Ok(self.dir.join(format!("{id}.json")))
+    }
+}
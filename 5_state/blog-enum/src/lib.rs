@@ -1,29 +1,124 @@
-#[derive(PartialEq)]
-enum State {
+pub mod repository;
+
+use std::error::Error;
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+pub use blog_common::audit::Action;
+pub use blog_common::policy::{Actor, ApprovalPolicy};
+
+use blog_common::policy::repeated_approval;
+
+/// A transition of a post, as kept in its log.
+pub type Entry = blog_common::audit::Entry<State>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
     Draft,
     PendingReview,
-    Published
+    /// Approved, and published once its publication time comes.
+    Scheduled,
+    Published,
+    Archived,
+}
+
+/// A post in the state, as in "cannot approve a draft".
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            State::Draft => "draft",
+            State::PendingReview => "post in review",
+            State::Scheduled => "scheduled post",
+            State::Published => "published post",
+            State::Archived => "archived post",
+        })
+    }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "Unchecked")]
 pub struct Post {
     state: State,
     content: String,
-    approve_counter: u8,
+    policy: ApprovalPolicy,
+    approvals: Vec<Actor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publish_at: Option<SystemTime>,
+    log: Vec<Entry>,
+}
+
+/// An action refused: the post stays as it was.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TransitionError {
+    /// The state of the post.
+    pub state: State,
+    pub action: Action,
+    pub reason: Reason,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The action does not apply to a post in the state.
+    NotAllowed,
+    /// The reviewer already approved the post since it was sent for review.
+    AlreadyApproved(String),
+    /// The publication time of the post has not come yet.
+    NotDue,
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.to_string();
+        let article = if state.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+        write!(f, "cannot {} {article} {state}", self.action)?;
+        match &self.reason {
+            Reason::NotAllowed => Ok(()),
+            Reason::AlreadyApproved(reviewer) => write!(f, ": {reviewer} already approved it"),
+            Reason::NotDue => f.write_str(" before its publication time"),
+        }
+    }
+}
+
+impl Error for TransitionError {}
+
+/// A draft under the default policy.
+impl Default for Post {
+    fn default() -> Self {
+        Post::new()
+    }
 }
 
 impl Post {
     pub fn new() -> Self {
+        Post::with_policy(ApprovalPolicy::default())
+    }
+
+    pub fn with_policy(policy: ApprovalPolicy) -> Self {
         Post {
             state: State::Draft,
             content: String::new(),
-            approve_counter: 0,
+            policy,
+            approvals: Vec::new(),
+            publish_at: None,
+            log: Vec::new(),
         }
     }
 
-    pub fn add_text(&mut self, text: &str) {
-        if self.state == State::Draft {
-            self.content.push_str(text)
-        }
+    pub fn add_text(&mut self, text: &str) -> Result<(), TransitionError> {
+        self.expect(&[State::Draft], Action::AddText)?;
+        self.content.push_str(text);
+        Ok(())
+    }
+
+    /// Sets when the post is published once approved; without a time, or
+    /// with one already past, approval publishes it at once.
+    pub fn schedule(&mut self, at: Option<SystemTime>) -> Result<(), TransitionError> {
+        self.expect(&[State::Draft], Action::Schedule)?;
+        self.publish_at = at;
+        Ok(())
     }
 
     pub fn content(&self) -> &str {
@@ -33,94 +128,465 @@ impl Post {
         }
     }
 
-    pub fn request_review(&mut self) {
-        if self.state == State::Draft {
-            self.state = State::PendingReview;
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn policy(&self) -> &ApprovalPolicy {
+        &self.policy
+    }
+
+    pub fn publish_at(&self) -> Option<SystemTime> {
+        self.publish_at
+    }
+
+    /// The approvals given since the post was last sent for review.
+    pub fn approvals(&self) -> &[Actor] {
+        &self.approvals
+    }
+
+    /// The transitions of the post, oldest first.
+    pub fn log(&self) -> &[Entry] {
+        &self.log
+    }
+
+    pub fn request_review(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.expect(&[State::Draft], Action::RequestReview)?;
+        self.state = State::PendingReview;
+        self.log.push(Entry::new(Action::RequestReview, actor, comment, self.state));
+        Ok(())
+    }
+
+    pub fn reject(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.expect(&[State::PendingReview], Action::Reject)?;
+        self.approvals.clear();
+        self.state = State::Draft;
+        self.log.push(Entry::new(Action::Reject, actor, comment, self.state));
+        Ok(())
+    }
+
+    pub fn approve(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.expect(&[State::PendingReview], Action::Approve)?;
+        if self.approvals.iter().any(|a| a.name == actor.name) {
+            return Err(TransitionError {
+                state: self.state,
+                action: Action::Approve,
+                reason: Reason::AlreadyApproved(actor.name.clone()),
+            });
+        }
+        self.approvals.push(actor.clone());
+        if self.policy.is_met(&self.approvals) {
+            self.state = match self.publish_at {
+                Some(at) if at > SystemTime::now() => State::Scheduled,
+                _ => State::Published,
+            };
+        }
+        self.log.push(Entry::new(Action::Approve, actor, comment, self.state));
+        Ok(())
+    }
+
+    /// Publishes a scheduled post whose publication time is `now` or earlier.
+    pub fn publish(&mut self, actor: &Actor, now: SystemTime) -> Result<(), TransitionError> {
+        self.expect(&[State::Scheduled], Action::Publish)?;
+        if self.publish_at.is_some_and(|at| at > now) {
+            return Err(TransitionError {
+                state: self.state,
+                action: Action::Publish,
+                reason: Reason::NotDue,
+            });
         }
+        self.state = State::Published;
+        self.log.push(Entry::new(Action::Publish, actor, None, self.state));
+        Ok(())
+    }
+
+    /// Takes a published or scheduled post back to draft.
+    pub fn unpublish(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.expect(&[State::Scheduled, State::Published], Action::Unpublish)?;
+        self.approvals.clear();
+        self.state = State::Draft;
+        self.log.push(Entry::new(Action::Unpublish, actor, comment, self.state));
+        Ok(())
     }
 
-    pub fn reject(&mut self) {
-        if self.state == State::PendingReview {
-            self.approve_counter = 0;
-            self.state = State::Draft;
+    /// Puts a draft or a published post away for good.
+    pub fn archive(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.expect(&[State::Draft, State::Published], Action::Archive)?;
+        self.state = State::Archived;
+        self.log.push(Entry::new(Action::Archive, actor, comment, self.state));
+        Ok(())
+    }
+
+    // Refuses `action` unless the post is in one of `states`
+    fn expect(&self, states: &[State], action: Action) -> Result<(), TransitionError> {
+        if states.contains(&self.state) {
+            Ok(())
+        } else {
+            Err(TransitionError {
+                state: self.state,
+                action,
+                reason: Reason::NotAllowed,
+            })
         }
     }
+}
 
-    pub fn approve(&mut self) {
-        if self.state == State::PendingReview {
-            self.approve_counter += 1;
-            if self.approve_counter == 2 {
-                self.state = State::Published;
-            }
+// A post as read, before it is checked to be one `Post` can be in.
+#[derive(Deserialize)]
+struct Unchecked {
+    state: State,
+    content: String,
+    #[serde(default)]
+    policy: ApprovalPolicy,
+    #[serde(default)]
+    approvals: Vec<Actor>,
+    #[serde(default)]
+    publish_at: Option<SystemTime>,
+    #[serde(default)]
+    log: Vec<Entry>,
+}
+
+impl TryFrom<Unchecked> for Post {
+    type Error = String;
+
+    fn try_from(post: Unchecked) -> Result<Self, Self::Error> {
+        if let Some(reviewer) = repeated_approval(&post.approvals) {
+            return Err(format!("{reviewer} approved the post twice"));
         }
+        if post.state == State::PendingReview && post.policy.is_met(&post.approvals) {
+            return Err("a post in review already has the approvals of its policy".to_string());
+        }
+        if post.state == State::Scheduled && post.publish_at.is_none() {
+            return Err("a scheduled post has no publication time".to_string());
+        }
+        Ok(Post {
+            state: post.state,
+            content: post.content,
+            policy: post.policy,
+            approvals: post.approvals,
+            publish_at: post.publish_at,
+            log: post.log,
+        })
     }
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
+    use std::time::Duration;
+
+    fn not_allowed(state: State, action: Action) -> Result<(), TransitionError> {
+        Err(TransitionError {
+            state,
+            action,
+            reason: Reason::NotAllowed,
+        })
+    }
 
     #[test]
     fn add_text() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
         let mut post = Post::new();
-        
-        post.add_text("Test");
+
+        post.add_text("Test").unwrap();
 
         assert_eq!("", post.content());
 
-        post.request_review();
+        post.request_review(&author, None).unwrap();
 
-        post.approve();
+        post.approve(&alice, None).unwrap();
         assert_eq!("", post.content());
 
-        post.approve();
-        
+        post.approve(&bob, None).unwrap();
+
         assert_eq!("Test", post.content());
     }
 
     #[test]
     fn reject_pending_review() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
         let mut post = Post::new();
 
-        post.add_text("Test");
+        post.add_text("Test").unwrap();
 
-        post.request_review();
+        post.request_review(&author, None).unwrap();
 
-        post.approve();
+        post.approve(&alice, None).unwrap();
 
         assert_eq!("", post.content());
 
-        post.reject();
+        post.reject(&bob, None).unwrap();
 
-        post.request_review();
+        post.request_review(&author, None).unwrap();
 
-        post.approve();
+        post.approve(&alice, None).unwrap();
 
-        post.approve();
+        post.approve(&bob, None).unwrap();
         assert_eq!("Test", post.content());
     }
 
     #[test]
     fn editable() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
         let mut post = Post::new();
 
-        post.add_text("Test");
-        post.add_text("Test");
+        post.add_text("Test").unwrap();
+        post.add_text("Test").unwrap();
 
-        post.request_review();
+        post.request_review(&author, None).unwrap();
 
-        post.add_text("Test");
+        assert_eq!(not_allowed(State::PendingReview, Action::AddText), post.add_text("Test"));
 
-        post.approve();
+        post.approve(&alice, None).unwrap();
 
-        post.add_text("Test");
+        assert_eq!(not_allowed(State::PendingReview, Action::AddText), post.add_text("Test"));
 
-        post.approve();
+        post.approve(&bob, None).unwrap();
 
-        post.add_text("Test");
+        assert_eq!(not_allowed(State::Published, Action::AddText), post.add_text("Test"));
 
         assert_eq!("TestTest", post.content());
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn invalid_transitions() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let mut post = Post::new();
+
+        assert_eq!(not_allowed(State::Draft, Action::Approve), post.approve(&alice, None));
+        assert_eq!(not_allowed(State::Draft, Action::Reject), post.reject(&alice, None));
+        post.request_review(&author, None).unwrap();
+        assert_eq!(not_allowed(State::PendingReview, Action::RequestReview), post.request_review(&author, None));
+        post.approve(&alice, None).unwrap();
+        post.approve(&bob, None).unwrap();
+        for (action, result) in [
+            (Action::RequestReview, post.request_review(&author, None)),
+            (Action::Approve, post.approve(&author, None)),
+            (Action::Reject, post.reject(&author, None)),
+        ] {
+            assert_eq!(not_allowed(State::Published, action), result);
+        }
+        assert_eq!(3, post.log().len());
+
+        let error = post.approve(&author, None).unwrap_err();
+        assert_eq!("cannot approve a published post", error.to_string());
+    }
+
+    // A post sent for review with `publish_at`, by Ann, and approved by
+    // Alice and Bob
+    fn approved(publish_at: Option<SystemTime>) -> Post {
+        let mut post = Post::new();
+        post.add_text("Test").unwrap();
+        post.schedule(publish_at).unwrap();
+        post.request_review(&Actor::new("Ann"), None).unwrap();
+        post.approve(&Actor::new("Alice"), None).unwrap();
+        post.approve(&Actor::new("Bob"), None).unwrap();
+        post
+    }
+
+    #[test]
+    fn scheduled() {
+        let editor = Actor::new("Ed");
+        let now = SystemTime::now();
+        let at = now + Duration::from_secs(3600);
+        let mut post = approved(Some(at));
+
+        assert_eq!(State::Scheduled, post.state());
+        assert_eq!("", post.content());
+        let error = post.publish(&editor, now).unwrap_err();
+        assert_eq!(Reason::NotDue, error.reason);
+        assert_eq!("cannot publish a scheduled post before its publication time", error.to_string());
+        assert_eq!(not_allowed(State::Scheduled, Action::Schedule), post.schedule(None));
+
+        post.publish(&editor, at).unwrap();
+        assert_eq!(State::Published, post.state());
+        assert_eq!("Test", post.content());
+        assert_eq!(Some((Action::Publish, State::Published)), post.log().last().map(|e| (e.action, e.state)));
+
+        // A publication time already past publishes on approval
+        let post = approved(Some(now - Duration::from_secs(1)));
+        assert_eq!(State::Published, post.state());
+        assert_eq!(not_allowed(State::Draft, Action::Publish), Post::new().publish(&editor, now));
+    }
+
+    #[test]
+    fn unpublish() {
+        let editor = Actor::new("Ed");
+        let mut post = approved(None);
+
+        post.unpublish(&editor, Some("Wrong date")).unwrap();
+        assert_eq!(State::Draft, post.state());
+        assert_eq!("", post.content());
+        post.add_text("!").unwrap();
+        assert_eq!(not_allowed(State::Draft, Action::Unpublish), post.unpublish(&editor, None));
+
+        let mut post = approved(Some(SystemTime::now() + Duration::from_secs(3600)));
+        post.unpublish(&editor, None).unwrap();
+        assert_eq!(State::Draft, post.state());
+
+        post.request_review(&editor, None).unwrap();
+        assert_eq!(not_allowed(State::PendingReview, Action::Unpublish), post.unpublish(&editor, None));
+    }
+
+    #[test]
+    fn archive() {
+        let editor = Actor::new("Ed");
+        let mut post = approved(None);
+
+        post.archive(&editor, None).unwrap();
+        assert_eq!(State::Archived, post.state());
+        assert_eq!("", post.content());
+        assert_eq!(not_allowed(State::Archived, Action::AddText), post.add_text("Test"));
+        assert_eq!(not_allowed(State::Archived, Action::RequestReview), post.request_review(&editor, None));
+        assert_eq!(not_allowed(State::Archived, Action::Unpublish), post.unpublish(&editor, None));
+        assert_eq!(not_allowed(State::Archived, Action::Archive), post.archive(&editor, None));
+        assert_eq!("cannot archive an archived post", post.archive(&editor, None).unwrap_err().to_string());
+
+        let mut post = Post::new();
+        post.archive(&editor, Some("Abandoned")).unwrap();
+        assert_eq!(State::Archived, post.state());
+
+        let mut post = Post::new();
+        post.request_review(&editor, None).unwrap();
+        assert_eq!(not_allowed(State::PendingReview, Action::Archive), post.archive(&editor, None));
+    }
+
+    #[test]
+    fn audit_log() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let mut post = Post::new();
+
+        post.request_review(&author, None).unwrap();
+        post.reject(&alice, Some("Too short")).unwrap();
+        assert!(post.approve(&alice, None).is_err());
+        post.request_review(&author, Some("Longer now")).unwrap();
+        post.approve(&alice, None).unwrap();
+        post.approve(&bob, Some("Fine")).unwrap();
+        assert!(post.reject(&bob, None).is_err());
+
+        let log: Vec<(Action, &str, Option<&str>, State)> = post
+            .log()
+            .iter()
+            .map(|e| (e.action, e.actor.as_str(), e.comment.as_deref(), e.state))
+            .collect();
+        assert_eq!(
+            vec![
+                (Action::RequestReview, "Ann", None, State::PendingReview),
+                (Action::Reject, "Alice", Some("Too short"), State::Draft),
+                (Action::RequestReview, "Ann", Some("Longer now"), State::PendingReview),
+                (Action::Approve, "Alice", None, State::PendingReview),
+                (Action::Approve, "Bob", Some("Fine"), State::Published),
+            ],
+            log
+        );
+        assert!(post.log().windows(2).all(|w| w[0].at <= w[1].at));
+    }
+
+    #[test]
+    fn duplicate_approval() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let mut post = Post::new();
+        post.add_text("Test").unwrap();
+        post.request_review(&author, None).unwrap();
+
+        post.approve(&alice, None).unwrap();
+        let error = post.approve(&alice, None).unwrap_err();
+        assert_eq!(Reason::AlreadyApproved("Alice".to_string()), error.reason);
+        assert_eq!("cannot approve a post in review: Alice already approved it", error.to_string());
+        assert_eq!(State::PendingReview, post.state());
+        assert_eq!(2, post.log().len());
+
+        post.approve(&bob, None).unwrap();
+        assert_eq!("Test", post.content());
+    }
+
+    // Sends `post` for review, approves it by `before`, rejects it, sends it
+    // again and approves it by `after`
+    fn approve_reject_approve(mut post: Post, before: &[&Actor], after: &[&Actor]) -> Post {
+        let author = Actor::new("Ann");
+        post.add_text("Test").unwrap();
+        post.request_review(&author, None).unwrap();
+        for reviewer in before {
+            post.approve(reviewer, None).unwrap();
+        }
+        post.reject(&author, None).unwrap();
+        post.request_review(&author, None).unwrap();
+        assert!(post.approvals().is_empty());
+        for reviewer in after {
+            post.approve(reviewer, None).unwrap();
+        }
+        post
+    }
+
+    #[test]
+    fn reject_resets_approvals() {
+        let (alice, bob, carol) = (Actor::new("Alice"), Actor::new("Bob"), Actor::new("Carol"));
+        let policy = ApprovalPolicy::approvals(3);
+
+        let post = approve_reject_approve(Post::with_policy(policy.clone()), &[&alice, &bob], &[&carol]);
+        assert_eq!(State::PendingReview, post.state());
+
+        let post = approve_reject_approve(Post::with_policy(policy), &[&alice, &bob], &[&alice, &bob, &carol]);
+        assert_eq!(State::Published, post.state());
+    }
+
+    #[test]
+    fn reject_resets_reviewers() {
+        let (alice, bob) = (Actor::new("Alice"), Actor::new("Bob"));
+        let policy = ApprovalPolicy::approvals(2);
+
+        // Who approved before the rejection may approve again
+        let post = approve_reject_approve(Post::with_policy(policy), &[&alice], &[&alice, &bob]);
+        assert_eq!(State::Published, post.state());
+    }
+
+    #[test]
+    fn reject_resets_required_roles() {
+        let (alice, carol) = (Actor::new("Alice"), Actor::new("Carol"));
+        let lawyer = Actor::new("Bob").with_role("legal");
+        let policy = ApprovalPolicy::approvals(2).require_role("legal");
+
+        let post = approve_reject_approve(Post::with_policy(policy.clone()), &[&lawyer], &[&alice, &carol]);
+        assert_eq!(State::PendingReview, post.state());
+
+        let post = approve_reject_approve(Post::with_policy(policy), &[&lawyer], &[&alice, &lawyer]);
+        assert_eq!(State::Published, post.state());
+        assert_eq!("Test", post.content());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (author, alice) = (Actor::new("Ann"), Actor::new("Alice"));
+        let mut post = Post::with_policy(ApprovalPolicy::approvals(2).require_role("legal"));
+        post.add_text("Test").unwrap();
+        post.request_review(&author, None).unwrap();
+        post.approve(&alice, Some("Good")).unwrap();
+
+        let saved = serde_json::to_string(&post).unwrap();
+        assert!(saved.starts_with(
+            r#"{"state":"pending_review","content":"Test","policy":{"approvals":2,"required_roles":["legal"]},"approvals":[{"name":"Alice"}],"log":["#
+        ));
+
+        let mut post: Post = serde_json::from_str(&saved).unwrap();
+        assert_eq!(State::PendingReview, post.state());
+        assert_eq!(2, post.log().len());
+        assert_eq!(Some("Good"), post.log()[1].comment.as_deref());
+        assert!(post.approve(&alice, None).is_err());
+        post.approve(&Actor::new("Bob").with_role("legal"), None).unwrap();
+        assert_eq!("Test", post.content());
+
+        let post: Post = serde_json::from_str(r#"{"state":"draft","content":"Test"}"#).unwrap();
+        assert_eq!(State::Draft, post.state());
+        assert_eq!(&ApprovalPolicy::default(), post.policy());
+        let met = r#"{"state":"pending_review","content":"","approvals":[{"name":"Alice"},{"name":"Bob"}]}"#;
+        assert!(serde_json::from_str::<Post>(met).is_err());
+        let twice = r#"{"state":"pending_review","content":"","policy":{"approvals":3},"approvals":[{"name":"Alice"},{"name":"Alice"}]}"#;
+        assert_eq!("Alice approved the post twice", serde_json::from_str::<Post>(twice).err().unwrap().to_string());
+        assert!(serde_json::from_str::<Post>(r#"{"state":"scheduled","content":""}"#).is_err());
+
+        let at = SystemTime::now() + Duration::from_secs(3600);
+        let post: Post = serde_json::from_str(&serde_json::to_string(&approved(Some(at))).unwrap()).unwrap();
+        assert_eq!((State::Scheduled, Some(at)), (post.state(), post.publish_at()));
+    }
+
+}
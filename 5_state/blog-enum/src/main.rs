@@ -1,29 +1,30 @@
-use blog_enum::Post;
+use blog_enum::{Actor, Post};
 
 fn main() {
+    let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
     let mut post = Post::new();
 
-    post.add_text("Test, test");
+    post.add_text("Test, test").unwrap();
 
     assert_eq!("", post.content());
 
-    post.request_review();
+    post.request_review(&author, None).unwrap();
     assert_eq!("", post.content());
     
-    post.approve();
+    post.approve(&alice, None).unwrap();
     assert_eq!("", post.content());
 
-    post.reject();
+    post.reject(&bob, Some("Needs work")).unwrap();
 
-    post.request_review();
+    post.request_review(&author, None).unwrap();
     assert_eq!("", post.content());
 
-    post.approve();
+    post.approve(&alice, None).unwrap();
     assert_eq!("", post.content());
 
-    post.approve();
+    post.approve(&bob, None).unwrap();
     assert_eq!("Test, test", post.content());
 
-    post.add_text("Test, test");
+    assert!(post.add_text("Test, test").is_err());
     assert_eq!("Test, test", post.content());
 }
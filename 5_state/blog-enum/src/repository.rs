@@ -0,0 +1,95 @@
+// Posts saved on disk, in the repository of `blog_common`.
+
+pub use blog_common::repository::RepositoryError;
+use blog_common::repository::Stored;
+
+use crate::{Post, State};
+
+pub type Repository = blog_common::repository::Repository<Post>;
+
+impl Stored for Post {
+    type State = State;
+    type Context = ();
+
+    fn default_context() {}
+
+    fn state(&self) -> State {
+        Post::state(self)
+    }
+
+    fn from_json(json: &[u8], _: &()) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Actor;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    // An empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("blog-enum-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn save_load_and_list() {
+        let dir = temp_dir("list");
+        let repository = Repository::open(&dir).unwrap();
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+
+        let mut draft = Post::new();
+        draft.add_text("Draft").unwrap();
+        repository.save("draft", &draft).unwrap();
+
+        let mut review = Post::new();
+        review.add_text("Review").unwrap();
+        review.request_review(&author, None).unwrap();
+        review.approve(&alice, None).unwrap();
+        repository.save("review", &review).unwrap();
+
+        let mut published = Post::new();
+        published.add_text("Published").unwrap();
+        published.request_review(&author, None).unwrap();
+        published.approve(&alice, None).unwrap();
+        published.approve(&bob, None).unwrap();
+        repository.save("published", &published).unwrap();
+
+        let repository = Repository::open(&dir).unwrap();
+        assert_eq!(vec!["draft", "published", "review"], repository.ids().unwrap());
+        assert_eq!(vec!["draft"], repository.list(State::Draft).unwrap());
+        assert_eq!(vec!["review"], repository.list(State::PendingReview).unwrap());
+        assert_eq!(vec!["published"], repository.list(State::Published).unwrap());
+
+        // The approval made before saving still counts
+        let mut review = repository.load("review").unwrap();
+        review.approve(&bob, None).unwrap();
+        assert_eq!("Review", review.content());
+        repository.save("review", &review).unwrap();
+        assert_eq!(vec!["published", "review"], repository.list(State::Published).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_ids_and_files() {
+        let dir = temp_dir("errors");
+        let repository = Repository::open(&dir).unwrap();
+
+        assert!(matches!(repository.save("../x", &Post::new()), Err(RepositoryError::InvalidId(_))));
+        assert!(matches!(repository.load(""), Err(RepositoryError::InvalidId(_))));
+        assert!(matches!(repository.load("missing"), Err(RepositoryError::NotFound(_))));
+
+        fs::write(dir.join("broken.json"), "{").unwrap();
+        assert!(matches!(repository.load("broken"), Err(RepositoryError::Format { .. })));
+        assert!(repository.list(State::Draft).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
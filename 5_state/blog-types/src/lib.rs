@@ -1,21 +1,153 @@
+pub mod repository;
+
+use std::error::Error;
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+pub use blog_common::audit::Action;
+pub use blog_common::policy::{Actor, ApprovalPolicy};
+
+use blog_common::policy::repeated_approval;
+
+/// A transition of a post, as kept in its log.
+pub type Entry = blog_common::audit::Entry<Status>;
+
+/// The state a post is in, as saved and listed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Draft,
+    PendingReview,
+    /// Approved, and published once its publication time comes.
+    Scheduled,
+    Published,
+    Archived,
+}
+
+/// A post in the state, as in "cannot approve a draft".
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Status::Draft => "draft",
+            Status::PendingReview => "post in review",
+            Status::Scheduled => "scheduled post",
+            Status::Published => "published post",
+            Status::Archived => "archived post",
+        })
+    }
+}
+
+/// An action refused. The types of the posts only allow the actions their
+/// states allow, so this comes from approving twice, from publishing before
+/// the publication time, or from acting on an `AnyPost`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TransitionError {
+    /// The state of the post.
+    pub state: Status,
+    pub action: Action,
+    pub reason: Reason,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The action does not apply to a post in the state.
+    NotAllowed,
+    /// The reviewer already approved the post since it was sent for review.
+    AlreadyApproved(String),
+    /// The publication time of the post has not come yet.
+    NotDue,
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.to_string();
+        let article = if state.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+        write!(f, "cannot {} {article} {state}", self.action)?;
+        match &self.reason {
+            Reason::NotAllowed => Ok(()),
+            Reason::AlreadyApproved(reviewer) => write!(f, ": {reviewer} already approved it"),
+            Reason::NotDue => f.write_str(" before its publication time"),
+        }
+    }
+}
+
+impl Error for TransitionError {}
+
+/// A refused action, with the post it was tried on, as it was.
+#[derive(Debug)]
+pub struct Refused<P> {
+    pub error: TransitionError,
+    pub post: Box<P>,
+}
+
+impl<P> fmt::Display for Refused<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<P: fmt::Debug> Error for Refused<P> {}
+
+#[derive(Debug)]
 pub struct Post {
     content: String,
+    policy: ApprovalPolicy,
+    publish_at: Option<SystemTime>,
+    log: Vec<Entry>,
 }
 
+#[derive(Debug)]
 pub struct DraftPost {
     content: String,
+    policy: ApprovalPolicy,
+    publish_at: Option<SystemTime>,
+    log: Vec<Entry>,
 }
 
 impl Post {
+    // A post starts as a draft, the type of its first state
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> DraftPost {
+        Post::with_policy(ApprovalPolicy::default())
+    }
+
+    pub fn with_policy(policy: ApprovalPolicy) -> DraftPost {
         DraftPost {
             content: String::new(),
+            policy,
+            publish_at: None,
+            log: Vec::new(),
         }
     }
 
     pub fn content(&self) -> &str {
         &self.content
     }
+
+    /// The transitions of the post, oldest first.
+    pub fn log(&self) -> &[Entry] {
+        &self.log
+    }
+
+    pub fn unpublish(mut self, actor: &Actor, comment: Option<&str>) -> DraftPost {
+        self.log.push(Entry::new(Action::Unpublish, actor, comment, Status::Draft));
+        DraftPost {
+            content: self.content,
+            policy: self.policy,
+            publish_at: self.publish_at,
+            log: self.log,
+        }
+    }
+
+    pub fn archive(mut self, actor: &Actor, comment: Option<&str>) -> ArchivedPost {
+        self.log.push(Entry::new(Action::Archive, actor, comment, Status::Archived));
+        ArchivedPost {
+            content: self.content,
+            log: self.log,
+        }
+    }
 }
 
 impl DraftPost {
@@ -23,41 +155,701 @@ impl DraftPost {
         self.content.push_str(text);
     }
 
-    pub fn request_review(self) -> PendingReviewPostV1 {
-        PendingReviewPostV1 {
+    /// Sets when the post is published once approved; without a time, or
+    /// with one already past, approval publishes it at once.
+    pub fn schedule(&mut self, at: Option<SystemTime>) {
+        self.publish_at = at;
+    }
+
+    pub fn publish_at(&self) -> Option<SystemTime> {
+        self.publish_at
+    }
+
+    /// The transitions of the post, oldest first.
+    pub fn log(&self) -> &[Entry] {
+        &self.log
+    }
+
+    pub fn request_review(mut self, actor: &Actor, comment: Option<&str>) -> PendingReviewPost {
+        self.log.push(Entry::new(Action::RequestReview, actor, comment, Status::PendingReview));
+        PendingReviewPost {
             content: self.content,
+            policy: self.policy,
+            publish_at: self.publish_at,
+            approvals: Vec::new(),
+            log: self.log,
+        }
+    }
+
+    pub fn archive(mut self, actor: &Actor, comment: Option<&str>) -> ArchivedPost {
+        self.log.push(Entry::new(Action::Archive, actor, comment, Status::Archived));
+        ArchivedPost {
+            content: self.content,
+            log: self.log,
         }
     }
 }
 
-pub struct PendingReviewPostV1 {
+#[derive(Debug)]
+pub struct PendingReviewPost {
     content: String,
+    policy: ApprovalPolicy,
+    publish_at: Option<SystemTime>,
+    approvals: Vec<Actor>,
+    log: Vec<Entry>,
+}
+
+/// What approving a post in review leads to: whether the approvals meet
+/// the policy of the post is only known when the post runs.
+#[derive(Debug)]
+pub enum Approval {
+    Pending(PendingReviewPost),
+    Scheduled(ScheduledPost),
+    Published(Post),
+}
+
+impl Approval {
+    pub fn pending(self) -> Option<PendingReviewPost> {
+        match self {
+            Approval::Pending(post) => Some(post),
+            _ => None,
+        }
+    }
+
+    pub fn scheduled(self) -> Option<ScheduledPost> {
+        match self {
+            Approval::Scheduled(post) => Some(post),
+            _ => None,
+        }
+    }
+
+    pub fn published(self) -> Option<Post> {
+        match self {
+            Approval::Published(post) => Some(post),
+            _ => None,
+        }
+    }
 }
 
-impl PendingReviewPostV1 {
-    pub fn approve(self) -> PendingReviewPostV2 {
-        PendingReviewPostV2 {content: self.content}
+impl PendingReviewPost {
+    /// The approvals given since the post was sent for review.
+    pub fn approvals(&self) -> &[Actor] {
+        &self.approvals
+    }
+
+    /// The transitions of the post, oldest first.
+    pub fn log(&self) -> &[Entry] {
+        &self.log
+    }
+
+    pub fn approve(mut self, actor: &Actor, comment: Option<&str>) -> Result<Approval, Refused<PendingReviewPost>> {
+        if self.approvals.iter().any(|a| a.name == actor.name) {
+            return Err(Refused {
+                error: TransitionError {
+                    state: Status::PendingReview,
+                    action: Action::Approve,
+                    reason: Reason::AlreadyApproved(actor.name.clone()),
+                },
+                post: Box::new(self),
+            });
+        }
+        self.approvals.push(actor.clone());
+        if !self.policy.is_met(&self.approvals) {
+            self.log.push(Entry::new(Action::Approve, actor, comment, Status::PendingReview));
+            return Ok(Approval::Pending(self));
+        }
+        match self.publish_at {
+            Some(at) if at > SystemTime::now() => {
+                self.log.push(Entry::new(Action::Approve, actor, comment, Status::Scheduled));
+                Ok(Approval::Scheduled(ScheduledPost {
+                    content: self.content,
+                    policy: self.policy,
+                    publish_at: at,
+                    log: self.log,
+                }))
+            }
+            _ => {
+                self.log.push(Entry::new(Action::Approve, actor, comment, Status::Published));
+                Ok(Approval::Published(Post {
+                    content: self.content,
+                    policy: self.policy,
+                    publish_at: self.publish_at,
+                    log: self.log,
+                }))
+            }
+        }
     }
 
-    pub fn reject(self) -> DraftPost {
+    pub fn reject(mut self, actor: &Actor, comment: Option<&str>) -> DraftPost {
+        self.log.push(Entry::new(Action::Reject, actor, comment, Status::Draft));
         DraftPost {
             content: self.content,
+            policy: self.policy,
+            publish_at: self.publish_at,
+            log: self.log,
         }
     }
 }
 
-pub struct PendingReviewPostV2 {
+/// Approved, and published once its publication time comes.
+#[derive(Debug)]
+pub struct ScheduledPost {
     content: String,
+    policy: ApprovalPolicy,
+    publish_at: SystemTime,
+    log: Vec<Entry>,
 }
 
-impl PendingReviewPostV2 {
-    pub fn approve(self) -> Post {
-        Post { content: self.content }
+impl ScheduledPost {
+    pub fn publish_at(&self) -> SystemTime {
+        self.publish_at
     }
 
-    pub fn reject(self) -> DraftPost {
+    /// The transitions of the post, oldest first.
+    pub fn log(&self) -> &[Entry] {
+        &self.log
+    }
+
+    /// Publishes the post if its publication time is `now` or earlier.
+    pub fn publish(mut self, actor: &Actor, now: SystemTime) -> Result<Post, Refused<ScheduledPost>> {
+        if self.publish_at > now {
+            return Err(Refused {
+                error: TransitionError {
+                    state: Status::Scheduled,
+                    action: Action::Publish,
+                    reason: Reason::NotDue,
+                },
+                post: Box::new(self),
+            });
+        }
+        self.log.push(Entry::new(Action::Publish, actor, None, Status::Published));
+        Ok(Post {
+            content: self.content,
+            policy: self.policy,
+            publish_at: Some(self.publish_at),
+            log: self.log,
+        })
+    }
+
+    pub fn unpublish(mut self, actor: &Actor, comment: Option<&str>) -> DraftPost {
+        self.log.push(Entry::new(Action::Unpublish, actor, comment, Status::Draft));
         DraftPost {
             content: self.content,
+            policy: self.policy,
+            publish_at: Some(self.publish_at),
+            log: self.log,
+        }
+    }
+}
+
+/// Put away for good: an archived post allows no action.
+#[derive(Debug)]
+pub struct ArchivedPost {
+    content: String,
+    log: Vec<Entry>,
+}
+
+impl ArchivedPost {
+    /// The transitions of the post, oldest first.
+    pub fn log(&self) -> &[Entry] {
+        &self.log
+    }
+}
+
+/// A post in whichever state it is in. The type of a post is its state, so
+/// a loaded post comes as this, to be matched to get the post back or acted
+/// on with the state checked when the program runs.
+#[derive(Debug)]
+pub enum AnyPost {
+    Draft(DraftPost),
+    PendingReview(PendingReviewPost),
+    Scheduled(ScheduledPost),
+    Published(Post),
+    Archived(ArchivedPost),
+}
+
+impl AnyPost {
+    pub fn status(&self) -> Status {
+        match self {
+            AnyPost::Draft(_) => Status::Draft,
+            AnyPost::PendingReview(_) => Status::PendingReview,
+            AnyPost::Scheduled(_) => Status::Scheduled,
+            AnyPost::Published(_) => Status::Published,
+            AnyPost::Archived(_) => Status::Archived,
+        }
+    }
+
+    pub fn add_text(&mut self, text: &str) -> Result<(), TransitionError> {
+        match self {
+            AnyPost::Draft(post) => {
+                post.add_text(text);
+                Ok(())
+            }
+            post => Err(post.not_allowed(Action::AddText)),
+        }
+    }
+
+    pub fn schedule(&mut self, at: Option<SystemTime>) -> Result<(), TransitionError> {
+        match self {
+            AnyPost::Draft(post) => {
+                post.schedule(at);
+                Ok(())
+            }
+            post => Err(post.not_allowed(Action::Schedule)),
+        }
+    }
+
+    pub fn request_review(self, actor: &Actor, comment: Option<&str>) -> Result<AnyPost, Refused<AnyPost>> {
+        match self {
+            AnyPost::Draft(post) => Ok(post.request_review(actor, comment).into()),
+            post => Err(post.refuse(Action::RequestReview)),
+        }
+    }
+
+    pub fn approve(self, actor: &Actor, comment: Option<&str>) -> Result<AnyPost, Refused<AnyPost>> {
+        match self {
+            AnyPost::PendingReview(post) => match post.approve(actor, comment) {
+                Ok(approval) => Ok(approval.into()),
+                Err(Refused { error, post }) => Err(Refused {
+                    error,
+                    post: Box::new(AnyPost::PendingReview(*post)),
+                }),
+            },
+            post => Err(post.refuse(Action::Approve)),
+        }
+    }
+
+    pub fn reject(self, actor: &Actor, comment: Option<&str>) -> Result<AnyPost, Refused<AnyPost>> {
+        match self {
+            AnyPost::PendingReview(post) => Ok(post.reject(actor, comment).into()),
+            post => Err(post.refuse(Action::Reject)),
+        }
+    }
+
+    pub fn publish(self, actor: &Actor, now: SystemTime) -> Result<AnyPost, Refused<AnyPost>> {
+        match self {
+            AnyPost::Scheduled(post) => match post.publish(actor, now) {
+                Ok(post) => Ok(post.into()),
+                Err(Refused { error, post }) => Err(Refused {
+                    error,
+                    post: Box::new(AnyPost::Scheduled(*post)),
+                }),
+            },
+            post => Err(post.refuse(Action::Publish)),
         }
     }
-}
\ No newline at end of file
+
+    pub fn unpublish(self, actor: &Actor, comment: Option<&str>) -> Result<AnyPost, Refused<AnyPost>> {
+        match self {
+            AnyPost::Scheduled(post) => Ok(post.unpublish(actor, comment).into()),
+            AnyPost::Published(post) => Ok(post.unpublish(actor, comment).into()),
+            post => Err(post.refuse(Action::Unpublish)),
+        }
+    }
+
+    pub fn archive(self, actor: &Actor, comment: Option<&str>) -> Result<AnyPost, Refused<AnyPost>> {
+        match self {
+            AnyPost::Draft(post) => Ok(post.archive(actor, comment).into()),
+            AnyPost::Published(post) => Ok(post.archive(actor, comment).into()),
+            post => Err(post.refuse(Action::Archive)),
+        }
+    }
+
+    fn not_allowed(&self, action: Action) -> TransitionError {
+        TransitionError {
+            state: self.status(),
+            action,
+            reason: Reason::NotAllowed,
+        }
+    }
+
+    fn refuse(self, action: Action) -> Refused<AnyPost> {
+        Refused {
+            error: self.not_allowed(action),
+            post: Box::new(self),
+        }
+    }
+}
+
+impl From<DraftPost> for AnyPost {
+    fn from(post: DraftPost) -> Self {
+        AnyPost::Draft(post)
+    }
+}
+
+impl From<PendingReviewPost> for AnyPost {
+    fn from(post: PendingReviewPost) -> Self {
+        AnyPost::PendingReview(post)
+    }
+}
+
+impl From<ScheduledPost> for AnyPost {
+    fn from(post: ScheduledPost) -> Self {
+        AnyPost::Scheduled(post)
+    }
+}
+
+impl From<Approval> for AnyPost {
+    fn from(approval: Approval) -> Self {
+        match approval {
+            Approval::Pending(post) => AnyPost::PendingReview(post),
+            Approval::Scheduled(post) => AnyPost::Scheduled(post),
+            Approval::Published(post) => AnyPost::Published(post),
+        }
+    }
+}
+
+impl From<Post> for AnyPost {
+    fn from(post: Post) -> Self {
+        AnyPost::Published(post)
+    }
+}
+
+impl From<ArchivedPost> for AnyPost {
+    fn from(post: ArchivedPost) -> Self {
+        AnyPost::Archived(post)
+    }
+}
+
+// The saved form of a post. An archived post keeps no policy.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    state: Status,
+    content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    policy: Option<ApprovalPolicy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    approvals: Vec<Actor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    publish_at: Option<SystemTime>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    log: Vec<Entry>,
+}
+
+impl Serialize for AnyPost {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (content, policy, approvals, publish_at, log) = match self {
+            AnyPost::Draft(post) => (&post.content, Some(&post.policy), &[][..], post.publish_at, &post.log),
+            AnyPost::PendingReview(post) => (&post.content, Some(&post.policy), &post.approvals[..], post.publish_at, &post.log),
+            AnyPost::Scheduled(post) => (&post.content, Some(&post.policy), &[][..], Some(post.publish_at), &post.log),
+            AnyPost::Published(post) => (&post.content, Some(&post.policy), &[][..], post.publish_at, &post.log),
+            AnyPost::Archived(post) => (&post.content, None, &[][..], None, &post.log),
+        };
+        let record = Record {
+            state: self.status(),
+            content: content.clone(),
+            policy: policy.cloned(),
+            approvals: approvals.to_vec(),
+            publish_at,
+            log: log.clone(),
+        };
+        record.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AnyPost {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let Record { state, content, policy, approvals, publish_at, log } = Record::deserialize(deserializer)?;
+        let policy = policy.unwrap_or_default();
+        if let Some(reviewer) = repeated_approval(&approvals) {
+            return Err(de::Error::custom(format!("{reviewer} approved the post twice")));
+        }
+        Ok(match (state, publish_at) {
+            (Status::Draft, _) => AnyPost::Draft(DraftPost { content, policy, publish_at, log }),
+            (Status::PendingReview, _) if !policy.is_met(&approvals) => AnyPost::PendingReview(PendingReviewPost {
+                content,
+                policy,
+                publish_at,
+                approvals,
+                log,
+            }),
+            (Status::PendingReview, _) => return Err(de::Error::custom("a post in review already has the approvals of its policy")),
+            (Status::Scheduled, Some(publish_at)) => AnyPost::Scheduled(ScheduledPost { content, policy, publish_at, log }),
+            (Status::Scheduled, None) => return Err(de::Error::custom("a scheduled post has no publication time")),
+            (Status::Published, _) => AnyPost::Published(Post { content, policy, publish_at, log }),
+            (Status::Archived, _) => AnyPost::Archived(ArchivedPost { content, log }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn request_review_and_approve() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let mut post = Post::new();
+        post.add_text("Test");
+
+        let post = post.request_review(&author, None).approve(&alice, None).unwrap().pending().unwrap();
+        let mut post = post.reject(&bob, None);
+        post.add_text("Test");
+
+        let post = post.request_review(&author, None).approve(&alice, None).unwrap().pending().unwrap();
+        let post = post.approve(&bob, None).unwrap().published().unwrap();
+        assert_eq!("TestTest", post.content());
+    }
+
+    #[test]
+    fn audit_log() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+
+        let post = Post::new().request_review(&author, None).reject(&alice, Some("Too short"));
+        let post = post.request_review(&author, Some("Longer now"));
+        let post = post.approve(&alice, None).unwrap().pending().unwrap();
+        let post = post.approve(&bob, Some("Fine")).unwrap().published().unwrap();
+
+        let log: Vec<(Action, &str, Option<&str>, Status)> = post
+            .log()
+            .iter()
+            .map(|e| (e.action, e.actor.as_str(), e.comment.as_deref(), e.state))
+            .collect();
+        assert_eq!(
+            vec![
+                (Action::RequestReview, "Ann", None, Status::PendingReview),
+                (Action::Reject, "Alice", Some("Too short"), Status::Draft),
+                (Action::RequestReview, "Ann", Some("Longer now"), Status::PendingReview),
+                (Action::Approve, "Alice", None, Status::PendingReview),
+                (Action::Approve, "Bob", Some("Fine"), Status::Published),
+            ],
+            log
+        );
+        assert!(post.log().windows(2).all(|w| w[0].at <= w[1].at));
+    }
+
+    #[test]
+    fn duplicate_approval() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let mut post = Post::new();
+        post.add_text("Test");
+
+        let post = post.request_review(&author, None).approve(&alice, None).unwrap().pending().unwrap();
+        let refused = post.approve(&alice, None).unwrap_err();
+        assert_eq!(Reason::AlreadyApproved("Alice".to_string()), refused.error.reason);
+        assert_eq!("cannot approve a post in review: Alice already approved it", refused.to_string());
+        assert_eq!(1, refused.post.approvals().len());
+        assert_eq!(2, refused.post.log().len());
+
+        let post = refused.post.approve(&bob, None).unwrap().published().unwrap();
+        assert_eq!("Test", post.content());
+    }
+
+    fn approved(publish_at: Option<SystemTime>) -> Approval {
+        let mut post = Post::new();
+        post.add_text("Test");
+        post.schedule(publish_at);
+        let post = post.request_review(&Actor::new("Ann"), None);
+        let post = post.approve(&Actor::new("Alice"), None).unwrap().pending().unwrap();
+        post.approve(&Actor::new("Bob"), None).unwrap()
+    }
+
+    #[test]
+    fn scheduled() {
+        let editor = Actor::new("Ed");
+        let now = SystemTime::now();
+        let at = now + Duration::from_secs(3600);
+        let post = approved(Some(at)).scheduled().unwrap();
+
+        assert_eq!(at, post.publish_at());
+        let refused = post.publish(&editor, now).unwrap_err();
+        assert_eq!(Reason::NotDue, refused.error.reason);
+        assert_eq!("cannot publish a scheduled post before its publication time", refused.to_string());
+
+        let post = refused.post.publish(&editor, at).unwrap();
+        assert_eq!("Test", post.content());
+        assert_eq!(Some((Action::Publish, Status::Published)), post.log().last().map(|e| (e.action, e.state)));
+
+        // A publication time already past publishes on approval
+        let post = approved(Some(now - Duration::from_secs(1)));
+        assert!(matches!(post, Approval::Published(_)));
+    }
+
+    #[test]
+    fn unpublish() {
+        let editor = Actor::new("Ed");
+        let post = approved(None).published().unwrap();
+
+        let mut post = post.unpublish(&editor, Some("Wrong date"));
+        post.add_text("!");
+        assert_eq!(Some((Action::Unpublish, Some("Wrong date"))), post.log().last().map(|e| (e.action, e.comment.as_deref())));
+
+        let at = SystemTime::now() + Duration::from_secs(3600);
+        let post = approved(Some(at)).scheduled().unwrap().unpublish(&editor, None);
+        assert_eq!(Some(at), post.publish_at());
+    }
+
+    #[test]
+    fn archive() {
+        let editor = Actor::new("Ed");
+
+        let post = approved(None).published().unwrap().archive(&editor, None);
+        assert_eq!(Some((Action::Archive, Status::Archived)), post.log().last().map(|e| (e.action, e.state)));
+
+        let post = Post::new().archive(&editor, Some("Abandoned"));
+        assert_eq!(1, post.log().len());
+    }
+
+    #[test]
+    fn any_post_transitions() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let not_allowed = |state, action| TransitionError {
+            state,
+            action,
+            reason: Reason::NotAllowed,
+        };
+        let mut post = AnyPost::from(Post::new());
+        post.add_text("Test").unwrap();
+
+        let refused = post.approve(&alice, None).unwrap_err();
+        assert_eq!(not_allowed(Status::Draft, Action::Approve), refused.error);
+        let refused = refused.post.reject(&alice, None).unwrap_err();
+        assert_eq!(not_allowed(Status::Draft, Action::Reject), refused.error);
+
+        let mut post = refused.post.request_review(&author, None).unwrap();
+        assert_eq!(Err(not_allowed(Status::PendingReview, Action::AddText)), post.add_text("Test"));
+        let refused = post.request_review(&author, None).unwrap_err();
+        assert_eq!(not_allowed(Status::PendingReview, Action::RequestReview), refused.error);
+        let post = refused.post.approve(&alice, None).unwrap();
+        let refused = post.approve(&alice, None).unwrap_err();
+        assert_eq!(Reason::AlreadyApproved("Alice".to_string()), refused.error.reason);
+        assert_eq!(Status::PendingReview, refused.post.status());
+
+        let mut post = refused.post.approve(&bob, None).unwrap();
+        assert_eq!(Status::Published, post.status());
+        assert_eq!(Err(not_allowed(Status::Published, Action::AddText)), post.add_text("Test"));
+        let refused = post.reject(&bob, None).unwrap_err();
+        assert_eq!("cannot reject a published post", refused.to_string());
+        match *refused.post {
+            AnyPost::Published(post) => assert_eq!("Test", post.content()),
+            _ => panic!("expected a published post"),
+        }
+    }
+
+    #[test]
+    fn any_post_schedule_unpublish_archive() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let now = SystemTime::now();
+        let at = now + Duration::from_secs(3600);
+        let mut post = AnyPost::from(Post::new());
+        post.schedule(Some(at)).unwrap();
+
+        let refused = post.publish(&author, now).unwrap_err();
+        assert_eq!("cannot publish a draft", refused.to_string());
+        let post = refused.post.request_review(&author, None).unwrap();
+        let post = post.approve(&alice, None).unwrap().approve(&bob, None).unwrap();
+        assert_eq!(Status::Scheduled, post.status());
+
+        let refused = post.archive(&author, None).unwrap_err();
+        assert_eq!("cannot archive a scheduled post", refused.to_string());
+        let refused = refused.post.publish(&author, now).unwrap_err();
+        assert_eq!(Reason::NotDue, refused.error.reason);
+        assert_eq!(Status::Scheduled, refused.post.status());
+        let post = refused.post.publish(&author, at).unwrap();
+        assert_eq!(Status::Published, post.status());
+
+        let mut post = post.unpublish(&author, None).unwrap();
+        assert_eq!(Status::Draft, post.status());
+        post.schedule(None).unwrap();
+        let mut post = post.archive(&author, None).unwrap();
+        assert_eq!(Status::Archived, post.status());
+        assert_eq!("cannot schedule an archived post", post.schedule(None).unwrap_err().to_string());
+        let refused = post.unpublish(&author, None).unwrap_err();
+        assert_eq!("cannot unpublish an archived post", refused.to_string());
+    }
+
+    // Sends `post` for review, approves it by `before`, rejects it, sends it
+    // again and approves it by `after`
+    fn approve_reject_approve(mut post: DraftPost, before: &[&Actor], after: &[&Actor]) -> Approval {
+        let author = Actor::new("Ann");
+        post.add_text("Test");
+        let mut review = post.request_review(&author, None);
+        for reviewer in before {
+            review = review.approve(reviewer, None).unwrap().pending().unwrap();
+        }
+        let mut review = review.reject(&author, None).request_review(&author, None);
+        assert!(review.approvals().is_empty());
+        let (last, after) = after.split_last().unwrap();
+        for reviewer in after {
+            review = review.approve(reviewer, None).unwrap().pending().unwrap();
+        }
+        review.approve(last, None).unwrap()
+    }
+
+    #[test]
+    fn reject_resets_approvals() {
+        let (alice, bob, carol) = (Actor::new("Alice"), Actor::new("Bob"), Actor::new("Carol"));
+        let policy = ApprovalPolicy::approvals(3);
+
+        let approval = approve_reject_approve(Post::with_policy(policy.clone()), &[&alice, &bob], &[&carol]);
+        assert!(matches!(approval, Approval::Pending(_)));
+
+        let approval = approve_reject_approve(Post::with_policy(policy), &[&alice, &bob], &[&alice, &bob, &carol]);
+        assert!(matches!(approval, Approval::Published(_)));
+    }
+
+    #[test]
+    fn reject_resets_reviewers() {
+        let (alice, bob) = (Actor::new("Alice"), Actor::new("Bob"));
+        let policy = ApprovalPolicy::approvals(2);
+
+        // Who approved before the rejection may approve again
+        let approval = approve_reject_approve(Post::with_policy(policy), &[&alice], &[&alice, &bob]);
+        assert!(matches!(approval, Approval::Published(_)));
+    }
+
+    #[test]
+    fn reject_resets_required_roles() {
+        let (alice, carol) = (Actor::new("Alice"), Actor::new("Carol"));
+        let lawyer = Actor::new("Bob").with_role("legal");
+        let policy = ApprovalPolicy::approvals(2).require_role("legal");
+
+        let approval = approve_reject_approve(Post::with_policy(policy.clone()), &[&lawyer], &[&alice, &carol]);
+        assert!(matches!(approval, Approval::Pending(_)));
+
+        let approval = approve_reject_approve(Post::with_policy(policy), &[&lawyer], &[&alice, &lawyer]);
+        assert_eq!("Test", approval.published().unwrap().content());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (author, alice) = (Actor::new("Ann"), Actor::new("Alice"));
+        let mut post = Post::with_policy(ApprovalPolicy::approvals(2).require_role("legal"));
+        post.add_text("Test");
+        let post: AnyPost = post.request_review(&author, None).approve(&alice, Some("Good")).unwrap().into();
+
+        let saved = serde_json::to_string(&post).unwrap();
+        assert!(saved.starts_with(
+            r#"{"state":"pending_review","content":"Test","policy":{"approvals":2,"required_roles":["legal"]},"approvals":[{"name":"Alice"}],"log":["#
+        ));
+
+        let post = match serde_json::from_str(&saved).unwrap() {
+            AnyPost::PendingReview(post) => post,
+            _ => panic!("expected a post in review"),
+        };
+        assert_eq!(Some("Good"), post.log()[1].comment.as_deref());
+        let post = post.approve(&alice, None).unwrap_err().post;
+        let post = post.approve(&Actor::new("Bob").with_role("legal"), None).unwrap().published().unwrap();
+        assert_eq!("Test", post.content());
+        assert_eq!(3, post.log().len());
+
+        let at = SystemTime::now() + Duration::from_secs(3600);
+        let post: AnyPost = approved(Some(at)).into();
+        let post = match serde_json::from_str(&serde_json::to_string(&post).unwrap()).unwrap() {
+            AnyPost::Scheduled(post) => post,
+            _ => panic!("expected a scheduled post"),
+        };
+        assert_eq!(at, post.publish_at());
+        let scheduled = r#"{"state":"scheduled","content":"Test"}"#;
+        let error = serde_json::from_str::<AnyPost>(scheduled).unwrap_err();
+        assert!(error.to_string().contains("a scheduled post has no publication time"));
+
+        let post: AnyPost = serde_json::from_str(r#"{"state":"draft","content":"Test"}"#).unwrap();
+        assert_eq!(Status::Draft, post.status());
+        let approved = r#"{"state":"pending_review","content":"","approvals":[{"name":"Alice"},{"name":"Bob"}]}"#;
+        assert!(serde_json::from_str::<AnyPost>(approved).is_err());
+        let twice = r#"{"state":"pending_review","content":"","policy":{"approvals":3},"approvals":[{"name":"Alice"},{"name":"Alice"}]}"#;
+        assert_eq!("Alice approved the post twice", serde_json::from_str::<AnyPost>(twice).unwrap_err().to_string());
+    }
+}
@@ -1,22 +1,23 @@
-use blog_types::Post;
+use blog_types::{Actor, Post};
 
 fn main() {
+    let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
     let mut post = Post::new();
 
     post.add_text("I ate a salad for lunch today");
 
-    let post = post.request_review();
+    let post = post.request_review(&author, None);
 
-    let post = post.approve();
+    let post = post.approve(&alice, None).unwrap().pending().unwrap();
 
-    let mut post = post.reject();
+    let mut post = post.reject(&bob, Some("Needs work"));
     post.add_text("Test");
 
-    let post = post.request_review();
+    let post = post.request_review(&author, None);
 
-    let post = post.approve();
+    let post = post.approve(&alice, None).unwrap().pending().unwrap();
 
-    let post = post.approve();
+    let post = post.approve(&bob, None).unwrap().published().unwrap();
     
     assert_eq!("I ate a salad for lunch todayTest", post.content());
 }
@@ -0,0 +1,94 @@
+// Posts saved on disk, in the repository of `blog_common`. A post is loaded
+// as the type of the state it was saved in.
+
+pub use blog_common::repository::RepositoryError;
+use blog_common::repository::Stored;
+
+use crate::{AnyPost, Status};
+
+pub type Repository = blog_common::repository::Repository<AnyPost>;
+
+impl Stored for AnyPost {
+    type State = Status;
+    type Context = ();
+
+    fn default_context() {}
+
+    fn state(&self) -> Status {
+        self.status()
+    }
+
+    fn from_json(json: &[u8], _: &()) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Actor, Post};
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    // An empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("blog-types-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn save_load_and_list() {
+        let dir = temp_dir("list");
+        let repository = Repository::open(&dir).unwrap();
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+
+        let mut draft = Post::new();
+        draft.add_text("Draft");
+        repository.save("draft", &draft.into()).unwrap();
+
+        let mut review = Post::new();
+        review.add_text("Review");
+        repository.save("review", &review.request_review(&author, None).approve(&alice, None).unwrap().into()).unwrap();
+
+        let mut published = Post::new();
+        published.add_text("Published");
+        let published = published.request_review(&author, None).approve(&alice, None).unwrap().pending().unwrap();
+        repository.save("published", &published.approve(&bob, None).unwrap().into()).unwrap();
+
+        let repository = Repository::open(&dir).unwrap();
+        assert_eq!(vec!["draft", "published", "review"], repository.ids().unwrap());
+        assert_eq!(vec!["draft"], repository.list(Status::Draft).unwrap());
+        assert_eq!(vec!["review"], repository.list(Status::PendingReview).unwrap());
+        assert_eq!(vec!["published"], repository.list(Status::Published).unwrap());
+
+        // The approval made before saving still counts
+        let review = match repository.load("review").unwrap() {
+            AnyPost::PendingReview(post) => post.approve(&bob, None).unwrap().published().unwrap(),
+            _ => panic!("expected a post in review"),
+        };
+        assert_eq!("Review", review.content());
+        repository.save("review", &review.into()).unwrap();
+        assert_eq!(vec!["published", "review"], repository.list(Status::Published).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_ids_and_files() {
+        let dir = temp_dir("errors");
+        let repository = Repository::open(&dir).unwrap();
+
+        assert!(matches!(repository.save("../x", &Post::new().into()), Err(RepositoryError::InvalidId(_))));
+        assert!(matches!(repository.load(""), Err(RepositoryError::InvalidId(_))));
+        assert!(matches!(repository.load("missing"), Err(RepositoryError::NotFound(_))));
+
+        fs::write(dir.join("broken.json"), "{").unwrap();
+        assert!(matches!(repository.load("broken"), Err(RepositoryError::Format { .. })));
+        assert!(repository.list(Status::Draft).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,178 +1,635 @@
+pub mod repository;
+pub mod workflow;
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+pub use blog_common::policy::{Actor, ApprovalPolicy};
+pub use workflow::{Workflow, WorkflowError};
+
+use blog_common::policy::repeated_approval;
+use workflow::{Context, Effect, Guard, StateDef};
+
+/// A transition of a post, as kept in its log: the event, and the state it
+/// took the post to.
+pub type Entry = blog_common::audit::Entry<Status, String>;
+
+/// The state a post is in, as saved and listed: the name of a state of its
+/// workflow.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Status(Cow<'static, str>);
+
+impl Status {
+    // The states of the standard workflow
+    pub const DRAFT: Status = Status(Cow::Borrowed("draft"));
+    pub const PENDING_REVIEW: Status = Status(Cow::Borrowed("pending_review"));
+    /// Approved, and published once its publication time comes.
+    pub const SCHEDULED: Status = Status(Cow::Borrowed("scheduled"));
+    pub const PUBLISHED: Status = Status(Cow::Borrowed("published"));
+    pub const ARCHIVED: Status = Status(Cow::Borrowed("archived"));
+
+    pub fn new(name: impl Into<String>) -> Self {
+        Status(Cow::Owned(name.into()))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A post, moved from state to state by its workflow.
 pub struct Post {
-    state: Option<Box<dyn State>>,
+    state: Status,
+    workflow: Arc<Workflow>,
     content: String,
+    policy: ApprovalPolicy,
+    publish_at: Option<SystemTime>,
+    approvals: Vec<Actor>,
+    log: Vec<Entry>,
+}
+
+/// An event or edit refused: the post stays as it was.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TransitionError {
+    /// The state of the post.
+    pub state: Status,
+    /// The event, or one of the `workflow::EDITS`.
+    pub event: String,
+    pub reason: Reason,
+    // The state and event as the message words them, from the workflow
+    label: String,
+    verb: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The event does not apply to a post in the state, or the workflow
+    /// has no such event.
+    NotAllowed,
+    /// The reviewer already approved the post since it was sent for review.
+    AlreadyApproved(String),
+    /// The publication time of the post has not come yet.
+    NotDue,
+    /// No transition of the workflow applies, as this guard does not hold.
+    Unmet(Guard),
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let article = if self.label.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+        write!(f, "cannot {} {article} {}", self.verb, self.label)?;
+        match &self.reason {
+            Reason::NotAllowed => Ok(()),
+            Reason::AlreadyApproved(reviewer) => write!(f, ": {reviewer} already approved it"),
+            Reason::NotDue => f.write_str(" before its publication time"),
+            Reason::Unmet(guard) => write!(f, " unless {guard}"),
+        }
+    }
+}
+
+impl Error for TransitionError {}
+
+/// A draft under the default policy.
+impl Default for Post {
+    fn default() -> Self {
+        Post::new()
+    }
 }
 
 impl Post {
     pub fn new() -> Self {
+        Post::with_policy(ApprovalPolicy::default())
+    }
+
+    pub fn with_policy(policy: ApprovalPolicy) -> Self {
+        Post::with_workflow(Workflow::standard(), policy)
+    }
+
+    pub fn with_workflow(workflow: Arc<Workflow>, policy: ApprovalPolicy) -> Self {
         Post {
-            state: Some(Box::new(Draft {})),
+            state: Status::new(workflow.initial()),
+            workflow,
             content: String::new(),
+            policy,
+            publish_at: None,
+            approvals: Vec::new(),
+            log: Vec::new(),
         }
     }
 
-    pub fn add_text(&mut self, text: &str) {
-        if self.state.as_ref().unwrap().is_editable() {
-            self.content.push_str(text);
-        }
+    pub fn add_text(&mut self, text: &str) -> Result<(), TransitionError> {
+        self.check_editable("add_text", "add text to")?;
+        self.content.push_str(text);
+        Ok(())
     }
 
-    pub fn content(&self) -> &str {
-        self.state.as_ref().unwrap().content(self)
+    /// Sets when the post is published once approved; without a time, or
+    /// with one already past, approval publishes it at once.
+    pub fn schedule(&mut self, at: Option<SystemTime>) -> Result<(), TransitionError> {
+        self.check_editable("schedule", "schedule")?;
+        self.publish_at = at;
+        Ok(())
     }
 
-    pub fn request_review(&mut self) {
-        if let Some(s) = self.state.take() {
-            self.state = Some(s.request_review())
+    pub fn content(&self) -> &str {
+        if self.current().shows_content {
+            &self.content
+        } else {
+            ""
         }
     }
-    pub fn reject(&mut self) {
-        if let Some(s) = self.state.take() {
-            self.state = Some(s.reject())
-        }
+
+    pub fn status(&self) -> Status {
+        self.state.clone()
     }
 
-    pub fn approve(&mut self) {
-        if let Some(s) = self.state.take() {
-            self.state = Some(s.approve())
-        }
+    pub fn workflow(&self) -> &Workflow {
+        &self.workflow
     }
-}
 
-trait State {
-    fn request_review(self: Box<Self>) -> Box<dyn State>;
-    fn approve(self: Box<Self>) -> Box<dyn State>;
-    fn reject(self: Box<Self>) -> Box<dyn State>;
-    fn is_editable(&self) -> bool {
-        false
+    pub fn policy(&self) -> &ApprovalPolicy {
+        &self.policy
     }
-    fn content<'a>(&self, _post: &'a Post) -> &'a str {
-        ""
+
+    pub fn publish_at(&self) -> Option<SystemTime> {
+        self.publish_at
     }
-    
-}
 
-struct Draft {}
+    /// The approvals given since the post was last sent for review.
+    pub fn approvals(&self) -> &[Actor] {
+        &self.approvals
+    }
 
+    /// The transitions of the post, oldest first.
+    pub fn log(&self) -> &[Entry] {
+        &self.log
+    }
 
-impl State for Draft {
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        Box::new(PendingReview {approve_counter: 0})
+    // The events of the standard workflow; other workflows fire theirs
+    // with `fire`
+
+    pub fn request_review(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.fire("request_review", actor, comment)
     }
-    fn approve(self: Box<Self>) -> Box<dyn State> {
-        self
+    pub fn reject(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.fire("reject", actor, comment)
     }
-    fn reject(self: Box<Self>) -> Box<dyn State> {
-        self
+
+    pub fn approve(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.fire("approve", actor, comment)
     }
-    fn is_editable(&self) -> bool {
-        true
+
+    /// Publishes a scheduled post whose publication time is `now` or earlier.
+    pub fn publish(&mut self, actor: &Actor, now: SystemTime) -> Result<(), TransitionError> {
+        self.fire_at("publish", actor, None, now)
     }
-}
 
-struct PendingReview {
-    approve_counter: u8
-}
+    /// Takes a published or scheduled post back to draft.
+    pub fn unpublish(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.fire("unpublish", actor, comment)
+    }
 
-impl State for PendingReview {
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        self
+    /// Puts a draft or a published post away for good.
+    pub fn archive(&mut self, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.fire("archive", actor, comment)
     }
-    fn approve(mut self: Box<Self>) -> Box<dyn State> {
-        self.approve_counter += 1;
-        match self.approve_counter {
-            2 => Box::new(Published {}),
-            _ => self
-        }
-     
+
+    /// Takes the transition of the workflow for `event`, and logs it.
+    pub fn fire(&mut self, event: &str, actor: &Actor, comment: Option<&str>) -> Result<(), TransitionError> {
+        self.fire_at(event, actor, comment, SystemTime::now())
     }
-    fn reject(self: Box<Self>) -> Box<dyn State> {
-        Box::new(Draft {})
+
+    // The state is always one of the workflow
+    fn current(&self) -> &StateDef {
+        self.workflow.state(self.state.name()).unwrap()
     }
-}
 
-struct Published {}
+    fn check_editable(&self, edit: &str, verb: &str) -> Result<(), TransitionError> {
+        if self.current().editable {
+            Ok(())
+        } else {
+            Err(self.refuse(edit, verb, Reason::NotAllowed))
+        }
+    }
 
-impl State for Published {
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        self
+    fn refuse(&self, event: &str, verb: &str, reason: Reason) -> TransitionError {
+        TransitionError {
+            state: self.state.clone(),
+            event: event.to_string(),
+            reason,
+            label: self.current().label(),
+            verb: verb.to_string(),
+        }
     }
-    fn approve(self: Box<Self>) -> Box<dyn State> {
-        self
+
+    fn fire_at(&mut self, event: &str, actor: &Actor, comment: Option<&str>, now: SystemTime) -> Result<(), TransitionError> {
+        let workflow = Arc::clone(&self.workflow);
+        let context = Context {
+            actor,
+            approvals: &self.approvals,
+            policy: &self.policy,
+            publish_at: self.publish_at,
+            now,
+        };
+        let transition = workflow.next(self.state.name(), event, &context).map_err(|reason| {
+            let verb = workflow.event(event).map_or(event, |e| e.verb());
+            self.refuse(event, verb, reason)
+        })?;
+        for effect in &transition.actions {
+            match effect {
+                Effect::RecordApproval => self.approvals.push(actor.clone()),
+                Effect::ClearApprovals => self.approvals.clear(),
+            }
+        }
+        self.state = Status::new(transition.to.as_str());
+        self.log.push(Entry::new(event.to_string(), actor, comment, self.state.clone()));
+        Ok(())
     }
-    fn reject(self: Box<Self>) -> Box<dyn State> {
-        self
+
+    /// Reads back a saved post into `workflow`, which must be one that could
+    /// have left the post as it was saved.
+    pub fn load(record: Record, workflow: Arc<Workflow>) -> Result<Post, String> {
+        if let Some(reviewer) = repeated_approval(&record.approvals) {
+            return Err(format!("{reviewer} approved the post twice"));
+        }
+        workflow.check(record.state.name(), &record.approvals, &record.policy, record.publish_at)?;
+        Ok(Post {
+            state: record.state,
+            workflow,
+            content: record.content,
+            policy: record.policy,
+            publish_at: record.publish_at,
+            approvals: record.approvals,
+            log: record.log,
+        })
     }
-    fn content<'a>(&self, post: &'a Post) -> &'a str {
-        &post.content
+}
+
+/// The saved form of a post, read back with `Post::load`.
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    state: Status,
+    content: String,
+    #[serde(default)]
+    policy: ApprovalPolicy,
+    #[serde(default)]
+    approvals: Vec<Actor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    publish_at: Option<SystemTime>,
+    #[serde(default)]
+    log: Vec<Entry>,
+}
+
+impl Serialize for Post {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let record = Record {
+            state: self.status(),
+            content: self.content.clone(),
+            policy: self.policy.clone(),
+            approvals: self.approvals.clone(),
+            publish_at: self.publish_at,
+            log: self.log.clone(),
+        };
+        record.serialize(serializer)
     }
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
+    use std::time::Duration;
+
+    fn not_allowed(state: Status, event: &str) -> Result<(), (Status, String, Reason)> {
+        Err((state, event.to_string(), Reason::NotAllowed))
+    }
+
+    // The state, event and reason of a refusal
+    fn refusal(result: Result<(), TransitionError>) -> Result<(), (Status, String, Reason)> {
+        result.map_err(|e| (e.state, e.event, e.reason))
+    }
 
     #[test]
     fn add_text() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
         let mut post = Post::new();
-        
-        post.add_text("Test");
+
+        post.add_text("Test").unwrap();
 
         assert_eq!("", post.content());
 
-        post.request_review();
+        post.request_review(&author, None).unwrap();
 
-        post.approve();
+        post.approve(&alice, None).unwrap();
         assert_eq!("", post.content());
 
-        post.approve();
-        
+        post.approve(&bob, None).unwrap();
+
         assert_eq!("Test", post.content());
     }
 
     #[test]
     fn reject_pending_review() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
         let mut post = Post::new();
 
-        post.add_text("Test");
+        post.add_text("Test").unwrap();
 
-        post.request_review();
+        post.request_review(&author, None).unwrap();
 
-        post.approve();
+        post.approve(&alice, None).unwrap();
 
         assert_eq!("", post.content());
 
-        post.reject();
+        post.reject(&bob, None).unwrap();
 
-        post.request_review();
+        post.request_review(&author, None).unwrap();
 
-        post.approve();
+        post.approve(&alice, None).unwrap();
 
-        post.approve();
+        post.approve(&bob, None).unwrap();
         assert_eq!("Test", post.content());
     }
 
     #[test]
     fn editable() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
         let mut post = Post::new();
 
-        post.add_text("Test");
-        post.add_text("Test");
+        post.add_text("Test").unwrap();
+        post.add_text("Test").unwrap();
 
-        post.request_review();
+        post.request_review(&author, None).unwrap();
 
-        post.add_text("Test");
+        assert_eq!(not_allowed(Status::PENDING_REVIEW, "add_text"), refusal(post.add_text("Test")));
 
-        post.approve();
+        post.approve(&alice, None).unwrap();
 
-        post.add_text("Test");
+        assert_eq!(not_allowed(Status::PENDING_REVIEW, "add_text"), refusal(post.add_text("Test")));
 
-        post.approve();
+        post.approve(&bob, None).unwrap();
 
-        post.add_text("Test");
+        assert_eq!(not_allowed(Status::PUBLISHED, "add_text"), refusal(post.add_text("Test")));
 
         assert_eq!("TestTest", post.content());
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn invalid_transitions() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let mut post = Post::new();
+
+        assert_eq!(not_allowed(Status::DRAFT, "approve"), refusal(post.approve(&alice, None)));
+        assert_eq!(not_allowed(Status::DRAFT, "reject"), refusal(post.reject(&alice, None)));
+        post.request_review(&author, None).unwrap();
+        assert_eq!(not_allowed(Status::PENDING_REVIEW, "request_review"), refusal(post.request_review(&author, None)));
+        post.approve(&alice, None).unwrap();
+        post.approve(&bob, None).unwrap();
+        for (action, result) in [
+            ("request_review", post.request_review(&author, None)),
+            ("approve", post.approve(&author, None)),
+            ("reject", post.reject(&author, None)),
+        ] {
+            assert_eq!(not_allowed(Status::PUBLISHED, action), refusal(result));
+        }
+        assert_eq!(3, post.log().len());
+
+        let error = post.approve(&author, None).unwrap_err();
+        assert_eq!("cannot approve a published post", error.to_string());
+    }
+
+    // A post sent for review with `publish_at`, by Ann, and approved by
+    // Alice and Bob
+    fn approved(publish_at: Option<SystemTime>) -> Post {
+        let mut post = Post::new();
+        post.add_text("Test").unwrap();
+        post.schedule(publish_at).unwrap();
+        post.request_review(&Actor::new("Ann"), None).unwrap();
+        post.approve(&Actor::new("Alice"), None).unwrap();
+        post.approve(&Actor::new("Bob"), None).unwrap();
+        post
+    }
+
+    #[test]
+    fn scheduled() {
+        let editor = Actor::new("Ed");
+        let now = SystemTime::now();
+        let at = now + Duration::from_secs(3600);
+        let mut post = approved(Some(at));
+
+        assert_eq!(Status::SCHEDULED, post.status());
+        assert_eq!("", post.content());
+        let error = post.publish(&editor, now).unwrap_err();
+        assert_eq!(Reason::NotDue, error.reason);
+        assert_eq!("cannot publish a scheduled post before its publication time", error.to_string());
+        assert_eq!(not_allowed(Status::SCHEDULED, "schedule"), refusal(post.schedule(None)));
+
+        post.publish(&editor, at).unwrap();
+        assert_eq!(Status::PUBLISHED, post.status());
+        assert_eq!("Test", post.content());
+        assert_eq!(Some(("publish", Status::PUBLISHED)), post.log().last().map(|e| (e.action.as_str(), e.state.clone())));
+
+        // A publication time already past publishes on approval
+        let post = approved(Some(now - Duration::from_secs(1)));
+        assert_eq!(Status::PUBLISHED, post.status());
+        assert_eq!(not_allowed(Status::DRAFT, "publish"), refusal(Post::new().publish(&editor, now)));
+    }
+
+    #[test]
+    fn unpublish() {
+        let editor = Actor::new("Ed");
+        let mut post = approved(None);
+
+        post.unpublish(&editor, Some("Wrong date")).unwrap();
+        assert_eq!(Status::DRAFT, post.status());
+        assert_eq!("", post.content());
+        post.add_text("!").unwrap();
+        assert_eq!(not_allowed(Status::DRAFT, "unpublish"), refusal(post.unpublish(&editor, None)));
+
+        let mut post = approved(Some(SystemTime::now() + Duration::from_secs(3600)));
+        post.unpublish(&editor, None).unwrap();
+        assert_eq!(Status::DRAFT, post.status());
+
+        post.request_review(&editor, None).unwrap();
+        assert_eq!(not_allowed(Status::PENDING_REVIEW, "unpublish"), refusal(post.unpublish(&editor, None)));
+    }
+
+    #[test]
+    fn archive() {
+        let editor = Actor::new("Ed");
+        let mut post = approved(None);
+
+        post.archive(&editor, None).unwrap();
+        assert_eq!(Status::ARCHIVED, post.status());
+        assert_eq!("", post.content());
+        assert_eq!(not_allowed(Status::ARCHIVED, "add_text"), refusal(post.add_text("Test")));
+        assert_eq!(not_allowed(Status::ARCHIVED, "request_review"), refusal(post.request_review(&editor, None)));
+        assert_eq!(not_allowed(Status::ARCHIVED, "unpublish"), refusal(post.unpublish(&editor, None)));
+        assert_eq!(not_allowed(Status::ARCHIVED, "archive"), refusal(post.archive(&editor, None)));
+        assert_eq!("cannot archive an archived post", post.archive(&editor, None).unwrap_err().to_string());
+
+        let mut post = Post::new();
+        post.archive(&editor, Some("Abandoned")).unwrap();
+        assert_eq!(Status::ARCHIVED, post.status());
+
+        let mut post = Post::new();
+        post.request_review(&editor, None).unwrap();
+        assert_eq!(not_allowed(Status::PENDING_REVIEW, "archive"), refusal(post.archive(&editor, None)));
+    }
+
+    #[test]
+    fn audit_log() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let mut post = Post::new();
+
+        post.request_review(&author, None).unwrap();
+        post.reject(&alice, Some("Too short")).unwrap();
+        assert!(post.approve(&alice, None).is_err());
+        post.request_review(&author, Some("Longer now")).unwrap();
+        post.approve(&alice, None).unwrap();
+        post.approve(&bob, Some("Fine")).unwrap();
+        assert!(post.reject(&bob, None).is_err());
+
+        let log: Vec<(&str, &str, Option<&str>, Status)> = post
+            .log()
+            .iter()
+            .map(|e| (e.action.as_str(), e.actor.as_str(), e.comment.as_deref(), e.state.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("request_review", "Ann", None, Status::PENDING_REVIEW),
+                ("reject", "Alice", Some("Too short"), Status::DRAFT),
+                ("request_review", "Ann", Some("Longer now"), Status::PENDING_REVIEW),
+                ("approve", "Alice", None, Status::PENDING_REVIEW),
+                ("approve", "Bob", Some("Fine"), Status::PUBLISHED),
+            ],
+            log
+        );
+        assert!(post.log().windows(2).all(|w| w[0].at <= w[1].at));
+    }
+
+    #[test]
+    fn duplicate_approval() {
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+        let mut post = Post::new();
+        post.add_text("Test").unwrap();
+        post.request_review(&author, None).unwrap();
+
+        post.approve(&alice, None).unwrap();
+        let error = post.approve(&alice, None).unwrap_err();
+        assert_eq!(Reason::AlreadyApproved("Alice".to_string()), error.reason);
+        assert_eq!("cannot approve a post in review: Alice already approved it", error.to_string());
+        assert_eq!(Status::PENDING_REVIEW, post.status());
+        assert_eq!(2, post.log().len());
+
+        post.approve(&bob, None).unwrap();
+        assert_eq!("Test", post.content());
+    }
+
+    // Sends `post` for review, approves it by `before`, rejects it, sends it
+    // again and approves it by `after`
+    fn approve_reject_approve(mut post: Post, before: &[&Actor], after: &[&Actor]) -> Post {
+        let author = Actor::new("Ann");
+        post.add_text("Test").unwrap();
+        post.request_review(&author, None).unwrap();
+        for reviewer in before {
+            post.approve(reviewer, None).unwrap();
+        }
+        post.reject(&author, None).unwrap();
+        post.request_review(&author, None).unwrap();
+        assert!(post.approvals().is_empty());
+        for reviewer in after {
+            post.approve(reviewer, None).unwrap();
+        }
+        post
+    }
+
+    #[test]
+    fn reject_resets_approvals() {
+        let (alice, bob, carol) = (Actor::new("Alice"), Actor::new("Bob"), Actor::new("Carol"));
+        let policy = ApprovalPolicy::approvals(3);
+
+        let post = approve_reject_approve(Post::with_policy(policy.clone()), &[&alice, &bob], &[&carol]);
+        assert_eq!(Status::PENDING_REVIEW, post.status());
+
+        let post = approve_reject_approve(Post::with_policy(policy), &[&alice, &bob], &[&alice, &bob, &carol]);
+        assert_eq!(Status::PUBLISHED, post.status());
+    }
+
+    #[test]
+    fn reject_resets_reviewers() {
+        let (alice, bob) = (Actor::new("Alice"), Actor::new("Bob"));
+        let policy = ApprovalPolicy::approvals(2);
+
+        // Who approved before the rejection may approve again
+        let post = approve_reject_approve(Post::with_policy(policy), &[&alice], &[&alice, &bob]);
+        assert_eq!(Status::PUBLISHED, post.status());
+    }
+
+    #[test]
+    fn reject_resets_required_roles() {
+        let (alice, carol) = (Actor::new("Alice"), Actor::new("Carol"));
+        let lawyer = Actor::new("Bob").with_role("legal");
+        let policy = ApprovalPolicy::approvals(2).require_role("legal");
+
+        let post = approve_reject_approve(Post::with_policy(policy.clone()), &[&lawyer], &[&alice, &carol]);
+        assert_eq!(Status::PENDING_REVIEW, post.status());
+
+        let post = approve_reject_approve(Post::with_policy(policy), &[&lawyer], &[&alice, &lawyer]);
+        assert_eq!(Status::PUBLISHED, post.status());
+        assert_eq!("Test", post.content());
+    }
+
+    // Reads back `json` into the standard workflow
+    fn load(json: &str) -> Result<Post, String> {
+        Post::load(serde_json::from_str(json).map_err(|e| e.to_string())?, Workflow::standard())
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (author, alice) = (Actor::new("Ann"), Actor::new("Alice"));
+        let mut post = Post::with_policy(ApprovalPolicy::approvals(2).require_role("legal"));
+        post.add_text("Test").unwrap();
+        post.request_review(&author, None).unwrap();
+        post.approve(&alice, Some("Good")).unwrap();
+
+        let saved = serde_json::to_string(&post).unwrap();
+        assert!(saved.starts_with(
+            r#"{"state":"pending_review","content":"Test","policy":{"approvals":2,"required_roles":["legal"]},"approvals":[{"name":"Alice"}],"log":["#
+        ));
+
+        let mut post = load(&saved).unwrap();
+        assert_eq!(Status::PENDING_REVIEW, post.status());
+        assert_eq!(2, post.log().len());
+        assert_eq!(Some("Good"), post.log()[1].comment.as_deref());
+        assert!(post.approve(&alice, None).is_err());
+        post.approve(&Actor::new("Bob").with_role("legal"), None).unwrap();
+        assert_eq!("Test", post.content());
+
+        let post = load(r#"{"state":"draft","content":"Test"}"#).unwrap();
+        assert_eq!(Status::DRAFT, post.status());
+        assert_eq!(&ApprovalPolicy::default(), post.policy());
+        let met = r#"{"state":"pending_review","content":"","approvals":[{"name":"Alice"},{"name":"Bob"}]}"#;
+        assert_eq!(Err("no transition leaves a post in state pending_review with its approvals".to_string()), load(met).map(|_| ()));
+        let twice = r#"{"state":"pending_review","content":"","policy":{"approvals":3},"approvals":[{"name":"Alice"},{"name":"Alice"}]}"#;
+        assert_eq!("Alice approved the post twice", load(twice).err().unwrap());
+        let unscheduled = load(r#"{"state":"scheduled","content":""}"#).err().unwrap();
+        assert_eq!("the workflow takes a post to state scheduled only with a publication time", unscheduled);
+
+        let at = SystemTime::now() + Duration::from_secs(3600);
+        let post = load(&serde_json::to_string(&approved(Some(at))).unwrap()).unwrap();
+        assert_eq!((Status::SCHEDULED, Some(at)), (post.status(), post.publish_at()));
+    }
+
+}
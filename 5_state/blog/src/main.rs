@@ -1,29 +1,49 @@
-use blog::Post;
+use std::env;
+use std::process;
+use std::sync::Arc;
+
+use blog::{Actor, Post, Workflow};
 
 fn main() {
+    // `blog --dot [WORKFLOW]` prints the graph of the workflow in the file,
+    // or of the standard one
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "--dot") {
+        let workflow = match args.get(1) {
+            Some(path) => Arc::new(Workflow::load(path).unwrap_or_else(|e| {
+                eprintln!("{path}: {e}");
+                process::exit(1);
+            })),
+            None => Workflow::standard(),
+        };
+        print!("{}", workflow.to_dot());
+        return;
+    }
+
+    let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
     let mut post = Post::new();
 
-    post.add_text("Test, test");
+    post.add_text("Test, test").unwrap();
 
     assert_eq!("", post.content());
 
-    post.request_review();
+    post.request_review(&author, None).unwrap();
     assert_eq!("", post.content());
     
-    post.approve();
+    post.approve(&alice, None).unwrap();
     assert_eq!("", post.content());
 
-    post.reject();
+    post.reject(&bob, Some("Needs work")).unwrap();
 
-    post.request_review();
+    post.request_review(&author, None).unwrap();
     assert_eq!("", post.content());
 
-    post.approve();
+    post.approve(&alice, None).unwrap();
     assert_eq!("", post.content());
 
-    post.approve();
+    post.approve(&bob, None).unwrap();
     assert_eq!("Test, test", post.content());
 
-    post.add_text("Test, test");
+    assert!(post.add_text("Test, test").is_err());
     assert_eq!("Test, test", post.content());
 }
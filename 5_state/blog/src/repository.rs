@@ -0,0 +1,112 @@
+// Posts saved on disk, in the repository of `blog_common`. A post is read
+// back into the workflow of the repository, the standard one unless
+// `Repository::with_context` gives another.
+
+use std::sync::Arc;
+
+use serde::de::Error as _;
+
+pub use blog_common::repository::RepositoryError;
+use blog_common::repository::Stored;
+
+use crate::{Post, Record, Status, Workflow};
+
+pub type Repository = blog_common::repository::Repository<Post>;
+
+impl Stored for Post {
+    type State = Status;
+    type Context = Arc<Workflow>;
+
+    fn default_context() -> Arc<Workflow> {
+        Workflow::standard()
+    }
+
+    fn state(&self) -> Status {
+        self.status()
+    }
+
+    fn from_json(json: &[u8], workflow: &Arc<Workflow>) -> Result<Self, serde_json::Error> {
+        let record: Record = serde_json::from_slice(json)?;
+        Post::load(record, Arc::clone(workflow)).map_err(serde_json::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Actor;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    // An empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("blog-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn save_load_and_list() {
+        let dir = temp_dir("list");
+        let repository = Repository::open(&dir).unwrap();
+        let (author, alice, bob) = (Actor::new("Ann"), Actor::new("Alice"), Actor::new("Bob"));
+
+        let mut draft = Post::new();
+        draft.add_text("Draft").unwrap();
+        repository.save("draft", &draft).unwrap();
+
+        let mut review = Post::new();
+        review.add_text("Review").unwrap();
+        review.request_review(&author, None).unwrap();
+        review.approve(&alice, None).unwrap();
+        repository.save("review", &review).unwrap();
+
+        let mut published = Post::new();
+        published.add_text("Published").unwrap();
+        published.request_review(&author, None).unwrap();
+        published.approve(&alice, None).unwrap();
+        published.approve(&bob, None).unwrap();
+        repository.save("published", &published).unwrap();
+
+        let repository = Repository::open(&dir).unwrap();
+        assert_eq!(vec!["draft", "published", "review"], repository.ids().unwrap());
+        assert_eq!(vec!["draft"], repository.list(Status::DRAFT).unwrap());
+        assert_eq!(vec!["review"], repository.list(Status::PENDING_REVIEW).unwrap());
+        assert_eq!(vec!["published"], repository.list(Status::PUBLISHED).unwrap());
+
+        // The approval made before saving still counts
+        let mut review = repository.load("review").unwrap();
+        review.approve(&bob, None).unwrap();
+        assert_eq!("Review", review.content());
+        repository.save("review", &review).unwrap();
+        assert_eq!(vec!["published", "review"], repository.list(Status::PUBLISHED).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_ids_and_files() {
+        let dir = temp_dir("errors");
+        let repository = Repository::open(&dir).unwrap();
+
+        assert!(matches!(repository.save("../x", &Post::new()), Err(RepositoryError::InvalidId(_))));
+        assert!(matches!(repository.load(""), Err(RepositoryError::InvalidId(_))));
+        assert!(matches!(repository.load("missing"), Err(RepositoryError::NotFound(_))));
+
+        fs::write(dir.join("broken.json"), "{").unwrap();
+        assert!(matches!(repository.load("broken"), Err(RepositoryError::Format { .. })));
+        assert!(repository.list(Status::DRAFT).is_err());
+
+        // A state the workflow of the repository does not have
+        fs::remove_file(dir.join("broken.json")).unwrap();
+        fs::write(dir.join("scheduled.json"), r#"{"state":"scheduled","content":"","publish_at":{"secs_since_epoch":0,"nanos_since_epoch":0}}"#).unwrap();
+        assert!(repository.load("scheduled").is_ok());
+        let direct = "initial = \"draft\"\n[[states]]\nname = \"draft\"\nfinal = true\n";
+        let repository = repository.with_context(Arc::new(Workflow::from_toml(direct).unwrap()));
+        assert!(matches!(repository.load("scheduled"), Err(RepositoryError::Format { .. })));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
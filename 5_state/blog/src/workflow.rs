@@ -0,0 +1,650 @@
+// The workflow of a post as data: its states, its events, and the
+// transitions the events make between the states, each with the guards that
+// must hold and the actions that go with it. States and events are names
+// declared in the workflow, so a workflow is not bound to the states of the
+// standard one; only guards and actions, which are code, are a fixed set. A
+// workflow is read from TOML, like the standard one in workflow.toml, and
+// checked when read: transitions must name declared states and events, every
+// state must be reachable from the initial state, and a final state must be
+// reachable from every state.
+
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Actor, ApprovalPolicy, Reason};
+
+/// The names of the edits of a post, which change no state and so cannot be
+/// events: states allow them with `editable`.
+pub const EDITS: [&str; 2] = ["add_text", "schedule"];
+
+/// A workflow, checked however it is read.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "RawWorkflow")]
+pub struct Workflow {
+    initial: String,
+    states: Vec<StateDef>,
+    events: Vec<EventDef>,
+    transitions: Vec<Transition>,
+}
+
+// A workflow as read, before it is checked
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawWorkflow {
+    initial: String,
+    states: Vec<StateDef>,
+    #[serde(default)]
+    events: Vec<EventDef>,
+    #[serde(default)]
+    transitions: Vec<Transition>,
+}
+
+impl TryFrom<RawWorkflow> for Workflow {
+    type Error = WorkflowError;
+
+    fn try_from(raw: RawWorkflow) -> Result<Self, Self::Error> {
+        let workflow = Workflow {
+            initial: raw.initial,
+            states: raw.states,
+            events: raw.events,
+            transitions: raw.transitions,
+        };
+        workflow.validate()?;
+        Ok(workflow)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateDef {
+    pub name: String,
+    /// A post in the state, as in "cannot approve a draft"; "post in state
+    /// <name>" if not given.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Whether text can be added to the post and its publication time set.
+    #[serde(default)]
+    pub editable: bool,
+    /// Whether the post shows its content.
+    #[serde(default)]
+    pub shows_content: bool,
+    /// Whether the state may lead nowhere.
+    #[serde(default, rename = "final")]
+    pub is_final: bool,
+}
+
+impl StateDef {
+    pub fn label(&self) -> String {
+        self.label.clone().unwrap_or_else(|| format!("post in state {}", self.name))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventDef {
+    pub name: String,
+    /// What the event does to a post, as in "cannot request a review of a
+    /// draft"; the name if not given.
+    #[serde(default)]
+    pub verb: Option<String>,
+}
+
+impl EventDef {
+    pub fn verb(&self) -> &str {
+        self.verb.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// A transition on an event, taken if its guards all hold. When several
+/// transitions leave a state on the same event, the first one wins.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    pub from: String,
+    pub event: String,
+    pub to: String,
+    #[serde(default)]
+    pub guards: Vec<Guard>,
+    #[serde(default)]
+    pub actions: Vec<Effect>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Guard {
+    /// The actor has not approved the post since it was sent for review.
+    NewReviewer,
+    /// The approvals, with one by the actor, meet the policy of the post.
+    PolicyMet,
+    PolicyNotMet,
+    /// The post has no publication time, or the time has come.
+    Due,
+    NotDue,
+}
+
+impl Guard {
+    /// The name of the guard in the workflow file.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Guard::NewReviewer => "new_reviewer",
+            Guard::PolicyMet => "policy_met",
+            Guard::PolicyNotMet => "policy_not_met",
+            Guard::Due => "due",
+            Guard::NotDue => "not_due",
+        }
+    }
+}
+
+/// What the guard asks for, as in "cannot publish a scheduled post unless
+/// its publication time has come".
+impl fmt::Display for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Guard::NewReviewer => "by a new reviewer",
+            Guard::PolicyMet => "its approvals meet its policy",
+            Guard::PolicyNotMet => "its approvals fall short of its policy",
+            Guard::Due => "its publication time has come",
+            Guard::NotDue => "its publication time is still to come",
+        })
+    }
+}
+
+/// What a transition does to the post besides changing its state: the
+/// `actions` of the transition in the workflow file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    /// Adds the approval of the actor.
+    RecordApproval,
+    ClearApprovals,
+}
+
+impl Effect {
+    /// The name of the action in the workflow file.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Effect::RecordApproval => "record_approval",
+            Effect::ClearApprovals => "clear_approvals",
+        }
+    }
+}
+
+/// What the guards of a transition look at.
+pub(crate) struct Context<'a> {
+    pub actor: &'a Actor,
+    pub approvals: &'a [Actor],
+    pub policy: &'a ApprovalPolicy,
+    pub publish_at: Option<SystemTime>,
+    pub now: SystemTime,
+}
+
+impl Guard {
+    fn holds(self, cx: &Context) -> bool {
+        match self {
+            Guard::NewReviewer => !cx.approvals.iter().any(|a| a.name == cx.actor.name),
+            Guard::PolicyMet | Guard::PolicyNotMet => {
+                let mut approvals = cx.approvals.to_vec();
+                approvals.push(cx.actor.clone());
+                cx.policy.is_met(&approvals) == (self == Guard::PolicyMet)
+            }
+            Guard::Due | Guard::NotDue => cx.publish_at.is_none_or(|at| at <= cx.now) == (self == Guard::Due),
+        }
+    }
+
+    // Why the event is refused when the guard does not hold
+    fn reason(self, cx: &Context) -> Reason {
+        match self {
+            Guard::NewReviewer => Reason::AlreadyApproved(cx.actor.name.clone()),
+            Guard::Due => Reason::NotDue,
+            guard => Reason::Unmet(guard),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WorkflowError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    /// A transition or the initial state names a state not in `states`.
+    Undeclared(String),
+    Duplicate(String),
+    /// A transition names an event not in `events`.
+    UndeclaredEvent(String),
+    DuplicateEvent(String),
+    /// An event is declared with the name of one of the `EDITS`.
+    NotAnEvent(String),
+    Unreachable(String),
+    /// A state from which no final state can be reached.
+    DeadEnd(String),
+}
+
+impl fmt::Display for WorkflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorkflowError::Io(e) => write!(f, "{e}"),
+            WorkflowError::Toml(e) => write!(f, "invalid workflow: {e}"),
+            WorkflowError::Undeclared(state) => write!(f, "state {state} is not declared"),
+            WorkflowError::Duplicate(state) => write!(f, "state {state} is declared twice"),
+            WorkflowError::UndeclaredEvent(event) => write!(f, "event {event} is not declared"),
+            WorkflowError::DuplicateEvent(event) => write!(f, "event {event} is declared twice"),
+            WorkflowError::NotAnEvent(name) => write!(f, "{name} is not an event"),
+            WorkflowError::Unreachable(state) => write!(f, "state {state} cannot be reached from the initial state"),
+            WorkflowError::DeadEnd(state) => write!(f, "state {state} leads to no final state"),
+        }
+    }
+}
+
+impl Error for WorkflowError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WorkflowError::Io(e) => Some(e),
+            WorkflowError::Toml(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WorkflowError {
+    fn from(e: io::Error) -> Self {
+        WorkflowError::Io(e)
+    }
+}
+
+impl Workflow {
+    /// The workflow of `Post::new`, in workflow.toml.
+    pub fn standard() -> Arc<Workflow> {
+        static STANDARD: OnceLock<Arc<Workflow>> = OnceLock::new();
+        let standard = STANDARD.get_or_init(|| Arc::new(Workflow::from_toml(include_str!("workflow.toml")).unwrap()));
+        Arc::clone(standard)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Workflow, WorkflowError> {
+        let raw: RawWorkflow = toml::from_str(toml).map_err(WorkflowError::Toml)?;
+        Workflow::try_from(raw)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Workflow, WorkflowError> {
+        Workflow::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn initial(&self) -> &str {
+        &self.initial
+    }
+
+    pub fn states(&self) -> &[StateDef] {
+        &self.states
+    }
+
+    pub fn events(&self) -> &[EventDef] {
+        &self.events
+    }
+
+    pub fn transitions(&self) -> &[Transition] {
+        &self.transitions
+    }
+
+    pub fn state(&self, name: &str) -> Option<&StateDef> {
+        self.states.iter().find(|s| s.name == name)
+    }
+
+    pub fn event(&self, name: &str) -> Option<&EventDef> {
+        self.events.iter().find(|e| e.name == name)
+    }
+
+    /// Checks that a transition of the workflow could have left a post in
+    /// `state` with `approvals` and `publish_at`, as a post read back must.
+    pub(crate) fn check(&self, state: &str, approvals: &[Actor], policy: &ApprovalPolicy, publish_at: Option<SystemTime>) -> Result<(), String> {
+        if self.state(state).is_none() {
+            return Err(format!("the workflow has no state {state}"));
+        }
+        let into: Vec<&Transition> = self.transitions.iter().filter(|t| t.to == state).collect();
+        let new = state == self.initial && approvals.is_empty();
+
+        // The approvals the transition leaves: none once cleared, the new
+        // one and those before once recorded, those before otherwise
+        let leaves = |t: &Transition| match t.actions.last() {
+            Some(Effect::ClearApprovals) => approvals.is_empty(),
+            Some(Effect::RecordApproval) => {
+                !approvals.is_empty()
+                    && t.guards.iter().all(|guard| match guard {
+                        Guard::PolicyMet => policy.is_met(approvals),
+                        Guard::PolicyNotMet => !policy.is_met(approvals),
+                        _ => true,
+                    })
+            }
+            None => true,
+        };
+        if !new && !into.iter().any(|t| leaves(t)) {
+            return Err(format!("no transition leaves a post in state {state} with its approvals"));
+        }
+        if publish_at.is_none() && state != self.initial && into.iter().all(|t| t.guards.contains(&Guard::NotDue)) {
+            return Err(format!("the workflow takes a post to state {state} only with a publication time"));
+        }
+        Ok(())
+    }
+
+    /// The transition `event` takes from `state`, or why there is none: the
+    /// first guard failing on the first transition for the event.
+    pub(crate) fn next(&self, state: &str, event: &str, cx: &Context) -> Result<&Transition, Reason> {
+        let mut reason = Reason::NotAllowed;
+        for (i, transition) in self.transitions.iter().filter(|t| t.from == state && t.event == event).enumerate() {
+            match transition.guards.iter().find(|guard| !guard.holds(cx)) {
+                None => return Ok(transition),
+                Some(guard) if i == 0 => reason = guard.reason(cx),
+                Some(_) => {}
+            }
+        }
+        Err(reason)
+    }
+
+    fn validate(&self) -> Result<(), WorkflowError> {
+        for (i, state) in self.states.iter().enumerate() {
+            if self.states[..i].iter().any(|s| s.name == state.name) {
+                return Err(WorkflowError::Duplicate(state.name.clone()));
+            }
+        }
+        for (i, event) in self.events.iter().enumerate() {
+            if EDITS.contains(&event.name.as_str()) {
+                return Err(WorkflowError::NotAnEvent(event.name.clone()));
+            }
+            if self.events[..i].iter().any(|e| e.name == event.name) {
+                return Err(WorkflowError::DuplicateEvent(event.name.clone()));
+            }
+        }
+        if self.state(&self.initial).is_none() {
+            return Err(WorkflowError::Undeclared(self.initial.clone()));
+        }
+        for transition in &self.transitions {
+            if self.event(&transition.event).is_none() {
+                return Err(WorkflowError::UndeclaredEvent(transition.event.clone()));
+            }
+            if let Some(state) = [&transition.from, &transition.to].into_iter().find(|s| self.state(s).is_none()) {
+                return Err(WorkflowError::Undeclared(state.clone()));
+            }
+        }
+
+        let reached = self.closure(vec![&self.initial], |t| (&t.from, &t.to));
+        let finals = self.states.iter().filter(|s| s.is_final).map(|s| s.name.as_str()).collect();
+        let finishing = self.closure(finals, |t| (&t.to, &t.from));
+        for state in &self.states {
+            if !reached.contains(&state.name.as_str()) {
+                return Err(WorkflowError::Unreachable(state.name.clone()));
+            }
+            if !finishing.contains(&state.name.as_str()) {
+                return Err(WorkflowError::DeadEnd(state.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    // The states reached from `start`, breadth first, along the transitions
+    // as `edge` turns them into pairs of states
+    fn closure<'a>(&'a self, start: Vec<&'a str>, edge: impl Fn(&'a Transition) -> (&'a String, &'a String)) -> Vec<&'a str> {
+        let mut reached = start;
+        let mut i = 0;
+        while let Some(&state) = reached.get(i) {
+            for (from, to) in self.transitions.iter().map(&edge) {
+                if from == state && !reached.contains(&to.as_str()) {
+                    reached.push(to);
+                }
+            }
+            i += 1;
+        }
+        reached
+    }
+
+    /// The workflow as a Graphviz graph: final states are drawn twice
+    /// circled, and transitions are labelled `event [guards] / actions`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph workflow {\n    start [shape=point];\n");
+        for state in &self.states {
+            let shape = if state.is_final { "doublecircle" } else { "circle" };
+            writeln!(dot, "    {:?} [shape={shape}];", state.name).unwrap();
+        }
+        writeln!(dot, "    start -> {:?};", self.initial).unwrap();
+        for transition in &self.transitions {
+            let mut label = transition.event.clone();
+            if !transition.guards.is_empty() {
+                let guards: Vec<&str> = transition.guards.iter().map(|g| g.as_str()).collect();
+                write!(label, " [{}]", guards.join(", ")).unwrap();
+            }
+            if !transition.actions.is_empty() {
+                let actions: Vec<&str> = transition.actions.iter().map(|a| a.as_str()).collect();
+                write!(label, " / {}", actions.join(", ")).unwrap();
+            }
+            writeln!(dot, "    {:?} -> {:?} [label={label:?}];", transition.from, transition.to).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Post, Status};
+    use std::time::Duration;
+
+    // Publishing straight from draft, without review
+    const DIRECT: &str = r#"
+initial = "draft"
+
+[[states]]
+name = "draft"
+label = "draft"
+editable = true
+
+[[states]]
+name = "published"
+shows_content = true
+
+[[states]]
+name = "archived"
+final = true
+
+[[events]]
+name = "publish"
+
+[[events]]
+name = "archive"
+
+[[transitions]]
+from = "draft"
+event = "publish"
+to = "published"
+guards = ["due"]
+
+[[transitions]]
+from = "published"
+event = "archive"
+to = "archived"
+"#;
+
+    #[test]
+    fn drives_post() {
+        let editor = Actor::new("Ed");
+        let now = SystemTime::now();
+        let mut post = Post::with_workflow(Arc::new(Workflow::from_toml(DIRECT).unwrap()), ApprovalPolicy::default());
+        post.add_text("Test").unwrap();
+
+        let error = post.request_review(&editor, None).unwrap_err();
+        assert_eq!(Reason::NotAllowed, error.reason);
+        post.schedule(Some(now + Duration::from_secs(60))).unwrap();
+        assert_eq!(Reason::NotDue, post.publish(&editor, now).unwrap_err().reason);
+        post.schedule(None).unwrap();
+
+        post.publish(&editor, now).unwrap();
+        assert_eq!(Status::PUBLISHED, post.status());
+        assert_eq!("Test", post.content());
+        post.archive(&editor, None).unwrap();
+        assert_eq!(Status::ARCHIVED, post.status());
+        assert_eq!(2, post.log().len());
+    }
+
+    #[test]
+    fn own_states_and_events() {
+        let toml = r#"
+initial = "idea"
+
+[[states]]
+name = "idea"
+editable = true
+
+[[states]]
+name = "fact_check"
+label = "post being checked"
+
+[[states]]
+name = "live"
+final = true
+shows_content = true
+
+[[events]]
+name = "submit"
+
+[[events]]
+name = "verify"
+
+[[transitions]]
+from = "idea"
+event = "submit"
+to = "fact_check"
+
+[[transitions]]
+from = "fact_check"
+event = "verify"
+to = "live"
+"#;
+        let editor = Actor::new("Ed");
+        let mut post = Post::with_workflow(Arc::new(Workflow::from_toml(toml).unwrap()), ApprovalPolicy::default());
+        post.add_text("Test").unwrap();
+
+        assert_eq!(Status::new("idea"), post.status());
+        assert_eq!(Reason::NotAllowed, post.request_review(&editor, None).unwrap_err().reason);
+        post.fire("submit", &editor, None).unwrap();
+        let error = post.fire("submit", &editor, None).unwrap_err();
+        assert_eq!("cannot submit a post being checked", error.to_string());
+        post.fire("verify", &editor, Some("Sources fine")).unwrap();
+        assert_eq!(Status::new("live"), post.status());
+        assert_eq!("Test", post.content());
+        assert_eq!("cannot add text to a post in state live", post.add_text("!").unwrap_err().to_string());
+        assert_eq!(Some(("verify", "live")), post.log().last().map(|e| (e.action.as_str(), e.state.name())));
+
+        // Saved by the names of the workflow, and loaded back into it only
+        let saved = serde_json::to_string(&post).unwrap();
+        assert!(saved.starts_with(r#"{"state":"live","#), "{saved}");
+        let loaded = Post::load(serde_json::from_str(&saved).unwrap(), Arc::clone(&post.workflow)).unwrap();
+        assert_eq!(Status::new("live"), loaded.status());
+        let error = Post::load(serde_json::from_str(&saved).unwrap(), Workflow::standard()).err().unwrap();
+        assert_eq!("the workflow has no state live", error);
+    }
+
+    #[test]
+    fn unmet_guard() {
+        let toml = DIRECT.replace(r#"guards = ["due"]"#, r#"guards = ["policy_met"]"#);
+        let mut post = Post::with_workflow(Arc::new(Workflow::from_toml(&toml).unwrap()), ApprovalPolicy::approvals(2));
+
+        let error = post.publish(&Actor::new("Ed"), SystemTime::now()).unwrap_err();
+        assert_eq!(Reason::Unmet(Guard::PolicyMet), error.reason);
+        assert_eq!("cannot publish a draft unless its approvals meet its policy", error.to_string());
+    }
+
+    #[test]
+    fn invalid_workflows() {
+        let error = |toml: &str| Workflow::from_toml(toml).unwrap_err().to_string();
+
+        assert_eq!("state published cannot be reached from the initial state", error(&DIRECT.replace("event = \"publish\"\nto = \"published\"", "event = \"publish\"\nto = \"archived\"")));
+        assert_eq!("state published leads to no final state", error(&DIRECT.replace("from = \"published\"", "from = \"draft\"")));
+        assert_eq!("state scheduled is not declared", error(&DIRECT.replace("to = \"archived\"", "to = \"scheduled\"")));
+        assert_eq!("state pending_review is not declared", error(&DIRECT.replace("initial = \"draft\"", "initial = \"pending_review\"")));
+        assert_eq!("state draft is declared twice", error(&DIRECT.replace("[[states]]\nname = \"archived\"", "[[states]]\nname = \"draft\"\n\n[[states]]\nname = \"archived\"")));
+        assert_eq!("event unpublish is not declared", error(&DIRECT.replace("event = \"archive\"", "event = \"unpublish\"")));
+        assert_eq!("event publish is declared twice", error(&DIRECT.replace("name = \"archive\"", "name = \"publish\"")));
+        assert_eq!("add_text is not an event", error(&DIRECT.replace("name = \"archive\"", "name = \"add_text\"")));
+        assert!(error("initial = \"draft\"\nstates = []\n[[transitions]]\nfrom = \"draft\"").starts_with("invalid workflow"));
+        assert!(error(&DIRECT.replace("final = true", "last = true")).starts_with("invalid workflow"));
+
+        // Read straight from TOML, a workflow is checked all the same
+        let unreachable = DIRECT.replace("event = \"publish\"\nto = \"published\"", "event = \"publish\"\nto = \"archived\"");
+        let error = toml::from_str::<Workflow>(&unreachable).unwrap_err();
+        assert!(error.to_string().contains("state published cannot be reached from the initial state"), "{error}");
+    }
+
+    #[test]
+    fn cycle_without_end() {
+        // Review and edit lead to each other but never to archived
+        let toml = r#"
+initial = "draft"
+
+[[states]]
+name = "draft"
+
+[[states]]
+name = "review"
+
+[[states]]
+name = "edit"
+
+[[states]]
+name = "archived"
+final = true
+
+[[events]]
+name = "send"
+
+[[events]]
+name = "archive"
+
+[[transitions]]
+from = "draft"
+event = "send"
+to = "review"
+
+[[transitions]]
+from = "review"
+event = "send"
+to = "edit"
+
+[[transitions]]
+from = "edit"
+event = "send"
+to = "review"
+
+[[transitions]]
+from = "draft"
+event = "archive"
+to = "archived"
+"#;
+        assert_eq!("state review leads to no final state", Workflow::from_toml(toml).unwrap_err().to_string());
+        let ended = toml.replace("from = \"edit\"\nevent = \"send\"\nto = \"review\"", "from = \"edit\"\nevent = \"send\"\nto = \"archived\"");
+        assert!(Workflow::from_toml(&ended).is_ok());
+    }
+
+    #[test]
+    fn names() {
+        for guard in [Guard::NewReviewer, Guard::PolicyMet, Guard::PolicyNotMet, Guard::Due, Guard::NotDue] {
+            assert_eq!(serde_json::json!(guard.as_str()), serde_json::to_value(guard).unwrap());
+        }
+        for effect in [Effect::RecordApproval, Effect::ClearApprovals] {
+            assert_eq!(serde_json::json!(effect.as_str()), serde_json::to_value(effect).unwrap());
+        }
+    }
+
+    #[test]
+    fn dot() {
+        let dot = Workflow::standard().to_dot();
+
+        assert!(dot.starts_with("digraph workflow {\n    start [shape=point];\n    \"draft\" [shape=circle];\n"), "{dot}");
+        assert!(dot.contains("    \"archived\" [shape=doublecircle];\n    start -> \"draft\";\n"), "{dot}");
+        assert!(dot.contains("    \"draft\" -> \"pending_review\" [label=\"request_review / clear_approvals\"];\n"), "{dot}");
+        assert!(dot.contains("    \"pending_review\" -> \"scheduled\" [label=\"approve [new_reviewer, policy_met, not_due] / clear_approvals\"];\n"), "{dot}");
+        assert!(dot.ends_with("    \"published\" -> \"archived\" [label=\"archive\"];\n}\n"), "{dot}");
+    }
+}